@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+// https://download.geonames.org/export/dump/readme.txt
+const CITY_NAME: usize = 1;
+const CITY_LATITUDE: usize = 4;
+const CITY_LONGITUDE: usize = 5;
+const CITY_COUNTRY_CODE: usize = 8;
+const CITY_ADMIN1_CODE: usize = 10;
+const CITY_COLUMNS: usize = 11;
+
+// countryInfo.txt
+const COUNTRY_CODE: usize = 0;
+const COUNTRY_NAME: usize = 4;
+
+const EARTH_RADIUS_KM: f64 = 6371.0;
+const KM_PER_DEGREE: f64 = 111.0;
+
+/// where a photo was taken, as names from the dataset
+#[derive(Debug, Clone, PartialEq)]
+pub struct Place {
+    /// the ISO code if the dataset came without countryInfo.txt
+    pub country: String,
+    pub region: Option<String>,
+    pub city: String,
+}
+
+#[derive(Debug)]
+struct City {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    country_code: String,
+    admin1_code: String,
+}
+
+/// nearest city lookups against a GeoNames dump (cities1000.txt and friends), everything stays in memory
+#[derive(Debug, Default)]
+pub struct Geocoder {
+    cities: Vec<City>,
+    // indexes into cities, bucketed by whole degrees of latitude and longitude
+    grid: HashMap<(i32, i32), Vec<usize>>,
+    // "US.CA" -> "California"
+    regions: HashMap<String, String>,
+    // "US" -> "United States"
+    countries: HashMap<String, String>,
+}
+
+fn cell(latitude: f64, longitude: f64) -> (i32, i32) {
+    (latitude.floor() as i32, longitude.floor() as i32)
+}
+
+// great circle distance
+fn distance_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f64 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = (lon2 - lon1).to_radians();
+    let a = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS_KM * a.sqrt().asin()
+}
+
+// tab separated, lines starting with # are comments
+fn rows(data: &str) -> impl Iterator<Item = Vec<&str>> {
+    data.lines().filter(|l| !l.starts_with('#') && !l.trim().is_empty()).map(|l| l.split('\t').collect())
+}
+
+impl Geocoder {
+    /// `cities` is a cities*.txt dump, `admin1` (admin1CodesASCII.txt) and `countries` (countryInfo.txt) turn codes into names
+    pub fn parse(cities: &str, admin1: Option<&str>, countries: Option<&str>) -> Result<Self, GeocodeError> {
+        let mut geocoder = Geocoder::default();
+
+        for (line, row) in rows(cities).enumerate() {
+            if row.len() < CITY_COLUMNS {
+                return Err(GeocodeError::InvalidLine(line + 1));
+            }
+            let (Ok(latitude), Ok(longitude)) = (row[CITY_LATITUDE].parse::<f64>(), row[CITY_LONGITUDE].parse::<f64>()) else {
+                return Err(GeocodeError::InvalidLine(line + 1));
+            };
+            geocoder.grid.entry(cell(latitude, longitude)).or_default().push(geocoder.cities.len());
+            geocoder.cities.push(City {
+                name: row[CITY_NAME].to_string(),
+                latitude,
+                longitude,
+                country_code: row[CITY_COUNTRY_CODE].to_string(),
+                admin1_code: row[CITY_ADMIN1_CODE].to_string(),
+            });
+        }
+
+        // "US.CA\tCalifornia\tCalifornia\t5332921"
+        geocoder.regions = admin1
+            .map(|data| rows(data).filter(|r| r.len() >= 2).map(|r| (r[0].to_string(), r[1].to_string())).collect())
+            .unwrap_or_default();
+        geocoder.countries = countries
+            .map(|data| rows(data).filter(|r| r.len() > COUNTRY_NAME).map(|r| (r[COUNTRY_CODE].to_string(), r[COUNTRY_NAME].to_string())).collect())
+            .unwrap_or_default();
+
+        Ok(geocoder)
+    }
+
+    pub fn load(cities: &Path, admin1: Option<&Path>, countries: Option<&Path>) -> Result<Self, GeocodeError> {
+        let read = |path: &Path| std::fs::read_to_string(path).map_err(|e| GeocodeError::IoError(path.to_path_buf(), e));
+        let admin1 = admin1.map(read).transpose()?;
+        let countries = countries.map(read).transpose()?;
+        Self::parse(&read(cities)?, admin1.as_deref(), countries.as_deref())
+    }
+
+    pub fn len(&self) -> usize {
+        self.cities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cities.is_empty()
+    }
+
+    /// the nearest city within `max_distance_km`
+    pub fn lookup(&self, latitude: f64, longitude: f64, max_distance_km: f64) -> Option<Place> {
+        if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+            return None;
+        }
+
+        // a degree of longitude shrinks towards the poles, so more cells are needed to cover the same distance
+        let lat_cells = (max_distance_km / KM_PER_DEGREE).ceil() as i32;
+        let lon_cells = (max_distance_km / (KM_PER_DEGREE * latitude.to_radians().cos().max(0.01))).ceil().min(180.0) as i32;
+        let (lat_cell, lon_cell) = cell(latitude, longitude);
+
+        let mut nearest: Option<(&City, f64)> = None;
+        for dlat in -lat_cells..=lat_cells {
+            for dlon in -lon_cells..=lon_cells {
+                // wrap around the antimeridian
+                let lon = (lon_cell + dlon + 180).rem_euclid(360) - 180;
+                let Some(indexes) = self.grid.get(&(lat_cell + dlat, lon)) else {
+                    continue;
+                };
+                for city in indexes.iter().map(|i| &self.cities[*i]) {
+                    let distance = distance_km(latitude, longitude, city.latitude, city.longitude);
+                    if distance <= max_distance_km && nearest.is_none_or(|(_, d)| distance < d) {
+                        nearest = Some((city, distance));
+                    }
+                }
+            }
+        }
+
+        nearest.map(|(city, _)| Place {
+            country: self.countries.get(&city.country_code).cloned().unwrap_or_else(|| city.country_code.clone()),
+            region: self.regions.get(&format!("{}.{}", city.country_code, city.admin1_code)).cloned(),
+            city: city.name.clone(),
+        })
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GeocodeError {
+    #[error("iO error reading {0:?}: {1}")]
+    IoError(std::path::PathBuf, std::io::Error),
+    #[error("invalid cities file, line {0}")]
+    InvalidLine(usize),
+}
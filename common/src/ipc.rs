@@ -1,5 +1,8 @@
 
+use chrono::NaiveDateTime;
 use serde::{Serialize, Deserialize};
+use crate::media_query::MediaQuery;
+use crate::models::option_date;
 use crate::models::queue::Queue;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -13,7 +16,48 @@ pub enum IpcRequest {
     FileSize {
         file: IpcFileRequest
     },
-    QueueProgress
+    // responds with an IpcFileResponse followed by `response_size` bytes of JPEG
+    RenderPage {
+        file: IpcFileRequest,
+        page: u16,
+        max_size: u32,
+    },
+    // writes the media's tags and albums to its XMP sidecar, responds with an IpcFileResponse
+    WriteSidecar {
+        file: IpcFileRequest,
+    },
+    QueueProgress,
+    // the rest respond with an IpcQueueControlResponse
+    // the current item finishes, nothing new starts until resumed
+    PauseQueue,
+    ResumeQueue,
+    // the current items finish and the run ends, nothing new starts until resumed
+    DrainQueue,
+    // running items are stopped and put back in the queue, nothing new starts until resumed
+    CancelQueue,
+    CancelTask {
+        task: String,
+    },
+    // the rest respond with an IpcQueueResponse
+    // queues the task for every compatible media matching `query`
+    Enqueue {
+        task: String,
+        query: MediaQuery,
+        priority: i32,
+    },
+    ClearQueue {
+        task: String,
+    },
+    // queues the task for the media ahead of everything else, even if it's up to date
+    RerunTask {
+        media_id: i32,
+        task: String,
+    },
+    // removes what the task stored for every compatible media matching `query` and queues them again
+    PurgeTask {
+        task: String,
+        query: MediaQuery,
+    },
 }
 
 
@@ -47,6 +91,8 @@ pub struct RunProgressSer {
     pub queue_id: Option<i32>,
     pub error: Option<String>,
     pub time: u32, // time taken to run the task in seconds
+    #[serde(default)]
+    pub steps: Vec<(String, u32)>, // time taken by each step in milliseconds
 }
 
 
@@ -59,6 +105,50 @@ pub enum QueueProgress {
     },
     Progress(RunProgressSer),
     Done(Result<(u32, u32), String>),
+    Scanning,
+    /// between scheduled runs
+    Idle {
+        last: Option<Result<(u32, u32), String>>,
+        #[serde(with = "option_date")]
+        next_scan: Option<NaiveDateTime>,
+        state: QueueState,
+        // quiet hours and canceled tasks
+        held_tasks: Vec<String>,
+    },
+}
+
+pub type IpcQueueProgressResponse = QueueProgress;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueState {
+    Running,
+    Paused,
+    Draining,
+    Canceled,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "status")]
+pub enum IpcQueueControlResponse {
+    Error {
+        error: String,
+    },
+    Success {
+        state: QueueState,
+        // task types canceled on their own, held until the queue is resumed
+        canceled_tasks: Vec<String>,
+    },
 }
 
-pub type IpcQueueProgressResponse = QueueProgress;
\ No newline at end of file
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "status")]
+pub enum IpcQueueResponse {
+    Error {
+        error: String,
+    },
+    Success {
+        // items queued or removed
+        count: u32,
+    },
+}
@@ -7,4 +7,7 @@ pub mod directory_tree;
 pub mod env;
 pub mod media_processors;
 pub mod runner_config;
-pub mod remote_models;
\ No newline at end of file
+pub mod remote_models;
+pub mod subtitles;
+pub mod geocoding;
+pub mod schedule;
\ No newline at end of file
@@ -0,0 +1,93 @@
+use std::f32::consts::PI;
+use image::RgbImage;
+
+// https://github.com/woltapp/blurhash/blob/master/Algorithm.md
+
+const CHARACTERS: &[u8; 83] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// what the gallery uses, enough detail for a placeholder and only 28 characters
+pub const X_COMPONENTS: u32 = 4;
+pub const Y_COMPONENTS: u32 = 3;
+
+fn encode83(value: u32, length: u32, out: &mut String) {
+    for i in 1..=length {
+        let digit = value / 83u32.pow(length - i) % 83;
+        out.push(CHARACTERS[digit as usize] as char);
+    }
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let v = value as f32 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u32 {
+    let v = value.clamp(0.0, 1.0);
+    if v <= 0.0031308 {
+        (v * 12.92 * 255.0 + 0.5) as u32
+    } else {
+        ((1.055 * v.powf(1.0 / 2.4) - 0.055) * 255.0 + 0.5) as u32
+    }
+}
+
+fn sign_pow(value: f32, exponent: f32) -> f32 {
+    value.abs().powf(exponent).copysign(value)
+}
+
+/// encodes `image` with `x_components` x `y_components` (1 to 9 each) cosine components
+///
+/// the cost is pixels x components, so pass a small image, a 32px thumbnail looks the same as the original
+pub fn encode(image: &RgbImage, x_components: u32, y_components: u32) -> String {
+    assert!((1..=9).contains(&x_components) && (1..=9).contains(&y_components), "blurhash components must be between 1 and 9");
+
+    let (width, height) = image.dimensions();
+    let linear: Vec<[f32; 3]> = image.pixels().map(|p| [srgb_to_linear(p[0]), srgb_to_linear(p[1]), srgb_to_linear(p[2])]).collect();
+
+    let mut factors = Vec::with_capacity((x_components * y_components) as usize);
+    for j in 0..y_components {
+        for i in 0..x_components {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut factor = [0.0f32; 3];
+            for y in 0..height {
+                let basis_y = (PI * j as f32 * y as f32 / height as f32).cos();
+                for x in 0..width {
+                    let basis = normalisation * (PI * i as f32 * x as f32 / width as f32).cos() * basis_y;
+                    let pixel = linear[(y * width + x) as usize];
+                    factor[0] += basis * pixel[0];
+                    factor[1] += basis * pixel[1];
+                    factor[2] += basis * pixel[2];
+                }
+            }
+            let scale = 1.0 / (width * height).max(1) as f32;
+            factors.push(factor.map(|f| f * scale));
+        }
+    }
+
+    let (dc, ac) = factors.split_first().unwrap();
+
+    let mut hash = String::new();
+    encode83((x_components - 1) + (y_components - 1) * 9, 1, &mut hash);
+
+    let max_value = if ac.is_empty() {
+        encode83(0, 1, &mut hash);
+        1.0
+    } else {
+        let actual_max = ac.iter().flatten().fold(0.0f32, |max, f| max.max(f.abs()));
+        let quantised_max = (actual_max * 166.0 - 0.5).floor().clamp(0.0, 82.0) as u32;
+        encode83(quantised_max, 1, &mut hash);
+        (quantised_max + 1) as f32 / 166.0
+    };
+
+    encode83((linear_to_srgb(dc[0]) << 16) + (linear_to_srgb(dc[1]) << 8) + linear_to_srgb(dc[2]), 4, &mut hash);
+
+    for factor in ac {
+        let [r, g, b] = factor.map(|f| (sign_pow(f / max_value, 0.5) * 9.0 + 9.5).floor().clamp(0.0, 18.0) as u32);
+        encode83(r * 19 * 19 + g * 19 + b, 2, &mut hash);
+    }
+
+    hash
+}
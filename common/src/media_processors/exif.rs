@@ -1,3 +1,4 @@
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime};
 use exif::{Exif, In, Tag, Value};
 use iso6709parse::ISO6709Coord;
 use nom_exif::{EntryValue, LatLng, TrackInfo, TrackInfoTag};
@@ -7,17 +8,36 @@ pub struct ExifMetadata {
     pub longitude: Option<f64>,
     pub latitude: Option<f64>,
     pub is_screenshot: bool,
+    pub content_identifier: Option<String>,
+    /// when the shutter fired, in UTC if the camera recorded its offset
+    pub captured_at: Option<NaiveDateTime>,
+    /// make and model
+    pub camera: Option<String>,
 }
 
+const APPLE_MAKER_NOTE: &[u8] = b"Apple iOS\0";
+const APPLE_CONTENT_IDENTIFIER: u16 = 0x0011;
+
 pub fn extract_exif(exif: &Exif) -> Result<ExifMetadata, exif::Error> {
     let mut metadata = ExifMetadata {
         longitude: None,
         latitude: None,
         is_screenshot: false,
+        content_identifier: None,
+        captured_at: None,
+        camera: None,
     };
 
     metadata.is_screenshot = exif.get_field(Tag::UserComment, In::PRIMARY).and_then(|field| parse_comment(&field.value)).map(|comment| comment.contains("Screenshot")).unwrap_or(false);
 
+    metadata.content_identifier = exif.get_field(Tag::MakerNote, In::PRIMARY).and_then(|field| match &field.value {
+        Value::Undefined(bytes, _) => apple_content_identifier(bytes),
+        _ => None,
+    });
+
+    metadata.captured_at = capture_time(exif);
+    metadata.camera = camera(exif);
+
     if let (Some(direction), Some(values)) = (exif.get_field(Tag::GPSLatitudeRef, In::PRIMARY), exif.get_field(Tag::GPSLatitude, In::PRIMARY)) {
         metadata.latitude = parse_gps(&direction.value, &values.value);
     }
@@ -44,6 +64,63 @@ fn parse_comment(comment: &Value) -> Option<String> {
     None
 }
 
+fn ascii(exif: &Exif, tag: Tag) -> Option<&[u8]> {
+    match &exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(values) => values.first().map(|v| v.as_slice()),
+        _ => None,
+    }
+}
+
+fn capture_time(exif: &Exif) -> Option<NaiveDateTime> {
+    let mut datetime = exif::DateTime::from_ascii(ascii(exif, Tag::DateTimeOriginal)?).ok()?;
+    if let Some(subsec) = ascii(exif, Tag::SubSecTimeOriginal) {
+        datetime.parse_subsec(subsec).ok();
+    }
+    if let Some(offset) = ascii(exif, Tag::OffsetTimeOriginal) {
+        datetime.parse_offset(offset).ok();
+    }
+    let date = NaiveDate::from_ymd_opt(datetime.year as i32, datetime.month as u32, datetime.day as u32)?;
+    let time = NaiveTime::from_hms_nano_opt(datetime.hour as u32, datetime.minute as u32, datetime.second as u32, datetime.nanosecond.unwrap_or(0))?;
+    Some(NaiveDateTime::new(date, time) - Duration::minutes(datetime.offset.unwrap_or(0) as i64))
+}
+
+fn camera(exif: &Exif) -> Option<String> {
+    let text = |tag| ascii(exif, tag).map(|v| String::from_utf8_lossy(v).trim().to_string()).filter(|v| !v.is_empty());
+    match (text(Tag::Make), text(Tag::Model)) {
+        // plenty of models already start with the make ("Canon EOS R5")
+        (Some(make), Some(model)) if model.starts_with(&make) => Some(model),
+        (Some(make), Some(model)) => Some(format!("{} {}", make, model)),
+        (make, model) => make.or(model),
+    }
+}
+
+// Apple's maker note is "Apple iOS\0", a 2 byte version, "MM" and then a big endian IFD with offsets relative to the start of the note
+fn apple_content_identifier(note: &[u8]) -> Option<String> {
+    if !note.starts_with(APPLE_MAKER_NOTE) || note.get(12..14)? != b"MM" {
+        return None;
+    }
+    let u16_at = |at: usize| note.get(at..at + 2).map(|b| u16::from_be_bytes([b[0], b[1]]));
+    let u32_at = |at: usize| note.get(at..at + 4).map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]) as usize);
+
+    let count = u16_at(14)? as usize;
+    for i in 0..count {
+        let entry = 16 + i * 12;
+        if u16_at(entry)? != APPLE_CONTENT_IDENTIFIER {
+            continue;
+        }
+        let len = u32_at(entry + 4)?;
+        let data = if len <= 4 {
+            note.get(entry + 8..entry + 8 + len)?
+        } else {
+            let offset = u32_at(entry + 8)?;
+            note.get(offset..offset.checked_add(len)?)?
+        };
+        let id = String::from_utf8_lossy(data).trim_end_matches('\0').to_string();
+        return (!id.is_empty()).then_some(id);
+    }
+    None
+}
+
 fn parse_gps(direction: &Value, values: &Value) -> Option<f64> {
     match (direction, values) {
         (Value::Ascii(direction), Value::Rational(values)) => {
@@ -73,6 +150,9 @@ pub fn extract_exif_nom(track_info: &TrackInfo) -> ExifMetadata {
         longitude: None,
         latitude: None,
         is_screenshot: false,
+        content_identifier: None,
+        captured_at: None,
+        camera: None,
     };
 
     let gps = track_info.get(TrackInfoTag::GpsIso6709);
@@ -1,9 +1,29 @@
+use std::collections::HashMap;
 use std::path::Path;
+use std::process::Command;
 use std::time::Duration;
-use crate::media_processors::format::{Audioable, Format, FormatType, MediaMetadata, MediaType};
+use ffmpeg_next::format::stream::Disposition;
+use image::{Rgb, RgbImage};
+use crate::media_processors::format::{Audioable, Format, FormatType, MediaMetadata, MediaType, Thumbnailable};
 use crate::models::system_time_to_naive_datetime;
 use crate::scan_config::AppConfig;
 
+// (ffmpeg tag, our key)
+const TAGS: [(&str, &str); 6] = [
+    ("title", "title"),
+    ("artist", "artist"),
+    ("album", "album"),
+    ("track", "track"),
+    ("date", "year"),
+    ("genre", "genre"),
+];
+
+// the waveform doesn't need anywhere near the full sample rate
+const WAVEFORM_SAMPLE_RATE: u32 = 4000;
+const WAVEFORM_FULL_SIZE: (u32, u32) = (1920, 1080);
+const WAVEFORM_BACKGROUND: Rgb<u8> = Rgb([24, 24, 27]);
+const WAVEFORM_FOREGROUND: Rgb<u8> = Rgb([147, 197, 253]);
+
 pub struct Audio;
 
 impl Audio {
@@ -17,17 +37,122 @@ impl Audio {
 
         Ok(stream.duration() as f64 * f64::from(stream.time_base()))
     }
+
+    /// ID3/Vorbis/MP4 tags as (key, value), keys are from `TAGS`
+    pub fn tags<T: AsRef<Path>>(path: T) -> Result<Vec<(String, String)>, AudioError> {
+        ffmpeg_next::init().unwrap();
+        let context = ffmpeg_next::format::input(&path)?;
+
+        // ogg keeps its vorbis comments on the stream rather than the container
+        let mut all: HashMap<String, String> = context
+            .streams()
+            .best(ffmpeg_next::media::Type::Audio)
+            .map(|s| s.metadata().iter().map(|(k, v)| (k.to_lowercase(), v.to_string())).collect())
+            .unwrap_or_default();
+        all.extend(context.metadata().iter().map(|(k, v)| (k.to_lowercase(), v.to_string())));
+
+        Ok(TAGS
+            .iter()
+            .filter_map(|(tag, key)| {
+                let value = all.get(*tag)?.trim();
+                // dates can be full timestamps, we only want the year
+                let value = if *key == "year" { value.get(0..4).unwrap_or(value) } else { value };
+                if value.is_empty() {
+                    return None;
+                }
+                Some((key.to_string(), value.to_string()))
+            })
+            .collect())
+    }
+
+    /// the embedded cover art (attached picture stream), if any
+    pub fn cover_art<T: AsRef<Path>>(path: T) -> Result<Option<RgbImage>, AudioError> {
+        ffmpeg_next::init().unwrap();
+        let mut context = ffmpeg_next::format::input(&path)?;
+        let index = context
+            .streams()
+            .find(|s| s.disposition().contains(Disposition::ATTACHED_PIC))
+            .map(|s| s.index());
+
+        let Some(index) = index else {
+            return Ok(None);
+        };
+
+        for (stream, packet) in context.packets() {
+            if stream.index() == index {
+                if let Some(data) = packet.data() {
+                    return Ok(Some(image::load_from_memory(data)?.to_rgb8()));
+                }
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn samples(path: &Path, app_config: &AppConfig) -> Result<Vec<i16>, AudioError> {
+        // mono signed 16-bit PCM to stdout
+        let output = Command::new(&app_config.ffmpeg_path)
+            .args(&[
+                "-v", "error",
+                "-i", path.to_string_lossy().to_string().as_str(),
+                "-ac", "1",
+                "-ar", WAVEFORM_SAMPLE_RATE.to_string().as_str(),
+                "-f", "s16le",
+                "-",
+            ])
+            .output()?;
+
+        if !output.status.success() {
+            return Err(AudioError::ConversionError(String::from_utf8_lossy(&output.stderr).to_string()));
+        }
+
+        Ok(output.stdout.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect())
+    }
+
+    pub fn render_waveform(samples: &[i16], width: u32, height: u32) -> RgbImage {
+        let mut image = RgbImage::from_pixel(width, height, WAVEFORM_BACKGROUND);
+        if samples.is_empty() || width == 0 || height == 0 {
+            return image;
+        }
+
+        let per_column = samples.len() as f64 / width as f64;
+        let peaks: Vec<f32> = (0..width)
+            .map(|x| {
+                let start = (x as f64 * per_column) as usize;
+                let end = (((x + 1) as f64 * per_column) as usize).max(start + 1).min(samples.len());
+                samples
+                    .get(start..end)
+                    .and_then(|column| column.iter().map(|s| s.unsigned_abs()).max())
+                    .unwrap_or(0) as f32
+            })
+            .collect();
+
+        // normalize to the loudest column so quiet recordings are still visible
+        let loudest = peaks.iter().cloned().fold(1.0, f32::max);
+        let middle = height as f32 / 2.0;
+
+        for (x, peak) in peaks.iter().enumerate() {
+            let half = (peak / loudest * middle * 0.9).max(1.0);
+            let top = (middle - half).max(0.0) as u32;
+            let bottom = ((middle + half) as u32).min(height - 1);
+            for y in top..=bottom {
+                image.put_pixel(x as u32, y, WAVEFORM_FOREGROUND);
+            }
+        }
+
+        image
+    }
 }
 
 impl Format for Audio {
     type Error = AudioError;
     const FORMAT_TYPE: FormatType = FormatType::Audio;
     const EXTENSIONS: &'static [&'static str] = &["mp3", "wav", "flac", "ogg", "m4a", "aac", "wma", "aiff", "alac", "m4a"];
-    const METADATA_VERSION: i32 = 1;
+    const METADATA_VERSION: i32 = 2;
 
     fn get_metadata(path: &Path, _: &AppConfig) -> Result<MediaMetadata, Self::Error> {
         let file_meta = path.metadata()?;
-        
+
         let seconds = Self::duration(path)?;
         let milliseconds = (seconds * 1000.0).round() as u64;
 
@@ -43,10 +168,26 @@ impl Format for Audio {
             latitude: None,
             is_screenshot: false,
             media_type: MediaType::Audio,
+            fields: Self::tags(path)?,
+            content_identifier: None,
+            motion_offset: None,
+            xmp: None,
         })
     }
 }
 
+impl Thumbnailable for Audio {
+    const THUMBNAIL_VERSION: i32 = 0;
+
+    fn decode(path: &Path, _: Option<u32>, app_config: &AppConfig) -> Result<RgbImage, Self::Error> {
+        if let Some(cover) = Self::cover_art(path)? {
+            return Ok(cover);
+        }
+        let samples = Self::samples(path, app_config)?;
+        Ok(Self::render_waveform(&samples, WAVEFORM_FULL_SIZE.0, WAVEFORM_FULL_SIZE.1))
+    }
+}
+
 impl Audioable for Audio {}
 
 #[derive(thiserror::Error, Debug)]
@@ -55,4 +196,8 @@ pub enum AudioError {
     IoError(#[from] std::io::Error),
     #[error("ffmpeg error: {0}")]
     FfmpegError(#[from] ffmpeg_next::Error),
+    #[error("image error: {0}")]
+    ImageError(#[from] image::ImageError),
+    #[error("conversion error: {0}")]
+    ConversionError(String),
 }
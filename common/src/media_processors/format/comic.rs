@@ -0,0 +1,148 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use image::RgbImage;
+use zip::ZipArchive;
+use crate::media_processors::format::{Format, FormatType, MediaMetadata, MediaType, Thumbnailable};
+use crate::models::system_time_to_naive_datetime;
+use crate::scan_config::AppConfig;
+
+const PAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png", "gif", "webp"];
+
+// pages are a few MB, anything past this is a broken archive or a zip bomb
+const MAX_PAGE_SIZE: u64 = 64 * 1024 * 1024;
+
+/// comic book archives, cbz (zip) and cbr (rar), each page is an image
+pub struct Comic;
+
+impl Comic {
+    fn is_rar(path: &Path) -> bool {
+        path.extension().unwrap_or_default().to_string_lossy().to_lowercase() == "cbr"
+    }
+
+    fn is_page(name: &str) -> bool {
+        let name = name.to_lowercase();
+        // skip macOS resource forks and hidden files
+        if name.starts_with("__macosx/") || name.rsplit('/').next().map(|n| n.starts_with('.')).unwrap_or(true) {
+            return false;
+        }
+        PAGE_EXTENSIONS.iter().any(|ext| name.ends_with(&format!(".{}", ext)))
+    }
+
+    /// page entry names in reading order
+    pub fn pages(path: &Path) -> Result<Vec<String>, ComicError> {
+        let mut pages: Vec<String> = if Self::is_rar(path) {
+            unrar::Archive::new(path)
+                .open_for_listing()?
+                .filter_map(|header| header.ok())
+                .filter(|header| header.is_file())
+                .map(|header| header.filename.to_string_lossy().replace('\\', "/"))
+                .filter(|name| Self::is_page(name))
+                .collect()
+        } else {
+            let archive = ZipArchive::new(File::open(path)?)?;
+            archive.file_names().filter(|name| Self::is_page(name)).map(|name| name.to_string()).collect()
+        };
+        pages.sort_by_key(|name| name.to_lowercase());
+        Ok(pages)
+    }
+
+    fn read_page(path: &Path, page: &str) -> Result<Vec<u8>, ComicError> {
+        if Self::is_rar(path) {
+            let mut archive = unrar::Archive::new(path).open_for_processing()?;
+            while let Some(header) = archive.read_header()? {
+                if header.entry().filename.to_string_lossy().replace('\\', "/") == page {
+                    if header.entry().unpacked_size > MAX_PAGE_SIZE {
+                        return Err(ComicError::PageTooLarge(page.to_string()));
+                    }
+                    let (data, _) = header.read()?;
+                    return Ok(data);
+                }
+                archive = header.skip()?;
+            }
+            Err(ComicError::NoPages)
+        } else {
+            let mut archive = ZipArchive::new(File::open(path)?)?;
+            let entry = archive.by_name(page)?;
+            if entry.size() > MAX_PAGE_SIZE {
+                return Err(ComicError::PageTooLarge(page.to_string()));
+            }
+            // the declared size can lie
+            let mut buf = Vec::new();
+            entry.take(MAX_PAGE_SIZE + 1).read_to_end(&mut buf)?;
+            if buf.len() as u64 > MAX_PAGE_SIZE {
+                return Err(ComicError::PageTooLarge(page.to_string()));
+            }
+            Ok(buf)
+        }
+    }
+
+    pub fn first_page(path: &Path) -> Result<RgbImage, ComicError> {
+        let pages = Self::pages(path)?;
+        let first = pages.first().ok_or(ComicError::NoPages)?;
+        let data = Self::read_page(path, first)?;
+        Ok(image::load_from_memory(&data)?.to_rgb8())
+    }
+}
+
+impl Format for Comic {
+    type Error = ComicError;
+    const FORMAT_TYPE: FormatType = FormatType::Comic;
+    const EXTENSIONS: &'static [&'static str] = &["cbz", "cbr"];
+    const METADATA_VERSION: i32 = 0;
+
+    fn get_metadata(path: &Path, _: &AppConfig) -> Result<MediaMetadata, Self::Error> {
+        let file_meta = path.metadata()?;
+
+        let pages = Self::pages(path)?;
+
+        let (width, height) = match pages.first() {
+            Some(first) => Self::read_page(path, first)
+                .ok()
+                .and_then(|data| image::ImageReader::new(std::io::Cursor::new(data)).with_guessed_format().ok()?.into_dimensions().ok())
+                .unwrap_or((0, 0)),
+            None => (0, 0),
+        };
+
+        Ok(MediaMetadata {
+            name: path.file_name().unwrap().to_string_lossy().to_string(),
+            width,
+            height,
+            size: file_meta.len() as u32,
+            created_at: system_time_to_naive_datetime(file_meta.created().unwrap()),
+            duration: None,
+            longitude: None,
+            latitude: None,
+            is_screenshot: false,
+            media_type: MediaType::Book,
+            fields: vec![("page_count".to_string(), pages.len().to_string())],
+            content_identifier: None,
+            motion_offset: None,
+            xmp: None,
+        })
+    }
+}
+
+impl Thumbnailable for Comic {
+    const THUMBNAIL_VERSION: i32 = 0;
+
+    fn decode(path: &Path, _: Option<u32>, _: &AppConfig) -> Result<RgbImage, Self::Error> {
+        Self::first_page(path)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ComicError {
+    #[error("iO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("zip error: {0}")]
+    ZipError(#[from] zip::result::ZipError),
+    #[error("rar error: {0}")]
+    RarError(#[from] unrar::error::UnrarError),
+    #[error("image error: {0}")]
+    ImageError(#[from] image::ImageError),
+    #[error("archive has no pages")]
+    NoPages,
+    #[error("page is too large: {0}")]
+    PageTooLarge(String),
+}
@@ -0,0 +1,183 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+use image::RgbImage;
+use percent_encoding::percent_decode_str;
+use zip::ZipArchive;
+use crate::media_processors::format::{Format, FormatType, MediaMetadata, MediaType, Thumbnailable};
+use crate::models::system_time_to_naive_datetime;
+use crate::scan_config::AppConfig;
+
+const CONTAINER_PATH: &str = "META-INF/container.xml";
+const DC_NAMESPACE: &str = "http://purl.org/dc/elements/1.1/";
+
+// package documents and covers are small, anything past this is a broken archive or a zip bomb
+const MAX_ENTRY_SIZE: u64 = 64 * 1024 * 1024;
+
+pub struct Epub;
+
+#[derive(Debug, Default)]
+pub struct EpubPackage {
+    pub title: Option<String>,
+    pub authors: Vec<String>,
+    pub language: Option<String>,
+    /// path of the cover image inside the archive
+    pub cover: Option<String>,
+}
+
+impl Epub {
+    fn read_entry(archive: &mut ZipArchive<File>, name: &str) -> Result<Vec<u8>, EpubError> {
+        let entry = archive.by_name(name)?;
+        if entry.size() > MAX_ENTRY_SIZE {
+            return Err(EpubError::EntryTooLarge(name.to_string()));
+        }
+        // the declared size can lie
+        let mut buf = Vec::new();
+        entry.take(MAX_ENTRY_SIZE + 1).read_to_end(&mut buf)?;
+        if buf.len() as u64 > MAX_ENTRY_SIZE {
+            return Err(EpubError::EntryTooLarge(name.to_string()));
+        }
+        Ok(buf)
+    }
+
+    /// reads the OPF package document the container points to
+    pub fn package(archive: &mut ZipArchive<File>) -> Result<EpubPackage, EpubError> {
+        let container = String::from_utf8(Self::read_entry(archive, CONTAINER_PATH)?).map_err(|_| EpubError::InvalidPackage("container is not utf-8"))?;
+        let container = roxmltree::Document::parse(&container)?;
+        let opf_path = container
+            .descendants()
+            .find(|n| n.has_tag_name("rootfile"))
+            .and_then(|n| n.attribute("full-path"))
+            .ok_or(EpubError::InvalidPackage("missing rootfile"))?
+            .to_string();
+
+        let opf = String::from_utf8(Self::read_entry(archive, &opf_path)?).map_err(|_| EpubError::InvalidPackage("package is not utf-8"))?;
+        let opf = roxmltree::Document::parse(&opf)?;
+
+        let dc = |name: &str| {
+            opf.descendants()
+                .filter(|n| n.tag_name().namespace() == Some(DC_NAMESPACE) && n.tag_name().name() == name)
+                .filter_map(|n| n.text())
+                .map(|t| t.trim().to_string())
+                .filter(|t| !t.is_empty())
+                .collect::<Vec<_>>()
+        };
+
+        let manifest: Vec<roxmltree::Node> = opf.descendants().filter(|n| n.has_tag_name("item")).collect();
+
+        // EPUB 3 marks the cover in the manifest, EPUB 2 uses <meta name="cover" content="item id">
+        let epub2_cover_id = opf
+            .descendants()
+            .find(|n| n.has_tag_name("meta") && n.attribute("name") == Some("cover"))
+            .and_then(|n| n.attribute("content"));
+
+        let is_image = |n: &roxmltree::Node| n.attribute("media-type").map(|t| t.starts_with("image/")).unwrap_or(false);
+
+        let cover = manifest
+            .iter()
+            .find(|n| n.attribute("properties").map(|p| p.split_whitespace().any(|p| p == "cover-image")).unwrap_or(false))
+            .or_else(|| manifest.iter().find(|n| epub2_cover_id.is_some() && n.attribute("id") == epub2_cover_id && is_image(n)))
+            .or_else(|| manifest.iter().find(|n| is_image(n) && n.attribute("href").map(|h| h.to_lowercase().contains("cover")).unwrap_or(false)))
+            .or_else(|| manifest.iter().find(|n| is_image(n)))
+            .and_then(|n| n.attribute("href"))
+            .map(|href| {
+                // hrefs are percent-encoded URLs relative to the package document, zip entries aren't
+                let href = percent_decode_str(href).decode_utf8_lossy();
+                match opf_path.rsplit_once('/') {
+                    Some((dir, _)) => format!("{}/{}", dir, href),
+                    None => href.into_owned(),
+                }
+            });
+
+        Ok(EpubPackage {
+            title: dc("title").into_iter().next(),
+            authors: dc("creator"),
+            language: dc("language").into_iter().next(),
+            cover,
+        })
+    }
+
+    pub fn cover(path: &Path) -> Result<RgbImage, EpubError> {
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let package = Self::package(&mut archive)?;
+        let cover = package.cover.ok_or(EpubError::NoCover)?;
+        let data = Self::read_entry(&mut archive, &cover)?;
+        Ok(image::load_from_memory(&data)?.to_rgb8())
+    }
+}
+
+impl Format for Epub {
+    type Error = EpubError;
+    const FORMAT_TYPE: FormatType = FormatType::Epub;
+    const EXTENSIONS: &'static [&'static str] = &["epub"];
+    const METADATA_VERSION: i32 = 0;
+
+    fn get_metadata(path: &Path, _: &AppConfig) -> Result<MediaMetadata, Self::Error> {
+        let file_meta = path.metadata()?;
+
+        let mut archive = ZipArchive::new(File::open(path)?)?;
+        let package = Self::package(&mut archive)?;
+
+        let (width, height) = match &package.cover {
+            Some(cover) => Self::read_entry(&mut archive, cover)
+                .ok()
+                .and_then(|data| image::ImageReader::new(std::io::Cursor::new(data)).with_guessed_format().ok()?.into_dimensions().ok())
+                .unwrap_or((0, 0)),
+            None => (0, 0),
+        };
+
+        let mut fields = Vec::new();
+        if let Some(title) = package.title {
+            fields.push(("title".to_string(), title));
+        }
+        if !package.authors.is_empty() {
+            fields.push(("author".to_string(), package.authors.join(", ")));
+        }
+        if let Some(language) = package.language {
+            fields.push(("language".to_string(), language));
+        }
+
+        Ok(MediaMetadata {
+            name: path.file_name().unwrap().to_string_lossy().to_string(),
+            width,
+            height,
+            size: file_meta.len() as u32,
+            created_at: system_time_to_naive_datetime(file_meta.created().unwrap()),
+            duration: None,
+            longitude: None,
+            latitude: None,
+            is_screenshot: false,
+            media_type: MediaType::Book,
+            fields,
+            content_identifier: None,
+            motion_offset: None,
+            xmp: None,
+        })
+    }
+}
+
+impl Thumbnailable for Epub {
+    const THUMBNAIL_VERSION: i32 = 0;
+
+    fn decode(path: &Path, _: Option<u32>, _: &AppConfig) -> Result<RgbImage, Self::Error> {
+        Self::cover(path)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EpubError {
+    #[error("iO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("zip error: {0}")]
+    ZipError(#[from] zip::result::ZipError),
+    #[error("xml error: {0}")]
+    XmlError(#[from] roxmltree::Error),
+    #[error("image error: {0}")]
+    ImageError(#[from] image::ImageError),
+    #[error("invalid package: {0}")]
+    InvalidPackage(&'static str),
+    #[error("no cover image")]
+    NoCover,
+    #[error("archive entry is too large: {0}")]
+    EntryTooLarge(String),
+}
@@ -1,9 +1,10 @@
 use std::path::{Path, PathBuf};
 use image::{RgbImage};
-use image::imageops::thumbnail;
 use libheif_rs::{ColorSpace, HeifContext, ItemId, LibHeif, RgbChroma};
 use crate::media_processors::exif::extract_exif;
-use crate::media_processors::format::{resize_dimensions, Format, FormatType, MediaMetadata, MediaType, Thumbnailable};
+use crate::media_processors::icc;
+use crate::media_processors::xmp::Xmp;
+use crate::media_processors::format::{Format, FormatType, MediaMetadata, MediaType, Thumbnailable};
 use crate::models::system_time_to_naive_datetime;
 use crate::scan_config::AppConfig;
 
@@ -13,7 +14,7 @@ impl Format for Heif {
     type Error = HeifError;
     const FORMAT_TYPE: FormatType = FormatType::Heif;
     const EXTENSIONS: &'static [&'static str] = &["heif", "heic"];
-    const METADATA_VERSION: i32 = 1;
+    const METADATA_VERSION: i32 = 5;
     fn get_metadata(path: &Path, _: &AppConfig) -> Result<MediaMetadata, HeifError> {
         let file_meta = path.metadata()?;
 
@@ -44,6 +45,14 @@ impl Format for Heif {
             extract_exif(&exif).ok()
         };
 
+        // XMP is stored as a mime item
+        let mut mime_ids: Vec<ItemId> = vec![0; 8];
+        let count = handle.metadata_block_ids(&mut mime_ids, b"mime");
+        let xmp = mime_ids[..count.min(mime_ids.len())]
+            .iter()
+            .filter(|id| handle.metadata_content_type(**id) == Some("application/rdf+xml"))
+            .find_map(|id| handle.metadata(*id).ok().and_then(|data| Xmp::embedded(&data)));
+
         let native = file_meta.created().unwrap();
 
         Ok(MediaMetadata {
@@ -51,24 +60,31 @@ impl Format for Heif {
             width: handle.width(),
             height: handle.height(),
             size: file_meta.len() as u32,
-            created_at: system_time_to_naive_datetime(native),
+            created_at: exif_metadata.as_ref().and_then(|e| e.captured_at).unwrap_or_else(|| system_time_to_naive_datetime(native)),
             duration: None,
             longitude: exif_metadata.as_ref().and_then(|e| e.longitude),
             latitude: exif_metadata.as_ref().and_then(|e| e.latitude),
             is_screenshot: exif_metadata.as_ref().map(|e| e.is_screenshot).unwrap_or(false),
             media_type: MediaType::Photo,
+            fields: exif_metadata.as_ref().and_then(|e| e.camera.clone()).map(|camera| vec![("camera".to_string(), camera)]).unwrap_or_default(),
+            content_identifier: exif_metadata.as_ref().and_then(|e| e.content_identifier.clone()),
+            motion_offset: None,
+            xmp,
         })
     }
 }
 
 impl Thumbnailable for Heif {
-    const THUMBNAIL_VERSION: i32 = 0;
+    const THUMBNAIL_VERSION: i32 = 1;
 
-    fn generate_thumbnail(path: &Path, width: u32, height: u32, _: &AppConfig) -> Result<RgbImage, Self::Error> {
+    fn decode(path: &Path, _: Option<u32>, _: &AppConfig) -> Result<RgbImage, Self::Error> {
         let lib_heif = LibHeif::new();
         let path_str = path.to_str().ok_or(HeifError::PathToString(path.to_path_buf()))?;
         let ctx = HeifContext::read_from_file(path_str)?;
         let handle = ctx.primary_image_handle()?;
+        // iPhones tag everything Display P3
+        // only an embedded ICC profile is honored, files that only carry an NCLX (CICP) color description are left as they are
+        let profile = handle.color_profile_raw().and_then(|profile| icc::parse_profile(&profile.data).inspect_err(|e| log::warn!("unable to read color profile of {:?}: {}", path, e)).ok());
 
         let image = lib_heif.decode(&handle, ColorSpace::Rgb(RgbChroma::Rgb), None)?;
 
@@ -85,12 +101,9 @@ impl Thumbnailable for Heif {
             rgb.extend_from_slice(&data[start..end]);
         }
 
-
-        let rgb_image = RgbImage::from_raw(image.width(), image.height(), rgb).unwrap();
-        let (nw, nh) = resize_dimensions(image.width(), image.height(), width, height, false);
-        let thumbnail = thumbnail(&rgb_image, nw, nh);
-
-        Ok(thumbnail)
+        let mut rgb = RgbImage::from_raw(image.width(), image.height(), rgb).unwrap();
+        icc::convert_to_srgb(&mut rgb, profile);
+        Ok(rgb)
     }
 }
 
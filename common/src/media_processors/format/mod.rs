@@ -4,16 +4,21 @@ pub mod video;
 pub mod raw;
 pub mod pdf;
 pub mod audio;
+pub mod epub;
+pub mod comic;
+pub mod svg;
 
 use std::cmp::max;
 use std::fmt::Display;
 use std::path::{Path, PathBuf};
 use std::process::{Command, Output};
-use std::time::Duration;
+use std::time::{Duration, UNIX_EPOCH};
 use image::{RgbImage};
 use serde::{Deserialize, Serialize};
 use sqlx::types::chrono;
 use strum::EnumString;
+use crate::media_processors::json_sidecar;
+use crate::media_processors::xmp::{self, Xmp};
 
 #[derive(Debug)]
 pub struct MediaMetadata {
@@ -27,6 +32,14 @@ pub struct MediaMetadata {
     pub latitude: Option<f64>,
     pub is_screenshot: bool,
     pub media_type: MediaType,
+    /// additional searchable key/value metadata (e.g. audio tags), stored in media_metadata
+    pub fields: Vec<(String, String)>,
+    /// Apple's Live Photo pairing id, present on both the still and the video
+    pub content_identifier: Option<String>,
+    /// where the video starts in a motion photo
+    pub motion_offset: Option<u32>,
+    /// ratings, labels and keywords from embedded XMP, sidecars are merged over this in `AnyFormat::get_metadata`
+    pub xmp: Option<Xmp>,
 }
 
 
@@ -39,6 +52,7 @@ pub enum MediaType {
     Video,
     Pdf,
     Audio,
+    Book,
     Other
 }
 
@@ -49,6 +63,7 @@ impl Display for MediaType {
             MediaType::Video => "video".to_string(),
             MediaType::Pdf => "pdf".to_string(),
             MediaType::Audio => "audio".to_string(),
+            MediaType::Book => "book".to_string(),
             MediaType::Other => "other".to_string()
         };
         write!(f, "{}", str)
@@ -75,14 +90,11 @@ pub trait Format {
 pub trait Thumbnailable: Format {
     const THUMBNAIL_VERSION: i32; // bump this if the thumbnail format changes
 
-    fn generate_thumbnail(path: &Path, width: u32, height: u32, app_config: &AppConfig) -> Result<RgbImage, Self::Error>;
-
-    fn generate_full(path: &Path, app_config: &AppConfig) -> Result<RgbImage, Self::Error> {
-        let metadata = Self::get_metadata(path, app_config)?;
-        let width = metadata.width;
-        let height = metadata.height;
-        Self::generate_thumbnail(path, width, height, app_config)
-    }
+    /// decodes the image every thumbnail rendition is resampled from, so the source is only decoded once
+    ///
+    /// `max_size` is the longest edge of the largest rendition (None for native resolution), it's a hint for
+    /// formats that can decode or render smaller for cheaper, the result is resampled to fit either way
+    fn decode(path: &Path, max_size: Option<u32>, app_config: &AppConfig) -> Result<RgbImage, Self::Error>;
 }
 
 pub trait Audioable: Format {
@@ -224,13 +236,19 @@ all_formats!({
         Video => video::Video,
         Raw => raw::Raw,
         Pdf => pdf::Pdf,
-        Audio => audio::Audio
+        Audio => audio::Audio,
+        Epub => epub::Epub,
+        Comic => comic::Comic,
+        Svg => svg::Svg
     },
-    all: [standard::Standard, heif::Heif, video::Video, raw::Raw, pdf::Pdf, audio::Audio],
-    thumbnailable:  [standard::Standard, heif::Heif, video::Video, raw::Raw, pdf::Pdf],
+    all: [standard::Standard, heif::Heif, video::Video, raw::Raw, pdf::Pdf, audio::Audio, epub::Epub, comic::Comic, svg::Svg],
+    thumbnailable:  [standard::Standard, heif::Heif, video::Video, raw::Raw, pdf::Pdf, audio::Audio, epub::Epub, comic::Comic, svg::Svg],
     audioable: [video::Video, audio::Audio]
 });
 
+/// `AnyFormat::sidecar_sources` of a file without any
+pub const NO_SIDECARS: &str = "[]";
+
 pub struct AnyFormat {
     format: FormatType,
     path: PathBuf
@@ -251,15 +269,69 @@ impl AnyFormat {
     }
 
     pub fn get_metadata(&self, app_config: &AppConfig) -> Result<MediaMetadata, MetadataError> {
-        match_format!(&self.format, |ActualFormat| { <ActualFormat as Format>::get_metadata(&self.path, app_config).map_err(|e| e.into()) })
+        let mut metadata: MediaMetadata = match_format!(&self.format, |ActualFormat| { <ActualFormat as Format>::get_metadata(&self.path, app_config).map_err(MetadataError::from) })?;
+
+        // sidecars are what other tools edit, so they win over whatever is embedded
+        match xmp::read_sidecar(&self.path) {
+            Ok(Some(sidecar)) => {
+                metadata.xmp = Some(match metadata.xmp.take() {
+                    Some(embedded) => sidecar.merge(embedded),
+                    None => sidecar,
+                });
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("unable to read xmp sidecar for {:?}: {}", self.path, e),
+        }
+
+        if let Some(xmp) = &metadata.xmp {
+            if let (Some(latitude), Some(longitude)) = (xmp.latitude, xmp.longitude) {
+                metadata.latitude = Some(latitude);
+                metadata.longitude = Some(longitude);
+            }
+        }
+
+        // Takeout strips EXIF from the files and keeps the real capture time and location in here
+        match json_sidecar::read_sidecar(&self.path) {
+            Ok(Some(sidecar)) => {
+                if let Some(taken_at) = sidecar.taken_at {
+                    metadata.created_at = taken_at;
+                }
+                if let (Some(latitude), Some(longitude)) = (sidecar.latitude, sidecar.longitude) {
+                    metadata.latitude = Some(latitude);
+                    metadata.longitude = Some(longitude);
+                }
+                if let Some(description) = sidecar.description {
+                    metadata.fields.retain(|(key, _)| key != "description");
+                    metadata.fields.push(("description".to_string(), description));
+                }
+            }
+            Ok(None) => {}
+            Err(e) => log::warn!("unable to read json sidecar for {:?}: {}", self.path, e),
+        }
+
+        Ok(metadata)
     }
 
-    pub fn generate_thumbnail(&self, width: u32, height: u32, app_config: &AppConfig) -> Result<RgbImage, MetadataError> {
-        match_format!(thumbnailable: &self.format, |ActualFormat| { <ActualFormat as Thumbnailable>::generate_thumbnail(&self.path, width, height, app_config).map_err(|e| e.into()) })
+    /// the sidecars next to the file and when they were modified, stored so editing one makes the metadata outdated
+    pub fn sidecar_sources(&self) -> String {
+        let sources: Vec<(String, u64)> = [xmp::sidecar_path(&self.path), json_sidecar::sidecar_path(&self.path)]
+            .into_iter()
+            .flatten()
+            .map(|path| {
+                let modified = path.metadata()
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                    .map(|d| d.as_nanos() as u64)
+                    .unwrap_or_default();
+                (path.to_string_lossy().to_string(), modified)
+            })
+            .collect();
+        serde_json::to_string(&sources).unwrap()
     }
 
-    pub fn generate_full(&self, app_config: &AppConfig) -> Result<RgbImage, MetadataError> {
-        match_format!(thumbnailable: &self.format, |ActualFormat| { <ActualFormat as Thumbnailable>::generate_full(&self.path, app_config).map_err(|e| e.into()) })
+    pub fn decode(&self, max_size: Option<u32>, app_config: &AppConfig) -> Result<RgbImage, MetadataError> {
+        match_format!(thumbnailable: &self.format, |ActualFormat| { <ActualFormat as Thumbnailable>::decode(&self.path, max_size, app_config).map_err(|e| e.into()) })
     }
 
     pub fn metadata_version(&self) -> i32 {
@@ -295,9 +367,24 @@ pub enum MetadataError {
     Pdf(#[from] pdf::PdfError),
     #[error("audio format error: {0}")]
     Audio(#[from] audio::AudioError),
+    #[error("epub format error: {0}")]
+    Epub(#[from] epub::EpubError),
+    #[error("comic format error: {0}")]
+    Comic(#[from] comic::ComicError),
+    #[error("svg format error: {0}")]
+    Svg(#[from] svg::SvgError),
 }
 
-
+/// scales a decoded image down so its longest edge fits `max_size`, never up
+pub fn resample(image: &RgbImage, max_size: Option<u32>) -> RgbImage {
+    match max_size {
+        Some(max_size) if image.width().max(image.height()) > max_size => {
+            let (nw, nh) = resize_dimensions(image.width(), image.height(), max_size, max_size, false);
+            image::imageops::thumbnail(image, nw, nh)
+        }
+        _ => image.clone(),
+    }
+}
 
 /// Calculates the width and height an image should be resized to.
 /// This preserves aspect ratio, and based on the `fill` parameter
@@ -1,5 +1,7 @@
-use image::{RgbImage};
+use image::{ImageFormat, RgbImage};
+use std::io::Cursor;
 use std::path::Path;
+use chrono::{NaiveDate, NaiveDateTime};
 use serde::{Deserialize, Serialize};
 use crate::media_processors::format::{resize_dimensions, Format, FormatType, MediaMetadata, MediaType, Thumbnailable};
 use crate::models::system_time_to_naive_datetime;
@@ -7,7 +9,19 @@ use crate::scan_config::AppConfig;
 use once_cell::sync::OnceCell;
 use pdfium_render::prelude::*;
 
-const FULL_SIZE: u32 = 1920;
+pub const FULL_SIZE: u32 = 1920;
+
+/// metadata key of the text layer, left out of media responses unless asked for since it can be megabytes
+pub const TEXT_KEY: &str = "text";
+
+// document info dictionary entries we keep, (tag, our key)
+const INFO_TAGS: [(PdfDocumentMetadataTagType, &str); 5] = [
+    (PdfDocumentMetadataTagType::Title, "title"),
+    (PdfDocumentMetadataTagType::Author, "author"),
+    (PdfDocumentMetadataTagType::Subject, "subject"),
+    (PdfDocumentMetadataTagType::CreationDate, "creation_date"),
+    (PdfDocumentMetadataTagType::Producer, "producer"),
+];
 
 static PDFIUM: OnceCell<Pdfium> = OnceCell::new();
 
@@ -21,21 +35,90 @@ impl Pdf {
     pub fn get_pdfium(pdfium_path: &str) -> &Pdfium {
         PDFIUM.get_or_init(|| { Pdfium::new(Pdfium::bind_to_library(pdfium_path).expect("could not init pdfium")) })
     }
+
+    /// PDF dates look like `D:YYYYMMDDHHmmSSOHH'mm'` where everything after the year is optional
+    pub fn parse_date(date: &str) -> Option<NaiveDateTime> {
+        let digits: String = date.trim_start_matches("D:").chars().take_while(|c| c.is_ascii_digit()).collect();
+        let part = |start: usize, default: u32| -> Option<u32> {
+            digits.get(start..start + 2).map(|p| p.parse().ok()).unwrap_or(Some(default))
+        };
+        let year = digits.get(0..4)?.parse().ok()?;
+        let date = NaiveDate::from_ymd_opt(year, part(4, 1)?, part(6, 1)?)?;
+        date.and_hms_opt(part(8, 0)?, part(10, 0)?, part(12, 0)?)
+    }
+
+    /// renders a single page (zero based) as a JPEG that fits within max_size x max_size
+    pub fn render_page_jpeg(path: &Path, page: u16, max_size: u32, app_config: &AppConfig) -> Result<Vec<u8>, PdfError> {
+        let pdfium = Self::get_pdfium(app_config.formats.pdf.pdfium_path.as_str());
+        let document = pdfium.load_pdf_from_file(path, None)?;
+        let page_count = document.pages().len();
+        if page >= page_count {
+            return Err(PdfError::PageOutOfRange(page, page_count));
+        }
+        let page = document.pages().get(page)?;
+
+        let (nw, nh) = resize_dimensions(page.width().value as u32, page.height().value as u32, max_size, max_size, false);
+
+        let image = page.render(nw as i32, nh as i32, None)?.as_image().to_rgb8();
+
+        let mut out = Cursor::new(Vec::new());
+        image.write_to(&mut out, ImageFormat::Jpeg)?;
+        Ok(out.into_inner())
+    }
 }
 
 impl Format for Pdf {
     type Error = PdfError;
     const FORMAT_TYPE: FormatType = FormatType::Pdf;
     const EXTENSIONS: &'static [&'static str] = &["pdf"];
-    const METADATA_VERSION: i32 = 1;
+    const METADATA_VERSION: i32 = 2;
 
-    fn get_metadata(path: &Path, _: &AppConfig) -> Result<MediaMetadata, Self::Error> {
+    fn get_metadata(path: &Path, app_config: &AppConfig) -> Result<MediaMetadata, Self::Error> {
         let file_meta = path.metadata()?;
 
+        let pdfium = Self::get_pdfium(app_config.formats.pdf.pdfium_path.as_str());
+        let document = pdfium.load_pdf_from_file(path, None)?;
+        let pages = document.pages();
+
+        // dimensions are in points, which is what we render thumbnails at
+        let (width, height) = if pages.len() > 0 {
+            let first = pages.get(0)?;
+            (first.width().value.round() as u32, first.height().value.round() as u32)
+        } else {
+            (0, 0)
+        };
+
+        let mut fields = vec![("page_count".to_string(), pages.len().to_string())];
+
+        for (tag, key) in INFO_TAGS {
+            let Some(value) = document.metadata().get(tag) else {
+                continue;
+            };
+            let value = value.value().trim();
+            if value.is_empty() {
+                continue;
+            }
+            let value = match tag {
+                PdfDocumentMetadataTagType::CreationDate => Self::parse_date(value).map(|d| d.to_string()).unwrap_or(value.to_string()),
+                _ => value.to_string(),
+            };
+            fields.push((key.to_string(), value));
+        }
+
+        // the text layer, scanned documents won't have one
+        let text = pages
+            .iter()
+            .filter_map(|page| page.text().ok().map(|text| text.all()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if !text.trim().is_empty() {
+            fields.push((TEXT_KEY.to_string(), text));
+        }
+
         Ok(MediaMetadata {
             name: path.file_name().unwrap().to_string_lossy().to_string(),
-            width: 0,
-            height: 0,
+            width,
+            height,
             size: file_meta.len() as u32,
             created_at: system_time_to_naive_datetime(file_meta.created().unwrap()),
             duration: None,
@@ -43,6 +126,10 @@ impl Format for Pdf {
             latitude: None,
             is_screenshot: false,
             media_type: MediaType::Pdf,
+            fields,
+            content_identifier: None,
+            motion_offset: None,
+            xmp: None,
         })
     }
 
@@ -51,23 +138,14 @@ impl Format for Pdf {
 impl Thumbnailable for Pdf {
     const THUMBNAIL_VERSION: i32 = 0;
 
-    fn generate_thumbnail(path: &Path, width: u32, height: u32, app_config: &AppConfig) -> Result<RgbImage, Self::Error> {
-        let pdfium = Self::get_pdfium(app_config.formats.pdf.pdfium_path.as_str());
-        let document = pdfium.load_pdf_from_file(path, None)?;
-        let page = document.pages().get(0)?;
-        
-        let (nw, nh) = resize_dimensions(page.width().value as u32, page.height().value as u32, width, height, false);
-
-        let image = page.render(nw as i32, nh as i32, None)?;
-        Ok(image.as_image().to_rgb8())
-    }
-
-    fn generate_full(path: &Path, app_config: &AppConfig) -> Result<RgbImage, Self::Error> {
+    // pages have no native resolution, the first page is rendered only as large as the largest rendition needs
+    fn decode(path: &Path, max_size: Option<u32>, app_config: &AppConfig) -> Result<RgbImage, Self::Error> {
         let pdfium = Self::get_pdfium(app_config.formats.pdf.pdfium_path.as_str());
         let document = pdfium.load_pdf_from_file(path, None)?;
         let page = document.pages().get(0)?;
 
-        let (nw, nh) = resize_dimensions(page.width().value as u32, page.height().value as u32, FULL_SIZE, FULL_SIZE, false);
+        let size = max_size.unwrap_or(FULL_SIZE);
+        let (nw, nh) = resize_dimensions(page.width().value as u32, page.height().value as u32, size, size, false);
 
         let image = page.render(nw as i32, nh as i32, None)?;
 
@@ -82,4 +160,8 @@ pub enum PdfError {
     IoError(#[from] std::io::Error),
     #[error("pdfium error: {0}")]
     PdfiumError(#[from] PdfiumError),
+    #[error("image error: {0}")]
+    ImageError(#[from] image::ImageError),
+    #[error("page {0} out of range, document has {1} pages")]
+    PageOutOfRange(u16, u16),
 }
@@ -1,8 +1,8 @@
 use std::path::Path;
-use image::imageops::thumbnail;
 use image::RgbImage;
 use imagepipe::Pipeline;
-use crate::media_processors::format::{resize_dimensions, Format, FormatType, MediaMetadata, MediaType, Thumbnailable};
+use crate::media_processors::exif::extract_exif;
+use crate::media_processors::format::{Format, FormatType, MediaMetadata, MediaType, Thumbnailable};
 use crate::models::system_time_to_naive_datetime;
 use crate::scan_config::AppConfig;
 
@@ -13,7 +13,7 @@ impl Format for Raw {
     
     const FORMAT_TYPE: FormatType = FormatType::Raw;
     const EXTENSIONS: &'static [&'static str] = &["raf", "arw"];
-    const METADATA_VERSION: i32 = 0;
+    const METADATA_VERSION: i32 = 2;
     fn get_metadata(path: &Path, _: &AppConfig) -> Result<MediaMetadata, RawError> {
         let file_meta = path.metadata()?;
 
@@ -21,17 +21,30 @@ impl Format for Raw {
 
         let image = rawloader::decode_file(path)?;
 
+        // TIFF based raws (ARW) carry regular EXIF, others just fall back to the file time
+        let file = std::fs::File::open(path)?;
+        let exif_metadata = exif::Reader::new()
+            .read_from_container(&mut std::io::BufReader::new(&file))
+            .ok()
+            .and_then(|e| extract_exif(&e).ok());
+
+        let camera = format!("{} {}", image.clean_make, image.clean_model).trim().to_string();
+
         Ok(MediaMetadata {
             name: path.file_name().unwrap().to_string_lossy().to_string(),
             width: image.width as u32,
             height: image.height as u32,
             size: file_meta.len() as u32,
-            created_at: system_time_to_naive_datetime(native),
+            created_at: exif_metadata.as_ref().and_then(|e| e.captured_at).unwrap_or_else(|| system_time_to_naive_datetime(native)),
             duration: None,
             longitude: None,
             latitude: None,
             is_screenshot: false,
             media_type: MediaType::Photo,
+            fields: if camera.is_empty() { Vec::new() } else { vec![("camera".to_string(), camera)] },
+            content_identifier: None,
+            motion_offset: None,
+            xmp: None,
         })
 
     }
@@ -39,16 +52,17 @@ impl Format for Raw {
 
 impl Thumbnailable for Raw {
     const THUMBNAIL_VERSION: i32 = 0;
-    fn generate_thumbnail(path: &Path, width: u32, height: u32, _: &AppConfig) -> Result<RgbImage, RawError> {
+    fn decode(path: &Path, max_size: Option<u32>, _: &AppConfig) -> Result<RgbImage, RawError> {
         let mut image = Pipeline::new_from_file(path).map_err(RawError::PipelineError)?;
+        // demosaicing is by far the slowest part, the pipeline can do it at a lower resolution when that's all that's needed
+        if let Some(max_size) = max_size {
+            image.globals.settings.maxwidth = max_size as usize;
+            image.globals.settings.maxheight = max_size as usize;
+        }
+        // the pipeline goes camera -> XYZ -> sRGB itself, there's no embedded profile to honour
         let srgb = image.output_8bit(None).map_err(RawError::PipelineError)?;
 
-
-        let rgb_image = RgbImage::from_raw(srgb.width as u32, srgb.height as u32, srgb.data).unwrap();
-        let (nw, nh) = resize_dimensions(srgb.width as u32, srgb.height as u32, width, height, false);
-        let thumbnail = thumbnail(&rgb_image, nw, nh);
-
-        Ok(thumbnail)
+        Ok(RgbImage::from_raw(srgb.width as u32, srgb.height as u32, srgb.data).unwrap())
     }
 }
 
@@ -1,17 +1,139 @@
-use image::{RgbImage};
+use image::{DynamicImage, ImageDecoder, ImageReader, RgbImage};
+use std::fs::File;
 use std::path::Path;
+use std::io::{Read, Seek, SeekFrom};
 use crate::media_processors::exif::extract_exif;
+use crate::media_processors::icc;
+use crate::media_processors::xmp::Xmp;
 use crate::media_processors::format::{Format, FormatType, MediaMetadata, MediaType, Thumbnailable};
 use crate::models::system_time_to_naive_datetime;
 use crate::scan_config::AppConfig;
 
+// XMP lives in the JPEG header, no need to look any further for it
+const XMP_SEARCH_LENGTH: usize = 256 * 1024;
+const SAMSUNG_MOTION_MARKER: &[u8] = b"MotionPhoto_Data";
+const SAMSUNG_HEADER: &[u8] = b"SEFH";
+const SAMSUNG_FOOTER: &[u8] = b"SEFT";
+// how much of the end of the file is searched for the marker when there's no directory
+const TRAILER_SEARCH_LENGTH: u64 = 1024 * 1024;
+
 pub struct Standard;
 
+impl Standard {
+    fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+        haystack.windows(needle.len()).rposition(|w| w == needle)
+    }
+
+    /// value of an XMP property in either attribute (`name="1"`) or element (`<name>1</name>`) form
+    fn xmp_value<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+        let (start, end) = match xmp.find(&format!("{}=\"", name)) {
+            Some(i) => (i + name.len() + 2, '"'),
+            None => (xmp.find(&format!("<{}>", name))? + name.len() + 2, '<'),
+        };
+        let rest = &xmp[start..];
+        Some(&rest[..rest.find(end)?])
+    }
+
+    // up to `len` bytes, fewer at the end of the file
+    fn read_at(file: &mut File, offset: u64, len: u64) -> std::io::Result<Vec<u8>> {
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = Vec::new();
+        file.by_ref().take(len).read_to_end(&mut buf)?;
+        Ok(buf)
+    }
+
+    fn is_mp4_at(file: &mut File, offset: u64) -> std::io::Result<bool> {
+        Ok(Self::read_at(file, offset, 8)?.get(4..8) == Some(b"ftyp"))
+    }
+
+    fn read_u32(data: &[u8], at: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?))
+    }
+
+    /// Samsung ends the file with a directory of the records it appended: "SEFH", version, count,
+    /// then per record (padding, type, distance back from "SEFH", length), followed by the directory size and "SEFT".
+    /// each record starts with (padding, type, name length, name) and the motion photo's name is the marker
+    pub fn samsung_directory_offset(file: &mut File, len: u64) -> std::io::Result<Option<u64>> {
+        let footer = Self::read_at(file, len.saturating_sub(8), 8)?;
+        if footer.get(4..8) != Some(SAMSUNG_FOOTER) {
+            return Ok(None);
+        }
+        let size = Self::read_u32(&footer, 0).unwrap_or(0) as u64;
+        let Some(directory_start) = (len - 8).checked_sub(size) else {
+            return Ok(None);
+        };
+        let directory = Self::read_at(file, directory_start, size)?;
+        if directory.get(..4) != Some(SAMSUNG_HEADER) {
+            return Ok(None);
+        }
+        let count = Self::read_u32(&directory, 8).unwrap_or(0) as usize;
+
+        for entry in directory.get(12..).unwrap_or_default().chunks_exact(12).take(count) {
+            let Some(record) = Self::read_u32(entry, 4).and_then(|back| directory_start.checked_sub(back as u64)) else {
+                continue;
+            };
+            let head = Self::read_at(file, record, 8 + SAMSUNG_MOTION_MARKER.len() as u64)?;
+            if head.get(8..) != Some(SAMSUNG_MOTION_MARKER) {
+                continue;
+            }
+            let video = record + 8 + Self::read_u32(&head, 4).unwrap_or(0) as u64;
+            if Self::is_mp4_at(file, video)? {
+                return Ok(Some(video));
+            }
+        }
+        Ok(None)
+    }
+
+    /// where the MP4 trailer of a Google or Samsung motion photo starts, `header` is the start of the file holding the XMP
+    ///
+    /// offsets are stored as u32 like file sizes, a trailer starting past 4 GiB isn't played rather than played from the wrong place
+    pub fn motion_photo_offset(path: &Path, header: &[u8]) -> Result<Option<u32>, std::io::Error> {
+        Ok(Self::motion_photo_offset_u64(path, header)?.and_then(|offset| u32::try_from(offset).ok()))
+    }
+
+    fn motion_photo_offset_u64(path: &Path, header: &[u8]) -> Result<Option<u64>, std::io::Error> {
+        let xmp = String::from_utf8_lossy(header);
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+
+        // Google measures from the end of the file, either the old MicroVideo tag or the container directory
+        let from_end = Self::xmp_value(&xmp, "GCamera:MicroVideoOffset")
+            .or_else(|| {
+                let item = xmp.find("Item:Semantic=\"MotionPhoto\"")?;
+                let item = &xmp[xmp[..item].rfind('<')?..];
+                Self::xmp_value(&item[..item.find('>')?], "Item:Length")
+            })
+            .and_then(|length| length.trim().parse::<u64>().ok());
+        if let Some(offset) = from_end.and_then(|length| len.checked_sub(length)) {
+            if Self::is_mp4_at(&mut file, offset)? {
+                return Ok(Some(offset));
+            }
+        }
+
+        // Samsung appends a marker right before the video, its directory says where
+        if let Some(offset) = Self::samsung_directory_offset(&mut file, len)? {
+            return Ok(Some(offset));
+        }
+
+        // no directory, the last marker near the end of the file
+        let trailer_start = len.saturating_sub(TRAILER_SEARCH_LENGTH);
+        let trailer = Self::read_at(&mut file, trailer_start, TRAILER_SEARCH_LENGTH)?;
+        if let Some(marker) = Self::rfind(&trailer, SAMSUNG_MOTION_MARKER) {
+            let offset = trailer_start + (marker + SAMSUNG_MOTION_MARKER.len()) as u64;
+            if Self::is_mp4_at(&mut file, offset)? {
+                return Ok(Some(offset));
+            }
+        }
+
+        Ok(None)
+    }
+}
+
 impl Format for Standard {
     type Error = StandardError;
     const FORMAT_TYPE: FormatType = FormatType::Standard;
     const EXTENSIONS: &'static [&'static str] = &["jpeg", "jpg", "png"];
-    const METADATA_VERSION: i32 = 1;
+    const METADATA_VERSION: i32 = 5;
 
     fn get_metadata(path: &Path, _: &AppConfig) -> Result<MediaMetadata, Self::Error> {
         let file_meta = path.metadata()?;
@@ -22,30 +144,50 @@ impl Format for Standard {
         let exifreader = exif::Reader::new();
 
         let exif_metadata = exifreader.read_from_container(&mut bufreader).ok().and_then(|e| extract_exif(&e).ok());
+
+        let mut header = Vec::with_capacity(XMP_SEARCH_LENGTH);
+        std::fs::File::open(path)?.take(XMP_SEARCH_LENGTH as u64).read_to_end(&mut header)?;
+
+        let ext = path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+        let motion_offset = if ext == "jpg" || ext == "jpeg" { Self::motion_photo_offset(path, &header)? } else { None };
         
         Ok(MediaMetadata {
             name: path.file_name().unwrap().to_string_lossy().to_string(),
             width,
             height,
             size: file_meta.len() as u32,
-            created_at: system_time_to_naive_datetime(file_meta.created().unwrap()),
+            created_at: exif_metadata.as_ref().and_then(|e| e.captured_at).unwrap_or_else(|| system_time_to_naive_datetime(file_meta.created().unwrap())),
             duration: None,
             longitude: exif_metadata.as_ref().and_then(|e| e.longitude),
             latitude: exif_metadata.as_ref().and_then(|e| e.latitude),
             is_screenshot: exif_metadata.as_ref().map(|e| e.is_screenshot).unwrap_or(false),
             media_type: MediaType::Photo,
+            fields: exif_metadata.as_ref().and_then(|e| e.camera.clone()).map(|camera| vec![("camera".to_string(), camera)]).unwrap_or_default(),
+            content_identifier: exif_metadata.as_ref().and_then(|e| e.content_identifier.clone()),
+            motion_offset,
+            xmp: Xmp::embedded(&header),
         })
     }
 
 
 }
 impl Thumbnailable for Standard {
-    const THUMBNAIL_VERSION: i32 = 0;
+    const THUMBNAIL_VERSION: i32 = 1;
+
+    fn decode(path: &Path, _: Option<u32>, _: &AppConfig) -> Result<RgbImage, Self::Error> {
+        let mut decoder = ImageReader::open(path)?.with_guessed_format()?.into_decoder()?;
+        let profile = match decoder.icc_profile()? {
+            Some(data) => icc::parse_profile(&data).inspect_err(|e| log::warn!("unable to read color profile of {:?}: {}", path, e)).ok(),
+            // Adobe RGB JPEGs straight from a camera usually only say so in their EXIF
+            None => {
+                let file = std::fs::File::open(path)?;
+                exif::Reader::new().read_from_container(&mut std::io::BufReader::new(&file)).ok().and_then(|e| icc::exif_profile(&e))
+            }
+        };
 
-    fn generate_thumbnail(path: &Path, width: u32, height: u32, _: &AppConfig) -> Result<RgbImage, Self::Error> {
-        let image = image::open(path)?;
-        let thumbnail = image.thumbnail(width, height);
-        Ok(thumbnail.to_rgb8())
+        let mut image = DynamicImage::from_decoder(decoder)?.to_rgb8();
+        icc::convert_to_srgb(&mut image, profile);
+        Ok(image)
     }
 
 }
@@ -0,0 +1,178 @@
+use std::path::Path;
+use std::sync::Arc;
+use image::RgbImage;
+use once_cell::sync::Lazy;
+use resvg::tiny_skia::{Color, Pixmap, Transform};
+use resvg::usvg;
+use crate::media_processors::format::{resize_dimensions, Format, FormatType, MediaMetadata, MediaType, Thumbnailable};
+use crate::models::system_time_to_naive_datetime;
+use crate::scan_config::AppConfig;
+
+pub const FULL_SIZE: u32 = 1920;
+
+// anything bigger than this is almost certainly an attempt to exhaust memory
+const MAX_FILE_SIZE: u64 = 32 * 1024 * 1024;
+const MAX_DIMENSION: f32 = 16384.0;
+const MAX_NODES: u32 = 1_000_000;
+
+// the SVG spec's fallback when neither a size nor a viewBox is given
+const DEFAULT_SIZE: (f32, f32) = (100.0, 100.0);
+
+// system fonts are only loaded once, it's slow
+static FONTS: Lazy<Arc<usvg::fontdb::Database>> = Lazy::new(|| {
+    let mut fonts = usvg::fontdb::Database::new();
+    fonts.load_system_fonts();
+    Arc::new(fonts)
+});
+
+pub struct Svg;
+
+impl Svg {
+    fn read(path: &Path) -> Result<String, SvgError> {
+        let size = path.metadata()?.len();
+        if size > MAX_FILE_SIZE {
+            return Err(SvgError::TooLarge(size));
+        }
+        Ok(std::fs::read_to_string(path)?)
+    }
+
+    /// parses a length in user units, relative lengths (%, em) aren't supported
+    fn parse_length(value: &str) -> Option<f32> {
+        let value = value.trim();
+        let (number, scale) = [("px", 1.0), ("pt", 4.0 / 3.0), ("pc", 16.0), ("mm", 96.0 / 25.4), ("cm", 96.0 / 2.54), ("in", 96.0)]
+            .iter()
+            .find_map(|(unit, scale)| value.strip_suffix(unit).map(|n| (n, *scale)))
+            .unwrap_or((value, 1.0));
+        let length = number.trim().parse::<f32>().ok()? * scale;
+        (length.is_finite() && length > 0.0).then_some(length)
+    }
+
+    fn parse_view_box(value: &str) -> Option<(f32, f32)> {
+        let parts: Vec<f32> = value
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|p| !p.is_empty())
+            .map(|p| p.parse::<f32>())
+            .collect::<Result<_, _>>()
+            .ok()?;
+        match parts[..] {
+            [_, _, w, h] if w.is_finite() && h.is_finite() && w > 0.0 && h > 0.0 => Some((w, h)),
+            _ => None,
+        }
+    }
+
+    /// the intrinsic size from the root element, width/height take precedence over the viewBox
+    pub fn dimensions(data: &str) -> Result<(f32, f32), SvgError> {
+        // DTDs are refused outright, entity expansion is the classic XML bomb
+        let document = roxmltree::Document::parse_with_options(data, roxmltree::ParsingOptions {
+            allow_dtd: false,
+            nodes_limit: MAX_NODES,
+        })?;
+        let root = document.root_element();
+        if !root.has_tag_name("svg") {
+            return Err(SvgError::NotSvg);
+        }
+
+        let width = root.attribute("width").and_then(Self::parse_length);
+        let height = root.attribute("height").and_then(Self::parse_length);
+        let view_box = root.attribute("viewBox").and_then(Self::parse_view_box);
+
+        let (width, height) = match (width, height, view_box) {
+            (Some(w), Some(h), _) => (w, h),
+            (Some(w), None, Some((vw, vh))) => (w, w * vh / vw),
+            (None, Some(h), Some((vw, vh))) => (h * vw / vh, h),
+            (None, None, Some(view_box)) => view_box,
+            (w, h, None) => (w.unwrap_or(DEFAULT_SIZE.0), h.unwrap_or(DEFAULT_SIZE.1)),
+        };
+
+        if !(width.is_finite() && height.is_finite()) || width > MAX_DIMENSION || height > MAX_DIMENSION {
+            return Err(SvgError::InvalidDimensions(width, height));
+        }
+
+        Ok((width.max(1.0), height.max(1.0)))
+    }
+
+    fn options() -> usvg::Options<'static> {
+        let mut options = usvg::Options::default();
+        // only data: URLs are allowed, never files or anything else the document points at
+        options.resources_dir = None;
+        options.image_href_resolver.resolve_string = Box::new(|_, _| None);
+        options.fontdb = FONTS.clone();
+        options
+    }
+
+    /// rasterizes the document to fit inside width x height, composited on white
+    pub fn render(path: &Path, width: u32, height: u32) -> Result<RgbImage, SvgError> {
+        let data = Self::read(path)?;
+        let (intrinsic_width, intrinsic_height) = Self::dimensions(&data)?;
+
+        let tree = usvg::Tree::from_str(&data, &Self::options())?;
+
+        let (nw, nh) = resize_dimensions(intrinsic_width.round() as u32, intrinsic_height.round() as u32, width, height, false);
+        let mut pixmap = Pixmap::new(nw, nh).ok_or(SvgError::InvalidDimensions(nw as f32, nh as f32))?;
+        pixmap.fill(Color::WHITE);
+
+        let size = tree.size();
+        let transform = Transform::from_scale(nw as f32 / size.width(), nh as f32 / size.height());
+        resvg::render(&tree, transform, &mut pixmap.as_mut());
+
+        // the background is opaque so the premultiplied data is already straight RGB
+        let rgb = pixmap.data().chunks_exact(4).flat_map(|p| [p[0], p[1], p[2]]).collect();
+        Ok(RgbImage::from_raw(nw, nh, rgb).unwrap())
+    }
+}
+
+impl Format for Svg {
+    type Error = SvgError;
+    const FORMAT_TYPE: FormatType = FormatType::Svg;
+    const EXTENSIONS: &'static [&'static str] = &["svg"];
+    const METADATA_VERSION: i32 = 0;
+
+    fn get_metadata(path: &Path, _: &AppConfig) -> Result<MediaMetadata, Self::Error> {
+        let file_meta = path.metadata()?;
+
+        let (width, height) = Self::dimensions(&Self::read(path)?)?;
+
+        Ok(MediaMetadata {
+            name: path.file_name().unwrap().to_string_lossy().to_string(),
+            width: width.round() as u32,
+            height: height.round() as u32,
+            size: file_meta.len() as u32,
+            created_at: system_time_to_naive_datetime(file_meta.created().unwrap()),
+            duration: None,
+            longitude: None,
+            latitude: None,
+            is_screenshot: false,
+            media_type: MediaType::Photo,
+            fields: Vec::new(),
+            content_identifier: None,
+            motion_offset: None,
+            xmp: None,
+        })
+    }
+}
+
+impl Thumbnailable for Svg {
+    const THUMBNAIL_VERSION: i32 = 0;
+
+    // vectors scale freely, so small icons are rendered up to a usable size
+    fn decode(path: &Path, max_size: Option<u32>, _: &AppConfig) -> Result<RgbImage, Self::Error> {
+        let size = max_size.unwrap_or(FULL_SIZE);
+        Self::render(path, size, size)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SvgError {
+    #[error("iO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("xml error: {0}")]
+    XmlError(#[from] roxmltree::Error),
+    #[error("svg error: {0}")]
+    UsvgError(#[from] usvg::Error),
+    #[error("root element is not <svg>")]
+    NotSvg,
+    #[error("file too large: {0} bytes")]
+    TooLarge(u64),
+    #[error("invalid dimensions: {0}x{1}")]
+    InvalidDimensions(f32, f32),
+}
@@ -1,4 +1,4 @@
-use ffmpeg_next::codec::Context;
+use ffmpeg_next::codec::{Context, Id as CodecId};
 use ffmpeg_next::format::Pixel;
 use ffmpeg_next::software::scaling::{context::Context as ScaleContext, flag::Flags};
 use ffmpeg_next::util::frame::video::Video as VideoFrame;
@@ -7,17 +7,47 @@ use nom_exif::{MediaParser, MediaSource, TrackInfo};
 use std::path::Path;
 use std::time::Duration;
 use crate::media_processors::exif::extract_exif_nom;
-use crate::media_processors::format::{resize_dimensions, Audioable, Format, FormatType, MediaMetadata, MediaType, Thumbnailable};
+use crate::media_processors::format::{Audioable, Format, FormatType, MediaMetadata, MediaType, Thumbnailable};
 use crate::models::system_time_to_naive_datetime;
 use crate::scan_config::AppConfig;
 
+// the Live Photo pairing id, the still has the same value in its maker note
+const CONTENT_IDENTIFIER_TAG: &str = "com.apple.quicktime.content.identifier";
+
 pub struct Video;
 
+impl Video {
+    /// whether the file can be played by browsers as is (H.264 video with AAC/MP3 audio or no audio)
+    pub fn web_playable(path: &Path) -> Result<bool, VideoError> {
+        ffmpeg_next::init().unwrap();
+        let context = ffmpeg_next::format::input(&path)?;
+        let video = context
+            .streams()
+            .best(ffmpeg_next::media::Type::Video)
+            .ok_or(VideoError::FfmpegError(ffmpeg_next::Error::StreamNotFound))?;
+        if video.parameters().id() != CodecId::H264 {
+            return Ok(false);
+        }
+        let audio = context.streams().best(ffmpeg_next::media::Type::Audio);
+        Ok(audio.map(|a| matches!(a.parameters().id(), CodecId::AAC | CodecId::MP3)).unwrap_or(true))
+    }
+
+    /// index of the first text based subtitle stream, image based ones (PGS, DVD) can't be converted to cues
+    pub fn text_subtitle_stream(path: &Path) -> Result<Option<usize>, VideoError> {
+        ffmpeg_next::init().unwrap();
+        let context = ffmpeg_next::format::input(&path)?;
+        Ok(context
+            .streams()
+            .filter(|s| s.parameters().medium() == ffmpeg_next::media::Type::Subtitle)
+            .position(|s| matches!(s.parameters().id(), CodecId::SUBRIP | CodecId::SRT | CodecId::ASS | CodecId::SSA | CodecId::WEBVTT | CodecId::MOV_TEXT | CodecId::TEXT)))
+    }
+}
+
 impl Format for Video {
     type Error = VideoError;
     const FORMAT_TYPE: FormatType = FormatType::Video;
-    const EXTENSIONS: &'static [&'static str] = &["mp4", "mov"];
-    const METADATA_VERSION: i32 = 2;
+    const EXTENSIONS: &'static [&'static str] = &["mp4", "mov", "mkv"];
+    const METADATA_VERSION: i32 = 5;
     fn get_metadata(path: &Path, _: &AppConfig) -> Result<MediaMetadata, Self::Error> {
         let file_meta = path.metadata()?;
         ffmpeg_next::init().unwrap();
@@ -41,7 +71,12 @@ impl Format for Video {
             }
         }.map(|e| extract_exif_nom(&e));
         
-        let seconds = stream.duration() as f64 * f64::from(stream.time_base());
+        // matroska only has a duration for the whole container
+        let seconds = if stream.duration() > 0 {
+            stream.duration() as f64 * f64::from(stream.time_base())
+        } else {
+            context.duration().max(0) as f64 / f64::from(ffmpeg_next::ffi::AV_TIME_BASE)
+        };
         let milliseconds = (seconds * 1000.0).round() as u64;
 
         Ok(MediaMetadata {
@@ -55,6 +90,10 @@ impl Format for Video {
             longitude: metadata.as_ref().and_then(|e| e.longitude),
             is_screenshot: metadata.as_ref().map(|e| e.is_screenshot).unwrap_or(false),
             media_type: MediaType::Video,
+            fields: Vec::new(),
+            content_identifier: context.metadata().get(CONTENT_IDENTIFIER_TAG).map(|id| id.to_string()),
+            motion_offset: None,
+            xmp: None,
         })
     }
 
@@ -62,7 +101,7 @@ impl Format for Video {
 
 impl Thumbnailable for Video {
     const THUMBNAIL_VERSION: i32 = 1;
-    fn generate_thumbnail(path: &Path, width: u32, height: u32, _: &AppConfig) -> Result<RgbImage, Self::Error> {
+    fn decode(path: &Path, _: Option<u32>, _: &AppConfig) -> Result<RgbImage, Self::Error> {
         ffmpeg_next::init().unwrap();
         let mut context = ffmpeg_next::format::input(&path)?;
         let stream = context
@@ -112,11 +151,7 @@ impl Thumbnailable for Video {
         )
             .unwrap();
 
-        let (nw, nh) = resize_dimensions(round_width, decoder.height(), width, height, false);
-
-        let thumbnail = image::imageops::thumbnail(&rgb_image, nw, nh);
-
-        Ok(thumbnail)
+        Ok(rgb_image)
     }
 }
 
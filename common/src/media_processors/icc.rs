@@ -0,0 +1,54 @@
+use exif::{Exif, In, Tag, Value};
+use image::RgbImage;
+use moxcms::{ColorProfile, DataColorSpace, Layout, TransformOptions};
+
+// EXIF ColorSpace when it's anything but sRGB
+const COLOR_SPACE_UNCALIBRATED: u32 = 0xFFFF;
+
+// DCF's interoperability index for the Adobe RGB option, cameras set it instead of embedding a profile
+const ADOBE_RGB_INTEROPERABILITY: &[u8] = b"R03";
+
+/// the color profile a JPEG's EXIF implies when it has no embedded ICC profile
+pub fn exif_profile(exif: &Exif) -> Option<ColorProfile> {
+    let uncalibrated = exif.get_field(Tag::ColorSpace, In::PRIMARY).and_then(|f| f.value.get_uint(0)) == Some(COLOR_SPACE_UNCALIBRATED);
+    let adobe_rgb = exif.get_field(Tag::InteroperabilityIndex, In::PRIMARY).is_some_and(|f| match &f.value {
+        Value::Ascii(values) => values.first().is_some_and(|v| v.as_slice() == ADOBE_RGB_INTEROPERABILITY),
+        _ => false,
+    });
+    (uncalibrated && adobe_rgb).then(ColorProfile::new_adobe_rgb)
+}
+
+pub fn parse_profile(icc: &[u8]) -> Result<ColorProfile, IccError> {
+    Ok(ColorProfile::new_from_slice(icc)?)
+}
+
+/// converts pixels in `profile` to sRGB, which is what browsers assume untagged thumbnails are in
+pub fn to_srgb(image: &mut RgbImage, profile: &ColorProfile) -> Result<(), IccError> {
+    // grey and CMYK profiles describe pixels that have already been expanded to RGB by the decoder
+    if profile.color_space != DataColorSpace::Rgb {
+        return Err(IccError::UnsupportedColorSpace(profile.color_space));
+    }
+
+    let transform = profile.create_transform_8bit(Layout::Rgb, &ColorProfile::new_srgb(), Layout::Rgb, TransformOptions::default())?;
+    let mut srgb = vec![0; image.as_raw().len()];
+    transform.transform(image.as_raw(), &mut srgb)?;
+    *image = RgbImage::from_raw(image.width(), image.height(), srgb).unwrap();
+    Ok(())
+}
+
+/// best effort, a broken or exotic profile leaves the pixels as they were rather than failing the thumbnail
+pub fn convert_to_srgb(image: &mut RgbImage, profile: Option<ColorProfile>) {
+    if let Some(profile) = profile {
+        if let Err(e) = to_srgb(image, &profile) {
+            log::warn!("unable to convert thumbnail to sRGB: {}", e);
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum IccError {
+    #[error("color profile error: {0}")]
+    CmsError(#[from] moxcms::CmsError),
+    #[error("unsupported color space: {0:?}")]
+    UnsupportedColorSpace(DataColorSpace),
+}
@@ -0,0 +1,205 @@
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, NaiveDateTime};
+use serde_json::Value;
+
+// Takeout cuts the sidecar's name (without ".json") down to this many characters
+const TAKEOUT_MAX_NAME_LENGTH: usize = 46;
+
+// newer exports name them photo.jpg.supplemental-metadata.json, truncated like everything else
+const TAKEOUT_SUPPLEMENTAL: &str = ".supplemental-metadata";
+
+// Google Photos saves edits next to the original, both share the original's sidecar
+const EDITED_SUFFIXES: &[&str] = &["-edited", "-bearbeitet", "-modifié", "-editado", "-modificato"];
+
+/// what a Google Takeout or exported (exiftool style, e.g. osxphotos) JSON sidecar knows about a file
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct JsonSidecar {
+    /// UTC
+    pub taken_at: Option<NaiveDateTime>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub description: Option<String>,
+    /// the name of the file it was written for
+    pub file_name: Option<String>,
+}
+
+impl JsonSidecar {
+    pub fn parse(json: &str) -> Result<Self, JsonSidecarError> {
+        match serde_json::from_str::<Value>(json)? {
+            // exiftool -j writes an array with one object per file
+            Value::Array(files) => match files.into_iter().next() {
+                Some(Value::Object(file)) => Ok(Self::parse_exiftool(&Value::Object(file))),
+                _ => Err(JsonSidecarError::Unrecognized),
+            },
+            value @ Value::Object(_) if value.get("photoTakenTime").is_some() || value.get("geoData").is_some() => Ok(Self::parse_takeout(&value)),
+            _ => Err(JsonSidecarError::Unrecognized),
+        }
+    }
+
+    fn parse_takeout(value: &Value) -> Self {
+        let timestamp = |key: &str| {
+            let timestamp = value.get(key)?.get("timestamp")?;
+            // it's a string of seconds, but be lenient
+            let seconds = timestamp.as_str().and_then(|s| s.parse::<i64>().ok()).or_else(|| timestamp.as_i64())?;
+            DateTime::from_timestamp(seconds, 0).map(|d| d.naive_utc())
+        };
+        // 0,0 means there's no location
+        let location = |key: &str| {
+            let geo = value.get(key)?;
+            let latitude = geo.get("latitude")?.as_f64()?;
+            let longitude = geo.get("longitude")?.as_f64()?;
+            (latitude != 0.0 || longitude != 0.0).then_some((latitude, longitude))
+        };
+        let location = location("geoData").or_else(|| location("geoDataExif"));
+
+        Self {
+            taken_at: timestamp("photoTakenTime").or_else(|| timestamp("creationTime")),
+            latitude: location.map(|(latitude, _)| latitude),
+            longitude: location.map(|(_, longitude)| longitude),
+            description: value.get("description").and_then(Value::as_str).map(|d| d.trim().to_string()).filter(|d| !d.is_empty()),
+            file_name: value.get("title").and_then(Value::as_str).map(|t| t.to_string()),
+        }
+    }
+
+    fn parse_exiftool(value: &Value) -> Self {
+        // keys are "DateTimeOriginal" or "EXIF:DateTimeOriginal" depending on -G
+        let field = |name: &str| {
+            value.as_object()?.iter().find_map(|(key, v)| (key.rsplit(':').next() == Some(name)).then_some(v))
+        };
+        let text = |name: &str| field(name).and_then(Value::as_str).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        // only numeric coordinates (exiftool -n) are understood
+        let coordinate = |name: &str| field(name).and_then(|v| v.as_f64().or_else(|| v.as_str()?.trim().parse().ok()));
+
+        let taken_at = text("DateTimeOriginal").or_else(|| text("CreateDate")).and_then(|d| parse_exif_date(&d));
+
+        Self {
+            taken_at,
+            latitude: coordinate("GPSLatitude"),
+            longitude: coordinate("GPSLongitude"),
+            description: text("Description").or_else(|| text("ImageDescription")).or_else(|| text("Caption-Abstract")),
+            file_name: text("FileName").or_else(|| text("SourceFile").and_then(|f| Some(Path::new(&f).file_name()?.to_string_lossy().to_string()))),
+        }
+    }
+}
+
+// "2020:09:13 12:26:40", optionally with subseconds and an offset, converted to UTC when there's an offset
+fn parse_exif_date(date: &str) -> Option<NaiveDateTime> {
+    if let Ok(date) = DateTime::parse_from_str(date, "%Y:%m:%d %H:%M:%S%.f%:z") {
+        return Some(date.naive_utc());
+    }
+    NaiveDateTime::parse_from_str(date, "%Y:%m:%d %H:%M:%S%.f").ok()
+}
+
+fn truncate(name: &str, length: usize) -> &str {
+    match name.char_indices().nth(length) {
+        Some((i, _)) => &name[..i],
+        None => name,
+    }
+}
+
+// "photo-edited(1).jpg" -> (["photo-edited", "photo"], "(1)", ".jpg")
+fn split_name(name: &str) -> (Vec<&str>, &str, &str) {
+    let (stem, extension) = match name.rfind('.') {
+        Some(i) if i > 0 => (&name[..i], &name[i..]),
+        _ => (name, ""),
+    };
+
+    // "photo(1)" -> ("photo", "(1)")
+    let (stem, counter) = match stem.strip_suffix(')').and_then(|s| s.rfind('(').map(|i| (i, s))) {
+        Some((i, s)) if i + 1 < s.len() && s[i + 1..].chars().all(|c| c.is_ascii_digit()) => (&stem[..i], &stem[i..]),
+        _ => (stem, ""),
+    };
+
+    let mut stems = vec![stem];
+    for suffix in EDITED_SUFFIXES {
+        if let Some(original) = stem.strip_suffix(suffix) {
+            stems.push(original);
+        }
+    }
+    (stems, counter, extension)
+}
+
+/// the names a sidecar for `name` might have, most likely first
+///
+/// Takeout truncates the sidecar name to 46 characters, moves a duplicate counter to the end
+/// (photo(1).jpg -> photo.jpg(1).json) and shares one sidecar between an original and its edit
+pub fn sidecar_names(name: &str) -> Vec<String> {
+    let (stems, counter, extension) = split_name(name);
+
+    let mut names = Vec::new();
+    let mut push = |name: String| {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    };
+    for stem in stems {
+        for supplemental in ["", TAKEOUT_SUPPLEMENTAL] {
+            let full = format!("{}{}{}", stem, extension, supplemental);
+            push(format!("{}{}.json", truncate(&full, TAKEOUT_MAX_NAME_LENGTH), counter));
+            // the counter isn't always moved
+            if !counter.is_empty() {
+                let full = format!("{}{}{}{}", stem, counter, extension, supplemental);
+                push(format!("{}.json", truncate(&full, TAKEOUT_MAX_NAME_LENGTH)));
+            }
+        }
+    }
+    names
+}
+
+/// some exports drop the media extension entirely, these are only used when the sidecar says which file it's for
+pub fn extensionless_sidecar_names(name: &str) -> Vec<String> {
+    let (stems, counter, _) = split_name(name);
+    let mut names: Vec<String> = Vec::new();
+    for stem in stems {
+        let name = format!("{}{}.json", stem, counter);
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+// IMG_1234.json could be the HEIC's or the MOV's of a Live Photo
+fn written_for_extension(sidecar: &Path, extension: &str) -> bool {
+    let Ok(json) = std::fs::read_to_string(sidecar) else {
+        return false;
+    };
+    let Ok(parsed) = JsonSidecar::parse(&json) else {
+        return false;
+    };
+    parsed.file_name
+        .as_deref()
+        .and_then(|name| Path::new(name).extension())
+        .is_some_and(|e| e.to_string_lossy().eq_ignore_ascii_case(extension))
+}
+
+/// an existing JSON sidecar next to `path`
+pub fn sidecar_path(path: &Path) -> Option<PathBuf> {
+    let name = path.file_name()?.to_string_lossy();
+    let parent = path.parent()?;
+    if let Some(sidecar) = sidecar_names(&name).into_iter().map(|n| parent.join(n)).find(|p| p.is_file()) {
+        return Some(sidecar);
+    }
+    let extension = path.extension()?.to_string_lossy();
+    extensionless_sidecar_names(&name)
+        .into_iter()
+        .map(|n| parent.join(n))
+        .find(|p| p.is_file() && written_for_extension(p, &extension))
+}
+
+pub fn read_sidecar(path: &Path) -> Result<Option<JsonSidecar>, JsonSidecarError> {
+    let Some(sidecar) = sidecar_path(path) else {
+        return Ok(None);
+    };
+    Ok(Some(JsonSidecar::parse(&std::fs::read_to_string(sidecar)?)?))
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum JsonSidecarError {
+    #[error("iO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("json error: {0}")]
+    JsonError(#[from] serde_json::Error),
+    #[error("not a Takeout or exiftool sidecar")]
+    Unrecognized,
+}
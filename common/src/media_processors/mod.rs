@@ -1,4 +1,9 @@
 pub mod format;
 pub mod exif;
+pub mod xmp;
+pub mod json_sidecar;
+pub mod icc;
+pub mod blurhash;
+pub mod palette;
 
 pub use image::RgbImage;
\ No newline at end of file
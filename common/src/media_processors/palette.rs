@@ -0,0 +1,179 @@
+use std::fmt;
+use std::str::FromStr;
+use image::RgbImage;
+
+// colors covering less of the image than this aren't dominant
+const MIN_SHARE: f32 = 0.05;
+const ITERATIONS: usize = 10;
+
+pub const PALETTE_SIZE: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// CIELAB (D65), euclidean distances in it are roughly how different two colors look
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Lab {
+    pub l: f32,
+    pub a: f32,
+    pub b: f32,
+}
+
+// sRGB D65 white point
+const WHITE: [f32; 3] = [0.95047, 1.0, 1.08883];
+
+fn lab_f(t: f32) -> f32 {
+    if t > 216.0 / 24389.0 {
+        t.cbrt()
+    } else {
+        (24389.0 / 27.0 * t + 16.0) / 116.0
+    }
+}
+
+fn lab_f_inverse(t: f32) -> f32 {
+    if t.powi(3) > 216.0 / 24389.0 {
+        t.powi(3)
+    } else {
+        (116.0 * t - 16.0) / (24389.0 / 27.0)
+    }
+}
+
+fn to_linear(v: u8) -> f32 {
+    let v = v as f32 / 255.0;
+    if v <= 0.04045 { v / 12.92 } else { ((v + 0.055) / 1.055).powf(2.4) }
+}
+
+fn from_linear(v: f32) -> u8 {
+    let v = v.clamp(0.0, 1.0);
+    let v = if v <= 0.0031308 { v * 12.92 } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 };
+    (v * 255.0).round() as u8
+}
+
+impl Color {
+    pub fn to_lab(self) -> Lab {
+        let (r, g, b) = (to_linear(self.r), to_linear(self.g), to_linear(self.b));
+        let x = 0.4124564 * r + 0.3575761 * g + 0.1804375 * b;
+        let y = 0.2126729 * r + 0.7151522 * g + 0.0721750 * b;
+        let z = 0.0193339 * r + 0.1191920 * g + 0.9503041 * b;
+        let (fx, fy, fz) = (lab_f(x / WHITE[0]), lab_f(y / WHITE[1]), lab_f(z / WHITE[2]));
+        Lab {
+            l: 116.0 * fy - 16.0,
+            a: 500.0 * (fx - fy),
+            b: 200.0 * (fy - fz),
+        }
+    }
+}
+
+impl Lab {
+    pub fn to_color(self) -> Color {
+        let fy = (self.l + 16.0) / 116.0;
+        let fx = fy + self.a / 500.0;
+        let fz = fy - self.b / 200.0;
+        let (x, y, z) = (lab_f_inverse(fx) * WHITE[0], lab_f_inverse(fy) * WHITE[1], lab_f_inverse(fz) * WHITE[2]);
+        Color {
+            r: from_linear(3.2404542 * x - 1.5371385 * y - 0.4985314 * z),
+            g: from_linear(-0.9692660 * x + 1.8760108 * y + 0.0415560 * z),
+            b: from_linear(0.0556434 * x - 0.2040259 * y + 1.0572252 * z),
+        }
+    }
+
+    pub fn distance_squared(&self, other: &Lab) -> f32 {
+        (self.l - other.l).powi(2) + (self.a - other.a).powi(2) + (self.b - other.b).powi(2)
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+/// "#ff8800", "ff8800" or "f80"
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let hex = s.trim().trim_start_matches('#');
+        let channel = |i: usize, len: usize| {
+            let v = u8::from_str_radix(hex.get(i * len..(i + 1) * len)?, 16).ok()?;
+            Some(if len == 1 { v * 17 } else { v })
+        };
+        let len = match hex.len() {
+            3 => 1,
+            6 => 2,
+            _ => return Err(format!("invalid color format: {}", s)),
+        };
+        match (channel(0, len), channel(1, len), channel(2, len)) {
+            (Some(r), Some(g), Some(b)) => Ok(Color { r, g, b }),
+            _ => Err(format!("invalid color format: {}", s)),
+        }
+    }
+}
+
+/// the most dominant colors first, with the share of the image each covers
+///
+/// k-means in Lab, seeded with the mean and then whichever pixel is furthest from every center so far,
+/// so the same image always gives the same palette. pass a thumbnail, every pixel is visited per iteration
+pub fn dominant_colors(image: &RgbImage, count: usize) -> Vec<(Color, f32)> {
+    let pixels: Vec<Lab> = image.pixels().map(|p| Color { r: p[0], g: p[1], b: p[2] }.to_lab()).collect();
+    if pixels.is_empty() || count == 0 {
+        return Vec::new();
+    }
+
+    let mean = |cluster: &[&Lab]| {
+        let n = cluster.len() as f32;
+        Lab {
+            l: cluster.iter().map(|p| p.l).sum::<f32>() / n,
+            a: cluster.iter().map(|p| p.a).sum::<f32>() / n,
+            b: cluster.iter().map(|p| p.b).sum::<f32>() / n,
+        }
+    };
+    let nearest = |centers: &[Lab], pixel: &Lab| {
+        centers
+            .iter()
+            .enumerate()
+            .map(|(i, c)| (i, c.distance_squared(pixel)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap()
+    };
+
+    let mut centers = vec![mean(&pixels.iter().collect::<Vec<_>>())];
+    while centers.len() < count {
+        let (furthest, distance) = pixels
+            .iter()
+            .map(|p| (p, nearest(&centers, p).1))
+            .max_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap();
+        // fewer distinct colors than requested
+        if distance == 0.0 {
+            break;
+        }
+        centers.push(*furthest);
+    }
+
+    let mut assignments = vec![0; pixels.len()];
+    for _ in 0..ITERATIONS {
+        for (assignment, pixel) in assignments.iter_mut().zip(&pixels) {
+            *assignment = nearest(&centers, pixel).0;
+        }
+        for (i, center) in centers.iter_mut().enumerate() {
+            let cluster: Vec<&Lab> = pixels.iter().zip(&assignments).filter(|(_, a)| **a == i).map(|(p, _)| p).collect();
+            if !cluster.is_empty() {
+                *center = mean(&cluster);
+            }
+        }
+    }
+
+    let mut palette: Vec<(Color, f32)> = centers
+        .iter()
+        .enumerate()
+        .map(|(i, center)| (center.to_color(), assignments.iter().filter(|a| **a == i).count() as f32 / pixels.len() as f32))
+        .filter(|(_, share)| *share >= MIN_SHARE)
+        .collect();
+    palette.sort_by(|a, b| b.1.total_cmp(&a.1));
+    palette
+}
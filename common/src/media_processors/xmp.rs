@@ -0,0 +1,291 @@
+use std::path::{Path, PathBuf};
+
+const NS_RDF: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
+const NS_DC: &str = "http://purl.org/dc/elements/1.1/";
+const NS_XMP: &str = "http://ns.adobe.com/xap/1.0/";
+const NS_LR: &str = "http://ns.adobe.com/lightroom/1.0/";
+const NS_DIGIKAM: &str = "http://www.digikam.org/ns/1.0/";
+const NS_EXIF: &str = "http://ns.adobe.com/exif/1.0/";
+
+const XMP_START: &[u8] = b"<x:xmpmeta";
+const XMP_END: &[u8] = b"</x:xmpmeta>";
+
+// hierarchical keywords under this root are ours (albums), they aren't imported as tags
+const KALEIDOSCOPE_ROOT: &str = "Kaleidoscope";
+const ALBUMS_ROOT: &str = "Kaleidoscope|Albums";
+
+const EMPTY_SIDECAR: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="">
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+/// the parts of XMP we care about, from a sidecar or embedded in the file
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Xmp {
+    /// -1 (rejected) to 5
+    pub rating: Option<i32>,
+    /// color label, e.g. "Red"
+    pub label: Option<String>,
+    /// already sanitized into valid tag names
+    pub keywords: Vec<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+impl Xmp {
+    pub fn parse(xmp: &str) -> Result<Self, XmpError> {
+        let document = roxmltree::Document::parse_with_options(xmp, roxmltree::ParsingOptions {
+            allow_dtd: false,
+            ..Default::default()
+        })?;
+
+        // properties are either attributes on rdf:Description or child elements
+        let property = |ns: &str, name: &str| {
+            document.descendants().find_map(|n| {
+                if n.has_tag_name((NS_RDF, "Description")) {
+                    n.attribute((ns, name)).map(|v| v.trim().to_string())
+                } else if n.has_tag_name((ns, name)) {
+                    n.text().map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+                } else {
+                    None
+                }
+            })
+        };
+        let list = |ns: &str, name: &str| {
+            document
+                .descendants()
+                .filter(|n| n.has_tag_name((ns, name)))
+                .flat_map(|n| n.descendants().filter(|li| li.has_tag_name((NS_RDF, "li"))))
+                .filter_map(|li| li.text())
+                .map(|v| v.trim().to_string())
+                .filter(|v| !v.is_empty())
+                .collect::<Vec<_>>()
+        };
+
+        let mut keywords = list(NS_DC, "subject");
+        // hierarchies only contribute their leaf, digiKam separates with / and Lightroom with |
+        let hierarchical = list(NS_LR, "hierarchicalSubject")
+            .into_iter()
+            .map(|k| k.replace('/', "|"))
+            .chain(list(NS_DIGIKAM, "TagsList").into_iter().map(|k| k.replace('/', "|")));
+        for keyword in hierarchical {
+            if keyword.split('|').next() == Some(KALEIDOSCOPE_ROOT) {
+                continue;
+            }
+            if let Some(leaf) = keyword.rsplit('|').next() {
+                keywords.push(leaf.to_string());
+            }
+        }
+        let mut tags: Vec<String> = Vec::new();
+        for tag in keywords.iter().filter_map(|k| sanitize_tag(k)) {
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+
+        Ok(Self {
+            rating: property(NS_XMP, "Rating").and_then(|r| r.parse::<f32>().ok()).map(|r| r.round() as i32),
+            label: property(NS_XMP, "Label").filter(|l| !l.is_empty()),
+            keywords: tags,
+            latitude: property(NS_EXIF, "GPSLatitude").and_then(|l| parse_coordinate(&l)),
+            longitude: property(NS_EXIF, "GPSLongitude").and_then(|l| parse_coordinate(&l)),
+        })
+    }
+
+    /// finds an XMP packet embedded in a file (JPEG APP1, PNG iTXt, HEIC mime item)
+    pub fn embedded(data: &[u8]) -> Option<Self> {
+        let start = data.windows(XMP_START.len()).position(|w| w == XMP_START)?;
+        let end = data[start..].windows(XMP_END.len()).position(|w| w == XMP_END)? + start + XMP_END.len();
+        let packet = std::str::from_utf8(&data[start..end]).ok()?;
+        Self::parse(packet).ok()
+    }
+
+    /// `self` wins wherever both have a value, keywords are combined
+    pub fn merge(mut self, other: Xmp) -> Xmp {
+        self.rating = self.rating.or(other.rating);
+        self.label = self.label.or(other.label);
+        self.latitude = self.latitude.or(other.latitude);
+        self.longitude = self.longitude.or(other.longitude);
+        for keyword in other.keywords {
+            if !self.keywords.contains(&keyword) {
+                self.keywords.push(keyword);
+            }
+        }
+        self
+    }
+}
+
+/// tags are restricted to [A-Za-z0-9_]
+pub fn sanitize_tag(keyword: &str) -> Option<String> {
+    let tag: String = keyword.trim().chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+    let tag = tag.trim_matches('_').to_string();
+    (!tag.is_empty()).then_some(tag)
+}
+
+// XMP GPS is "DDD,MM.mmmmR" or "DDD,MM,SSR"
+fn parse_coordinate(value: &str) -> Option<f64> {
+    let value = value.trim();
+    let direction = value.chars().last()?;
+    let parts: Vec<f64> = value[..value.len() - direction.len_utf8()]
+        .split(',')
+        .map(|p| p.trim().parse::<f64>())
+        .collect::<Result<_, _>>()
+        .ok()?;
+    let coordinate = match parts[..] {
+        [degrees, minutes] => degrees + minutes / 60.0,
+        [degrees, minutes, seconds] => degrees + minutes / 60.0 + seconds / 3600.0,
+        _ => return None,
+    };
+    match direction {
+        'N' | 'E' => Some(coordinate),
+        'S' | 'W' => Some(-coordinate),
+        _ => None,
+    }
+}
+
+/// an existing sidecar, darktable/digiKam name them IMG_1234.JPG.xmp and Lightroom IMG_1234.xmp
+pub fn sidecar_path(path: &Path) -> Option<PathBuf> {
+    let mut candidates = Vec::new();
+    for ext in ["xmp", "XMP"] {
+        candidates.push(PathBuf::from(format!("{}.{}", path.to_string_lossy(), ext)));
+        candidates.push(path.with_extension(ext));
+    }
+    candidates.into_iter().find(|p| p.is_file())
+}
+
+pub fn read_sidecar(path: &Path) -> Result<Option<Xmp>, XmpError> {
+    let Some(sidecar) = sidecar_path(path) else {
+        return Ok(None);
+    };
+    Ok(Some(Xmp::parse(&std::fs::read_to_string(sidecar)?)?))
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn bag(element: &str, container: &str, values: &[String]) -> String {
+    let mut out = format!("   <{}>\n    <rdf:{}>\n", element, container);
+    for value in values {
+        out.push_str(&format!("     <rdf:li>{}</rdf:li>\n", escape(value)));
+    }
+    out.push_str(&format!("    </rdf:{}>\n   </{}>\n", container, element));
+    out
+}
+
+// drops every <element>...</element> block
+fn remove_element(xmp: &mut String, element: &str) {
+    let open = format!("<{}>", element);
+    let close = format!("</{}>", element);
+    while let Some(start) = xmp.find(&open) {
+        let Some(end) = xmp[start..].find(&close) else {
+            break;
+        };
+        let mut end = start + end + close.len();
+        // take the rest of the line with it
+        if xmp[end..].starts_with('\n') {
+            end += 1;
+        }
+        let line_start = xmp[..start].rfind('\n').map(|i| i + 1).unwrap_or(start);
+        let start = if xmp[line_start..start].trim().is_empty() { line_start } else { start };
+        xmp.replace_range(start..end, "");
+    }
+}
+
+fn items(document: &roxmltree::Document, ns: &str, name: &str) -> Vec<String> {
+    document
+        .descendants()
+        .filter(|n| n.has_tag_name((ns, name)))
+        .flat_map(|n| n.descendants().filter(|li| li.has_tag_name((NS_RDF, "li"))))
+        .filter_map(|li| li.text().map(|t| t.trim().to_string()))
+        .filter(|t| !t.is_empty())
+        .collect()
+}
+
+/// `xmp` with tags (dc:subject) and albums (lr:hierarchicalSubject under Kaleidoscope|Albums) written into it.
+/// keywords and hierarchies from other tools stay as they were written while the tag they map to is still there,
+/// keywords that can't be a tag at all are always kept, everything else in the document is left alone
+pub fn update_sidecar(xmp: &str, tags: &[String], albums: &[String]) -> Result<String, XmpError> {
+    let existing = roxmltree::Document::parse_with_options(xmp, roxmltree::ParsingOptions {
+        allow_dtd: false,
+        ..Default::default()
+    })?;
+    let kept = |keyword: &str| sanitize_tag(keyword).map(|tag| tags.contains(&tag)).unwrap_or(true);
+
+    let mut subjects: Vec<String> = items(&existing, NS_DC, "subject").into_iter().filter(|k| kept(k)).collect();
+    for tag in tags {
+        if !subjects.iter().any(|k| sanitize_tag(k).as_ref() == Some(tag)) {
+            subjects.push(tag.clone());
+        }
+    }
+
+    let mut hierarchical: Vec<String> = items(&existing, NS_LR, "hierarchicalSubject")
+        .into_iter()
+        .filter(|k| k.split('|').next() != Some(KALEIDOSCOPE_ROOT))
+        .filter(|k| k.rsplit('|').next().map(kept).unwrap_or(false))
+        .collect();
+    drop(existing);
+    hierarchical.extend(albums.iter().map(|album| format!("{}|{}", ALBUMS_ROOT, album.replace('|', "/"))));
+
+    let mut xmp = xmp.to_string();
+    remove_element(&mut xmp, "dc:subject");
+    remove_element(&mut xmp, "lr:hierarchicalSubject");
+
+    let description = xmp.find("<rdf:Description").ok_or(XmpError::InvalidSidecar("missing rdf:Description"))?;
+    let tag_end = description + xmp[description..].find('>').ok_or(XmpError::InvalidSidecar("unterminated rdf:Description"))?;
+
+    // make sure the prefixes we write are declared
+    let mut declarations = String::new();
+    for (prefix, ns) in [("dc", NS_DC), ("lr", NS_LR)] {
+        if !xmp.contains(&format!("xmlns:{}=", prefix)) {
+            declarations.push_str(&format!(" xmlns:{}=\"{}\"", prefix, ns));
+        }
+    }
+
+    let mut body = String::from("\n");
+    if !subjects.is_empty() {
+        body.push_str(&bag("dc:subject", "Bag", &subjects));
+    }
+    if !hierarchical.is_empty() {
+        body.push_str(&bag("lr:hierarchicalSubject", "Bag", &hierarchical));
+    }
+
+    if xmp[..tag_end].ends_with('/') {
+        // self closing description, open it up
+        xmp.replace_range(tag_end - 1..tag_end + 1, &format!("{}>{}  </rdf:Description>", declarations, body));
+    } else {
+        let body = body.trim_end_matches('\n');
+        xmp.insert_str(tag_end + 1, body);
+        xmp.insert_str(tag_end, &declarations);
+    }
+
+    Ok(xmp)
+}
+
+/// writes tags and albums into the sidecar (see `update_sidecar`), creating one next to the file if there isn't one.
+/// a sidecar shared by a RAW+JPEG pair should get the tags and albums of both
+pub fn write_sidecar(path: &Path, tags: &[String], albums: &[String]) -> Result<PathBuf, XmpError> {
+    let sidecar = sidecar_path(path).unwrap_or_else(|| PathBuf::from(format!("{}.xmp", path.to_string_lossy())));
+    let xmp = if sidecar.is_file() { std::fs::read_to_string(&sidecar)? } else { EMPTY_SIDECAR.to_string() };
+    let xmp = update_sidecar(&xmp, tags, albums)?;
+
+    // written next to the sidecar first so a crash never leaves half a file behind
+    let temp = sidecar.with_extension("xmp.tmp");
+    std::fs::write(&temp, xmp)?;
+    std::fs::rename(&temp, &sidecar)?;
+
+    Ok(sidecar)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum XmpError {
+    #[error("iO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("xml error: {0}")]
+    XmlError(#[from] roxmltree::Error),
+    #[error("invalid sidecar: {0}")]
+    InvalidSidecar(&'static str),
+}
@@ -12,6 +12,7 @@ use chrono::{NaiveDate, NaiveTime, TimeZone};
 use toml::Table;
 use uuid::Uuid;
 use crate::media_query::macros::DSLType;
+use crate::media_processors::palette::Color;
 
 // NOTE! make sure longer ops come first
 
@@ -69,6 +70,13 @@ dsl_types! {
                 Ok(x.parse().map_err(|_| format!("invalid uuid format: {}", x))?)
             }
         };
+        color(DSLColor, Color) {
+            // near, colors never match exactly
+            Equal = "=",
+            |x| {
+                x.parse::<Color>()
+            }
+        };
 }
 
 impl DSLBool {
@@ -121,6 +129,42 @@ impl DSLString {
             DSLString::NotLike => "NOT LIKE",
         }
     }
+
+    /// the same comparison sqlite makes, for text that isn't a column like a transcript segment
+    pub fn matches(&self, value: &str, text: &str) -> bool {
+        match self {
+            DSLString::NotEqual => value != text,
+            DSLString::Equal => value == text,
+            DSLString::Like => like(value, text),
+            DSLString::NotLike => !like(value, text),
+        }
+    }
+}
+
+// sqlite's LIKE, % is any run of characters, _ is one, case insensitive for ascii only
+fn like(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let text: Vec<char> = text.chars().map(|c| c.to_ascii_lowercase()).collect();
+    let (mut p, mut t) = (0, 0);
+    // the last % seen and where in the text it stopped covering
+    let mut wildcard: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == '%' {
+            wildcard = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '_' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if let Some((wp, wt)) = wildcard {
+            // let the % cover one more character and try again
+            wildcard = Some((wp, wt + 1));
+            p = wp + 1;
+            t = wt + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '%')
 }
 
 impl DSLDate {
@@ -151,14 +195,26 @@ query_dsl! {
         latitude(float, Latitude, []),
         transcript(string, Transcript, [MediaExtra,]),
         vision_ocr(string, VisionOcr, [MediaExtra,]),
-        full_search(string, FullSearch, [MediaExtra, CustomMetadata,]),
+        subtitles(string, Subtitles, [MediaExtra,]),
+        metadata(string, Metadata, [MediaMetadata,]),
+        full_search(string, FullSearch, [MediaExtra, CustomMetadata, MediaMetadata,]),
         album_uuid(uuid, AlbumUuid, [AlbumAll,]),
         tag(string, Tag, [MediaTag,]),
         has_thumbnail(bool, HasThumbnail, []),
+        is_companion(bool, IsCompanion, []),
+        collapse_stacks(bool, CollapseStacks, []),
+        rating(integer, Rating, []),
+        label(string, Label, []),
+        color(color, Color, []),
+        country(string, Country, []),
+        city(string, City, []),
     }
 }
 
-const FULL_SEARCH_QUERIES: [&'static str; 3] = ["media.name", "media_extra.whisper_transcript", "media_extra.vision_ocr_result"];
+// how far (CIE76 delta E) a palette color can be from the one searched for, ~2 is barely noticeable
+const COLOR_DISTANCE: f32 = 20.0;
+
+const FULL_SEARCH_QUERIES: [&'static str; 5] = ["media.name", "media_extra.whisper_transcript", "media_extra.vision_ocr_result", "media_extra.subtitles", "media_metadata.value"];
 
 #[derive(PartialEq, Debug, Hash, Eq)]
 pub enum JoinableTable {
@@ -166,6 +222,7 @@ pub enum JoinableTable {
     AlbumAll,
     MediaTag,
     CustomMetadata,
+    MediaMetadata,
 }
 
 impl JoinableTable {
@@ -175,6 +232,7 @@ impl JoinableTable {
             JoinableTable::AlbumAll => " LEFT JOIN album_media ON media.id = album_media.media_id INNER JOIN album ON album_media.album_id = album.id ",
             JoinableTable::MediaTag => " LEFT JOIN media_tag ON media.id = media_tag.media_id ",
             JoinableTable::CustomMetadata => " LEFT JOIN custom_metadata ON media.id = custom_metadata.media_id ",
+            JoinableTable::MediaMetadata => " LEFT JOIN media_metadata ON media.id = media_metadata.media_id ",
         }
     }
 }
@@ -186,6 +244,33 @@ impl MediaQuery {
         }
     }
 
+    /// (op, value) of every full_search filter
+    pub fn full_searches(&self) -> impl Iterator<Item = (&DSLString, &str)> {
+        self.filters.iter().filter_map(|f| match f {
+            MediaQueryType::FullSearch(op, search) => Some((op, search.as_str())),
+            _ => None,
+        })
+    }
+
+    /// for listings, the video half of a Live Photo is only shown alongside its still unless asked for
+    pub fn without_companions(&self) -> Self {
+        let mut query = self.clone();
+        if !query.filters.iter().any(|f| matches!(f, MediaQueryType::IsCompanion(..))) {
+            // order_by, asc, limit and page have to stay last
+            let index = query.filters
+                .iter()
+                .position(|f| matches!(f, MediaQueryType::OrderBy(..) | MediaQueryType::Asc(..) | MediaQueryType::Limit(..) | MediaQueryType::Page(..)))
+                .unwrap_or(query.filters.len());
+            query.filters.insert(index, MediaQueryType::IsCompanion(DSLBool::Equal, false));
+        }
+        query
+    }
+
+    /// whether it only matches a page of the results
+    pub fn is_paged(&self) -> bool {
+        self.filters.iter().any(|f| matches!(f, MediaQueryType::Limit(..) | MediaQueryType::Page(..)))
+    }
+
     pub fn to_count_query(&self) -> Self {
         Self {
             filters: self.filters.iter().filter(|f| {
@@ -381,6 +466,16 @@ impl MediaQuery {
                         .push(op.to_sql_string())
                         .push_bind(search.clone());
                 }
+                MediaQueryType::Subtitles(op, search) => {
+                    query.push(" AND media_extra.subtitles ")
+                        .push(op.to_sql_string())
+                        .push_bind(search.clone());
+                }
+                MediaQueryType::Metadata(op, search) => {
+                    query.push(" AND media_metadata.value ")
+                        .push(op.to_sql_string())
+                        .push_bind(search.clone());
+                }
                 MediaQueryType::AlbumUuid(op, album_uuid) => {
                     query.push(" AND album.uuid ")
                         .push(op.to_sql_string())
@@ -391,6 +486,60 @@ impl MediaQuery {
                     .push(op.to_sql_string())
                     .push_bind(thumbnail.clone());
                 }
+                MediaQueryType::IsCompanion(_, companion) => {
+                    query
+                        .push(" AND media.companion_of IS ")
+                        .push(if *companion { "NOT " } else { "" })
+                        .push("NULL");
+                }
+                MediaQueryType::Rating(op, rating) => {
+                    query
+                        .push(" AND media.rating ")
+                        .push(op.to_sql_string())
+                        .push_bind(rating.clone());
+                }
+                MediaQueryType::Label(op, label) => {
+                    query
+                        .push(" AND media.label ")
+                        .push(op.to_sql_string())
+                        .push_bind(label.clone());
+                }
+                MediaQueryType::Country(op, country) => {
+                    query
+                        .push(" AND media.country ")
+                        .push(op.to_sql_string())
+                        .push_bind(country.clone());
+                }
+                MediaQueryType::City(op, city) => {
+                    query
+                        .push(" AND media.city ")
+                        .push(op.to_sql_string())
+                        .push_bind(city.clone());
+                }
+                MediaQueryType::Color(_, color) => {
+                    let lab = color.to_lab();
+                    query.push(" AND media.id IN (SELECT media_id FROM media_color WHERE (l - ");
+                    query.push_bind(lab.l);
+                    query.push(") * (l - ");
+                    query.push_bind(lab.l);
+                    query.push(") + (a - ");
+                    query.push_bind(lab.a);
+                    query.push(") * (a - ");
+                    query.push_bind(lab.a);
+                    query.push(") + (b - ");
+                    query.push_bind(lab.b);
+                    query.push(") * (b - ");
+                    query.push_bind(lab.b);
+                    query.push(") <= ");
+                    query.push_bind(COLOR_DISTANCE * COLOR_DISTANCE);
+                    query.push(")");
+                }
+                MediaQueryType::CollapseStacks(_, collapse) => {
+                    // only the cover of each stack is listed
+                    if *collapse {
+                        query.push(" AND (media.id NOT IN (SELECT media_id FROM stack_media) OR media.id IN (SELECT cover_id FROM stack))");
+                    }
+                }
                 MediaQueryType::FullSearch(op, search) => {
                     query.push(" AND (1=2");
                     for term in FULL_SEARCH_QUERIES {
@@ -9,14 +9,14 @@ use uuid::{Uuid};
 use crate::media_query::MediaQuery;
 use crate::models::{date, MediaError};
 use crate::{sqlize, update_set};
-use crate::media_processors::format::{FormatType, MediaType};
+use crate::media_processors::format::{FormatType, MediaType, NO_SIDECARS};
 use crate::models::custom_metadata::CustomMetadata;
 use crate::models::media_extra::MediaExtra;
 use crate::models::media_tag::MediaTag;
 use crate::types::{AcquireClone, DbPool, SqliteAcquire};
 
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, Clone)]
 pub struct Metadata {
     pub id: i32,
     pub media_id: i32,
@@ -24,6 +24,34 @@ pub struct Metadata {
     pub value: String,
 }
 
+sqlize!(Metadata, "media_metadata", id, [
+    media_id,
+    key,
+    value
+]);
+
+impl Metadata {
+    pub async fn by_key(db: impl SqliteAcquire<'_>, key: &str) -> Result<Vec<Self>, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        Ok(sqlx::query("SELECT * FROM media_metadata WHERE key = $1;")
+            .bind(key)
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| row.borrow().into())
+            .collect())
+    }
+
+    pub async fn delete_by_media_id(db: impl SqliteAcquire<'_>, media_id: i32) -> Result<(), sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        sqlx::query("DELETE FROM media_metadata WHERE media_id = $1;")
+            .bind(media_id)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+}
+
 #[derive(Debug, Serialize, PartialEq, Clone)]
 pub struct Media {
     pub id: i32,
@@ -56,6 +84,35 @@ pub struct Media {
     pub metadata_version: i32,
     pub thumbnail_version: i32,
     pub import_id: i32,
+
+    /// shared by both halves of an Apple Live Photo
+    pub content_identifier: Option<String>,
+    /// the still this media is the motion half of, companions are hidden from queries by default
+    pub companion_of: Option<i32>,
+    /// byte offset of the video embedded in a Google/Samsung motion photo
+    pub motion_offset: Option<u32>,
+
+    /// from XMP, -1 (rejected) to 5
+    pub rating: Option<i32>,
+    /// XMP color label
+    pub label: Option<String>,
+
+    /// the rendition config the thumbnails were generated with, NULL for the defaults
+    pub thumbnail_renditions: Option<String>,
+
+    /// placeholder shown while the thumbnail loads
+    pub blurhash: Option<String>,
+    /// dominant colors as comma separated hex, most dominant first
+    pub palette: Option<String>,
+
+    /// reverse geocoded from the coordinates by the geocode task
+    pub country: Option<String>,
+    pub region: Option<String>,
+    pub city: Option<String>,
+    pub geocode_version: i32,
+
+    /// the sidecars the metadata was read with, see `AnyFormat::sidecar_sources`
+    pub sidecar_sources: Option<String>,
 }
 
 sqlize!(Media, "media", id, [
@@ -79,13 +136,26 @@ sqlize!(Media, "media", id, [
     metadata_version,
     thumbnail_version,
     import_id,
-    has_thumbnail
+    has_thumbnail,
+    content_identifier,
+    companion_of,
+    motion_offset,
+    rating,
+    label,
+    thumbnail_renditions,
+    blurhash,
+    palette,
+    country,
+    region,
+    city,
+    geocode_version,
+    sidecar_sources
 ]);
 
 impl Media {
     pub fn safe_column(name: &str) -> Result<(), sqlx::Error> {
         match name {
-            "id" | "uuid" | "name" | "created_at" | "width" | "height" | "size" | "path" | "liked" | "media_type" | "added_at" | "duration" | "import_id" => Ok(()),
+            "id" | "uuid" | "name" | "created_at" | "width" | "height" | "size" | "path" | "liked" | "media_type" | "added_at" | "duration" | "import_id" | "rating" | "country" | "city" => Ok(()),
             _ => Err(sqlx::Error::ColumnNotFound(name.to_string()))
         }
     }
@@ -165,6 +235,25 @@ impl Media {
             .collect())
     }
 
+    /// whether a sidecar was added, removed or edited since the metadata was read
+    pub fn sidecars_changed(&self, sources: &str) -> bool {
+        match &self.sidecar_sources {
+            Some(previous) => previous != sources,
+            // read before sources were recorded, only outdated if there's a sidecar now
+            None => sources != NO_SIDECARS,
+        }
+    }
+
+    /// the motion half of a Live Photo, if this is the still
+    pub async fn companion(&self, db: impl SqliteAcquire<'_>) -> Result<Option<Self>, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        Ok(sqlx::query("SELECT * FROM media WHERE companion_of = $1;")
+            .bind(self.id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .map(|row| row.borrow().into()))
+    }
+
     pub async fn delete<'a>(&self, db: impl SqliteExecutor<'a>) -> Result<(), sqlx::Error> {
         sqlx::query("DELETE FROM media WHERE id = $1")
             .bind(self.id)
@@ -204,6 +293,40 @@ impl Media {
             .collect())
     }
 
+    /// replaces the tags added by `task` (e.g. XMP keywords), tags that already exist from elsewhere are left alone
+    pub async fn set_task_tags(&self, db: &mut impl AcquireClone, task: &str, tags: &[String]) -> Result<(), sqlx::Error> {
+        self.remove_task_tags(db.acquire_clone(), task).await?;
+        let existing = self.tags(db.acquire_clone()).await?;
+        for tag in tags {
+            if !existing.iter().any(|t| &t.tag == tag) {
+                self.add_tag(db, tag.clone(), Some(task.to_string())).await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn remove_task_tags(&self, db: impl SqliteAcquire<'_>, task: &str) -> Result<(), sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        sqlx::query("DELETE FROM media_tag WHERE media_id = $1 AND task = $2;")
+            .bind(self.id)
+            .bind(task)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// names of the albums this media is in
+    pub async fn albums(&self, db: impl SqliteAcquire<'_>) -> Result<Vec<String>, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        Ok(sqlx::query("SELECT album.name FROM album INNER JOIN album_media ON album.id = album_media.album_id WHERE album_media.media_id = $1 ORDER BY album.name")
+            .bind(self.id)
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
     pub async fn remove_tag(&self, db: impl SqliteAcquire<'_>, tag: &str) -> Result<bool, sqlx::Error> {
         let mut conn = db.acquire().await?;
         let res = sqlx::query("DELETE FROM media_tag WHERE media_id = $1 AND tag = $2;")
@@ -215,6 +338,32 @@ impl Media {
     }
 
 
+    pub async fn metadata(&self, db: impl SqliteAcquire<'_>) -> Result<Vec<Metadata>, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        Ok(sqlx::query("SELECT * FROM media_metadata WHERE media_id = $1 ORDER BY media_metadata.id")
+            .bind(self.id)
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| row.borrow().into())
+            .collect())
+    }
+
+    /// replaces all metadata fields for this media
+    pub async fn set_metadata(&self, db: &mut impl AcquireClone, fields: &[(String, String)]) -> Result<(), sqlx::Error> {
+        Metadata::delete_by_media_id(db.acquire_clone(), self.id).await?;
+        for (key, value) in fields {
+            let mut metadata = Metadata {
+                id: 0,
+                media_id: self.id,
+                key: key.clone(),
+                value: value.clone(),
+            };
+            metadata.create(db.acquire_clone()).await?;
+        }
+        Ok(())
+    }
+
     pub async fn add_custom(&self, db: &mut impl AcquireClone, key: String, value: String, version: i32, include_search: bool) -> Result<CustomMetadata, sqlx::Error> {
         let mut custom = CustomMetadata {
             id: 0,
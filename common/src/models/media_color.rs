@@ -0,0 +1,56 @@
+use crate::question_marks;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Row};
+use std::borrow::Borrow;
+use serde::{Serialize};
+use crate::{sqlize, update_set};
+use crate::media_processors::palette::Color;
+use crate::types::{AcquireClone, SqliteAcquire};
+
+/// one entry of a media's palette, in Lab so the color filter can compare distances in SQL
+#[derive(Serialize, Debug, Clone)]
+pub struct MediaColor {
+    pub id: i32,
+    pub media_id: i32,
+    pub l: f64,
+    pub a: f64,
+    pub b: f64,
+    // of the image, 0 to 1
+    pub share: f64,
+}
+
+sqlize!(MediaColor, "media_color", id, [
+    media_id,
+    l,
+    a,
+    b,
+    share
+]);
+
+impl MediaColor {
+    pub async fn delete_by_media_id(db: impl SqliteAcquire<'_>, media_id: i32) -> Result<(), sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        sqlx::query("DELETE FROM media_color WHERE media_id = $1;")
+            .bind(media_id)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn replace(db: &mut impl AcquireClone, media_id: i32, palette: &[(Color, f32)]) -> Result<(), sqlx::Error> {
+        Self::delete_by_media_id(db.acquire_clone(), media_id).await?;
+        for (color, share) in palette {
+            let lab = color.to_lab();
+            let mut media_color = MediaColor {
+                id: 0,
+                media_id,
+                l: lab.l as f64,
+                a: lab.a as f64,
+                b: lab.b as f64,
+                share: *share as f64,
+            };
+            media_color.create(db.acquire_clone()).await?;
+        }
+        Ok(())
+    }
+}
@@ -1,7 +1,7 @@
 use crate::question_marks;
 use sqlx::sqlite::SqliteRow;
 use sqlx::types::chrono::NaiveDateTime;
-use sqlx::{Execute, Row, SqliteExecutor};
+use sqlx::{Execute, QueryBuilder, Row, SqliteExecutor};
 use std::borrow::Borrow;
 use serde::Serialize;
 use crate::{sqlize, update_set};
@@ -18,6 +18,13 @@ pub struct MediaExtra {
     pub whisper_transcript: Option<String>,
     pub vision_ocr_version: i32,
     pub vision_ocr_result: Option<String>,
+    pub transcode_version: i32,
+    pub transcode_format: Option<String>,
+    pub subtitles_version: i32,
+    /// JSON, same shape as `whisper_transcript`
+    pub subtitles: Option<String>,
+    /// JSON list of the sidecar files found when `subtitles` was read, to notice added or removed ones
+    pub subtitles_sources: Option<String>,
 }
 
 impl Default for MediaExtra {
@@ -31,6 +38,11 @@ impl Default for MediaExtra {
             whisper_transcript: None,
             vision_ocr_version: -1,
             vision_ocr_result: None,
+            transcode_version: -1,
+            transcode_format: None,
+            subtitles_version: -1,
+            subtitles: None,
+            subtitles_sources: None,
         }
     }
 }
@@ -42,7 +54,12 @@ sqlize!(MediaExtra, "media_extra", id, [
     whisper_confidence,
     whisper_transcript,
     vision_ocr_version,
-    vision_ocr_result
+    vision_ocr_result,
+    transcode_version,
+    transcode_format,
+    subtitles_version,
+    subtitles,
+    subtitles_sources
 ]);
 
 impl MediaExtra {
@@ -50,7 +67,7 @@ impl MediaExtra {
     // see: https://github.com/launchbadge/sqlx/issues/2093, remove when fixed
     pub async fn create_no_bug(&mut self, db: impl SqliteAcquire<'_>) -> Result<(), sqlx::Error> {
         let mut conn = db.acquire().await?;
-        let res = sqlx::query("INSERT INTO media_extra (media_id, whisper_version, whisper_language, whisper_confidence, whisper_transcript, vision_ocr_version, vision_ocr_result) VALUES ($1, $2, $3, $4, $5, $6, $7) RETURNING id")
+        let res = sqlx::query("INSERT INTO media_extra (media_id, whisper_version, whisper_language, whisper_confidence, whisper_transcript, vision_ocr_version, vision_ocr_result, transcode_version, transcode_format, subtitles_version, subtitles, subtitles_sources) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12) RETURNING id")
             .bind(&self.media_id)
             .bind(&self.whisper_version)
             .bind(&self.whisper_language)
@@ -58,6 +75,11 @@ impl MediaExtra {
             .bind(&self.whisper_transcript)
             .bind(&self.vision_ocr_version)
             .bind(&self.vision_ocr_result)
+            .bind(&self.transcode_version)
+            .bind(&self.transcode_format)
+            .bind(&self.subtitles_version)
+            .bind(&self.subtitles)
+            .bind(&self.subtitles_sources)
             .fetch_one(&mut *conn)
             .await?;
         
@@ -78,6 +100,27 @@ impl MediaExtra {
             .into())
     }
 
+    /// the extras of whichever of `media_ids` have one, in no particular order
+    pub async fn from_media_ids(db: impl SqliteAcquire<'_>, media_ids: &[i32]) -> Result<Vec<Self>, sqlx::Error> {
+        if media_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut conn = db.acquire().await?;
+        let mut query = QueryBuilder::new("SELECT * FROM media_extra WHERE media_id IN (");
+        let mut separated = query.separated(", ");
+        for media_id in media_ids {
+            separated.push_bind(*media_id);
+        }
+        query.push(")");
+        Ok(query
+            .build()
+            .fetch_all(&mut *conn)
+            .await?
+            .iter()
+            .map(|row| row.into())
+            .collect())
+    }
+
     pub async fn delete(&self,db: impl SqliteAcquire<'_>) -> Result<(), sqlx::Error> {
         let mut conn = db.acquire().await?;
         sqlx::query("DELETE FROM media_extra WHERE id = $1")
@@ -12,6 +12,9 @@ pub mod media_extra;
 pub mod media_tag;
 pub mod custom_metadata;
 pub mod custom_task_media;
+pub mod stack;
+pub mod media_color;
+pub mod place;
 
 pub mod date {
     use serde::{self, Deserialize, Serializer};
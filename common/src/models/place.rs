@@ -0,0 +1,55 @@
+use serde::Serialize;
+use sqlx::Row;
+use sqlx::sqlite::SqliteRow;
+use crate::media_query::MediaQuery;
+use crate::models::MediaError;
+use crate::types::DbPool;
+
+#[derive(Serialize, Debug)]
+pub struct PlaceCount {
+    pub country: Option<String>,
+    pub region: Option<String>,
+    pub city: String,
+    pub count: i32,
+}
+
+impl From<&SqliteRow> for PlaceCount {
+    fn from(row: &SqliteRow) -> Self {
+        Self {
+            country: row.get("country"),
+            region: row.get("region"),
+            city: row.get("city"),
+            count: row.get("count"),
+        }
+    }
+}
+
+pub struct Places;
+
+impl Places {
+    /// every geocoded place matching the query, most photographed first
+    pub async fn all(db: &DbPool, media_query: &MediaQuery) -> Result<Vec<PlaceCount>, MediaError> {
+        let mut query = sqlx::QueryBuilder::new(
+            "SELECT
+                    media.country AS country,
+                    media.region AS region,
+                    media.city AS city,
+                    COUNT(DISTINCT media.id) AS count
+                 FROM media ");
+
+        media_query.sqlize(&mut query)?;
+
+        query.push(" AND media.city IS NOT NULL \
+        GROUP BY media.country, media.region, media.city \
+        ORDER BY count DESC, media.city ASC");
+
+        let query = query.build();
+
+        Ok(query
+            .fetch_all(db)
+            .await?
+            .iter()
+            .map(|row| row.into())
+            .collect())
+    }
+}
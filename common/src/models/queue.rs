@@ -1,10 +1,12 @@
 use std::borrow::Borrow;
-use sqlx::{Executor, Row, Sqlite};
+use std::collections::BTreeMap;
+use chrono::Utc;
+use sqlx::{Connection, Executor, QueryBuilder, Row, Sqlite};
 use sqlx::SqliteExecutor;
 use sqlx::sqlite::SqliteRow;
 use crate::update_set;
 use crate::question_marks;
-use crate::models::date;
+use crate::models::{date, option_date};
 use serde::{Deserialize, Serialize};
 use crate::sqlize;
 use crate::types::SqliteAcquire;
@@ -16,19 +18,75 @@ pub struct Queue {
     pub task: String,
     #[serde(with = "date")]
     pub created_at: chrono::NaiveDateTime,
+    /// how many times a runner has picked this up, including a run in progress
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    /// hidden from runners until then, while leased or backing off after a failure
+    #[serde(with = "option_date")]
+    pub available_at: Option<chrono::NaiveDateTime>,
+    /// higher runs first, ties by age
+    pub priority: i32,
 }
 
 sqlize!(Queue, "queue", id, [
     media_id,
     task,
-    created_at
+    created_at,
+    attempts,
+    last_error,
+    available_at,
+    priority
 ]);
 
+// pushes an item in front of everything scanned in the background
+pub const PRIORITY_USER: i32 = 10;
+
+// what's stored in queue_failed for items whose dependency failed first
+const DEPENDENCY_FAILED: &str = "a task it depends on failed";
+
+// appended to a query on `queue`, matches items whose media has one of `dependencies` in `table`
+fn push_dependency_in(query: &mut QueryBuilder<Sqlite>, table: &str, dependencies: &[&str], exists: bool) {
+    query.push(format!(" AND {}EXISTS (SELECT 1 FROM {} dependency WHERE dependency.media_id = queue.media_id AND dependency.task IN (", if exists { "" } else { "NOT " }, table));
+    let mut separated = query.separated(", ");
+    for dependency in dependencies {
+        separated.push_bind(dependency.to_string());
+    }
+    query.push("))");
+}
+
+// appended to a query on `queue`, hides items whose media still has a dependency queued or failed
+fn push_dependencies_done(query: &mut QueryBuilder<Sqlite>, dependencies: &[&str]) {
+    if dependencies.is_empty() {
+        return;
+    }
+    for table in ["queue", "queue_failed"] {
+        push_dependency_in(query, table, dependencies, false);
+    }
+}
+
 impl Queue {
+    pub fn new(media_id: i32, task: &str) -> Self {
+        Self {
+            id: 0,
+            media_id,
+            task: task.to_string(),
+            created_at: Utc::now().naive_utc(),
+            attempts: 0,
+            last_error: None,
+            available_at: None,
+            priority: 0,
+        }
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
     pub async fn get_next(db: impl SqliteAcquire<'_>, task: &str) -> Result<Option<Queue>, sqlx::Error>
     {
         let mut conn = db.acquire().await?;
-        let queue = sqlx::query("SELECT * FROM queue WHERE task = ? ORDER BY created_at ASC LIMIT 1")
+        let queue = sqlx::query("SELECT * FROM queue WHERE task = ? ORDER BY priority DESC, created_at ASC LIMIT 1")
             .bind(task)
             .fetch_optional(&mut *conn)
             .await?;
@@ -38,6 +96,99 @@ impl Queue {
         }
     }
 
+    /// claims the most urgent available item whose `dependencies` are done for its media,
+    /// it stays in the queue but hidden for `lease` in case the runner dies with it
+    pub async fn lease_next(db: impl SqliteAcquire<'_>, task: &str, dependencies: &[&str], lease: chrono::Duration) -> Result<Option<Queue>, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        let now = Utc::now().naive_utc();
+        // a single statement, so two runners can't claim the same item
+        let mut query = QueryBuilder::new("UPDATE queue SET attempts = attempts + 1, available_at = ");
+        query.push_bind(now + lease);
+        query.push(" WHERE id = (SELECT id FROM queue WHERE task = ");
+        query.push_bind(task);
+        query.push(" AND (available_at IS NULL OR available_at <= ");
+        query.push_bind(now);
+        query.push(")");
+        push_dependencies_done(&mut query, dependencies);
+        query.push(" ORDER BY priority DESC, created_at ASC LIMIT 1) RETURNING *");
+        let queue = query.build().fetch_optional(&mut *conn).await?;
+        match queue {
+            Some(row) => Ok(Some(row.borrow().into())),
+            None => Ok(None),
+        }
+    }
+
+    /// pushes the lease back out to `lease` from now while the item is running,
+    /// false if it was lost: the lease ran out and another runner took it, or the item is gone
+    pub async fn renew(&mut self, db: impl SqliteAcquire<'_>, lease: chrono::Duration) -> Result<bool, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        let available_at = Utc::now().naive_utc() + lease;
+        // every lease bumps attempts, so it tells this lease apart from a later one
+        let renewed = sqlx::query("UPDATE queue SET available_at = ? WHERE id = ? AND attempts = ?")
+            .bind(available_at)
+            .bind(self.id)
+            .bind(self.attempts)
+            .execute(&mut *conn)
+            .await?
+            .rows_affected() > 0;
+        if renewed {
+            self.available_at = Some(available_at);
+        }
+        Ok(renewed)
+    }
+
+    /// moves items of `task` whose media has one of `dependencies` in queue_failed there too,
+    /// they'd never run otherwise. requeueing the dependency doesn't bring them back, returns how many were moved
+    pub async fn fail_blocked(db: impl SqliteAcquire<'_>, task: &str, dependencies: &[&str]) -> Result<u64, sqlx::Error> {
+        if dependencies.is_empty() {
+            return Ok(0);
+        }
+        let mut conn = db.acquire().await?;
+        let mut transaction = conn.begin().await?;
+        let mut query = QueryBuilder::new("INSERT INTO queue_failed (media_id, task, created_at, failed_at, attempts, last_error) SELECT media_id, task, created_at, ");
+        query.push_bind(Utc::now().naive_utc());
+        query.push(", attempts, ");
+        query.push_bind(DEPENDENCY_FAILED);
+        query.push(" FROM queue WHERE task = ");
+        query.push_bind(task);
+        push_dependency_in(&mut query, "queue_failed", dependencies, true);
+        query.build().execute(&mut *transaction).await?;
+
+        let mut query = QueryBuilder::new("DELETE FROM queue WHERE task = ");
+        query.push_bind(task);
+        push_dependency_in(&mut query, "queue_failed", dependencies, true);
+        let moved = query.build().execute(&mut *transaction).await?.rows_affected();
+        transaction.commit().await?;
+        Ok(moved)
+    }
+
+    /// whether any of `tasks` is still queued for the media
+    pub async fn pending(db: impl SqliteAcquire<'_>, media_id: i32, tasks: &[&str]) -> Result<bool, sqlx::Error> {
+        Self::any_for_media(db, "queue", media_id, tasks).await
+    }
+
+    /// whether any of `tasks` has given up on the media
+    pub async fn failed(db: impl SqliteAcquire<'_>, media_id: i32, tasks: &[&str]) -> Result<bool, sqlx::Error> {
+        Self::any_for_media(db, "queue_failed", media_id, tasks).await
+    }
+
+    async fn any_for_media(db: impl SqliteAcquire<'_>, table: &str, media_id: i32, tasks: &[&str]) -> Result<bool, sqlx::Error> {
+        if tasks.is_empty() {
+            return Ok(false);
+        }
+        let mut conn = db.acquire().await?;
+        let mut query = QueryBuilder::new(format!("SELECT COUNT(*) FROM {} WHERE media_id = ", table));
+        query.push_bind(media_id);
+        query.push(" AND task IN (");
+        let mut separated = query.separated(", ");
+        for task in tasks {
+            separated.push_bind(task.to_string());
+        }
+        query.push(")");
+        let count: u32 = query.build().fetch_one(&mut *conn).await?.get(0);
+        Ok(count > 0)
+    }
+
     pub async fn from_media_id(db: impl SqliteAcquire<'_>, task: &str, media_id: i32) -> Result<Option<Queue>, sqlx::Error> {
         let mut conn = db.acquire().await?;
         let queue = sqlx::query("SELECT * FROM queue WHERE media_id = ? AND task = ?")
@@ -59,7 +210,52 @@ impl Queue {
             .await?;
         Ok(count.get(0))
     }
-    
+
+    /// items that aren't leased or backing off, and whose `dependencies` are done, what `lease_next` could take
+    pub async fn count_available(db: impl SqliteAcquire<'_>, task: &str, dependencies: &[&str]) -> Result<u32, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        let mut query = QueryBuilder::new("SELECT COUNT(*) FROM queue WHERE task = ");
+        query.push_bind(task);
+        query.push(" AND (available_at IS NULL OR available_at <= ");
+        query.push_bind(Utc::now().naive_utc());
+        query.push(")");
+        push_dependencies_done(&mut query, dependencies);
+        Ok(query.build().fetch_one(&mut *conn).await?.get(0))
+    }
+
+    /// puts a failed item back, hidden until `backoff` has passed
+    pub async fn retry_later(&mut self, db: impl SqliteAcquire<'_>, error: &str, backoff: chrono::Duration) -> Result<(), sqlx::Error> {
+        self.last_error = Some(error.to_string());
+        self.available_at = Some(Utc::now().naive_utc() + backoff);
+        self.update_by_id(db).await
+    }
+
+    /// hands a leased item back untouched, the interrupted run doesn't count as an attempt
+    pub async fn release(&mut self, db: impl SqliteAcquire<'_>) -> Result<(), sqlx::Error> {
+        self.attempts = (self.attempts - 1).max(0);
+        self.available_at = None;
+        self.update_by_id(db).await
+    }
+
+    /// gives up on the item, moving it to queue_failed
+    pub async fn fail(&self, db: impl SqliteAcquire<'_>, error: &str) -> Result<QueueFailed, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        let mut transaction = conn.begin().await?;
+        let mut failed = QueueFailed {
+            id: 0,
+            media_id: self.media_id,
+            task: self.task.clone(),
+            created_at: self.created_at,
+            failed_at: Utc::now().naive_utc(),
+            attempts: self.attempts,
+            last_error: Some(error.to_string()),
+        };
+        failed.create(&mut *transaction).await?;
+        self.delete(&mut *transaction).await?;
+        transaction.commit().await?;
+        Ok(failed)
+    }
+
     pub async fn delete(&self, db: impl SqliteAcquire<'_>) -> Result<(), sqlx::Error> {
         let mut conn = db.acquire().await?;
         sqlx::query("DELETE FROM queue WHERE id = ?")
@@ -69,6 +265,87 @@ impl Queue {
         Ok(())
     }
 
+    /// most urgent first, like runners take them
+    pub async fn list(db: impl SqliteAcquire<'_>, task: &str, limit: u32) -> Result<Vec<Queue>, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        Ok(sqlx::query("SELECT * FROM queue WHERE task = ? ORDER BY priority DESC, created_at ASC LIMIT ?")
+            .bind(task)
+            .bind(limit)
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| row.borrow().into())
+            .collect())
+    }
+
+    /// every task with something queued or failed
+    pub async fn summary(db: impl SqliteAcquire<'_>) -> Result<Vec<QueueSummary>, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        let mut summaries: BTreeMap<String, QueueSummary> = BTreeMap::new();
+        let rows = sqlx::query("SELECT task, COUNT(*), SUM(available_at IS NULL OR available_at <= ?), MIN(created_at) FROM queue GROUP BY task")
+            .bind(Utc::now().naive_utc())
+            .fetch_all(&mut *conn)
+            .await?;
+        for row in rows {
+            let task: String = row.get(0);
+            summaries.insert(task.clone(), QueueSummary {
+                task,
+                pending: row.get(1),
+                available: row.get(2),
+                failed: 0,
+                oldest: row.get(3),
+            });
+        }
+        let rows = sqlx::query("SELECT task, COUNT(*) FROM queue_failed GROUP BY task")
+            .fetch_all(&mut *conn)
+            .await?;
+        for row in rows {
+            let task: String = row.get(0);
+            summaries.entry(task.clone()).or_insert_with(|| QueueSummary {
+                task,
+                pending: 0,
+                available: 0,
+                failed: 0,
+                oldest: None,
+            }).failed = row.get(1);
+        }
+        Ok(summaries.into_values().collect())
+    }
+
+    pub async fn delete_by_task(db: impl SqliteAcquire<'_>, task: &str) -> Result<u64, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        Ok(sqlx::query("DELETE FROM queue WHERE task = ?")
+            .bind(task)
+            .execute(&mut *conn)
+            .await?
+            .rows_affected())
+    }
+
+    /// queues `task` for the media in place of what's waiting, an item that's leased or backing off isn't pulled
+    /// from under its runner, it stays and takes the higher of the two priorities
+    pub async fn replace(db: impl SqliteAcquire<'_>, task: &str, media_id: i32, priority: i32) -> Result<(), sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        let mut transaction = conn.begin().await?;
+        sqlx::query("DELETE FROM queue WHERE media_id = ? AND task = ? AND (available_at IS NULL OR available_at <= ?)")
+            .bind(media_id)
+            .bind(task)
+            .bind(Utc::now().naive_utc())
+            .execute(&mut *transaction)
+            .await?;
+        let folded = sqlx::query("UPDATE queue SET priority = MAX(priority, ?) WHERE media_id = ? AND task = ?")
+            .bind(priority)
+            .bind(media_id)
+            .bind(task)
+            .execute(&mut *transaction)
+            .await?
+            .rows_affected() > 0;
+        if !folded {
+            Queue::new(media_id, task).with_priority(priority).create(&mut *transaction).await?;
+        }
+        transaction.commit().await?;
+        Ok(())
+    }
+
     pub async fn delete_by_media_id(db: impl SqliteAcquire<'_>, task: &str, media_id: i32) -> Result<(), sqlx::Error> {
         let mut conn = db.acquire().await?;
         sqlx::query("DELETE FROM queue WHERE media_id = ? AND task = ?")
@@ -78,4 +355,103 @@ impl Queue {
             .await?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct QueueSummary {
+    pub task: String,
+    pub pending: u32,
+    /// not leased or backing off
+    pub available: u32,
+    pub failed: u32,
+    #[serde(with = "option_date")]
+    pub oldest: Option<chrono::NaiveDateTime>,
+}
+
+/// queue items that ran out of attempts, kept until they're requeued or the media is deleted
+#[derive(Serialize, Debug, Clone, Deserialize)]
+pub struct QueueFailed {
+    pub id: i32,
+    pub media_id: i32,
+    pub task: String,
+    #[serde(with = "date")]
+    pub created_at: chrono::NaiveDateTime,
+    #[serde(with = "date")]
+    pub failed_at: chrono::NaiveDateTime,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+}
+
+sqlize!(QueueFailed, "queue_failed", id, [
+    media_id,
+    task,
+    created_at,
+    failed_at,
+    attempts,
+    last_error
+]);
+
+impl QueueFailed {
+    /// most recent failures first, for every task if `task` is None
+    pub async fn all(db: impl SqliteAcquire<'_>, task: Option<&str>) -> Result<Vec<QueueFailed>, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        Ok(sqlx::query("SELECT * FROM queue_failed WHERE ? IS NULL OR task = ? ORDER BY failed_at DESC")
+            .bind(task)
+            .bind(task)
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| row.borrow().into())
+            .collect())
+    }
+
+    pub async fn delete_by_media_id(db: impl SqliteAcquire<'_>, task: &str, media_id: i32) -> Result<(), sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        sqlx::query("DELETE FROM queue_failed WHERE media_id = ? AND task = ?")
+            .bind(media_id)
+            .bind(task)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// moves the item back into the queue with its attempts reset, along with what gave up on the media because a dependency failed,
+    /// if that was another dependency they're moved back on the next run
+    pub async fn requeue(&self, db: impl SqliteAcquire<'_>) -> Result<Queue, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        let mut transaction = conn.begin().await?;
+        let blocked: Vec<String> = sqlx::query("SELECT DISTINCT task FROM queue_failed WHERE media_id = ? AND last_error = ? AND task != ?")
+            .bind(self.media_id)
+            .bind(DEPENDENCY_FAILED)
+            .bind(&self.task)
+            .fetch_all(&mut *transaction)
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect();
+        for task in &blocked {
+            Queue::replace(&mut *transaction, task, self.media_id, 0).await?;
+            Self::delete_by_media_id(&mut *transaction, task, self.media_id).await?;
+        }
+
+        Queue::delete_by_media_id(&mut *transaction, &self.task, self.media_id).await?;
+        let mut queue = Queue::new(self.media_id, &self.task);
+        queue.create(&mut *transaction).await?;
+        sqlx::query("DELETE FROM queue_failed WHERE id = ?")
+            .bind(self.id)
+            .execute(&mut *transaction)
+            .await?;
+        transaction.commit().await?;
+        Ok(queue)
+    }
+
+    /// requeues every failed item of `task`, or of every task if None
+    pub async fn requeue_all(db: impl SqliteAcquire<'_>, task: Option<&str>) -> Result<u32, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        let failed = Self::all(&mut *conn, task).await?;
+        for item in &failed {
+            item.requeue(&mut *conn).await?;
+        }
+        Ok(failed.len() as u32)
+    }
+}
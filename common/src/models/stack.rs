@@ -0,0 +1,174 @@
+use crate::question_marks;
+use sqlx::sqlite::SqliteRow;
+use sqlx::types::chrono::NaiveDateTime;
+use sqlx::Row;
+use std::borrow::Borrow;
+use std::fmt::Display;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::models::date;
+use crate::models::media::Media;
+use crate::{sqlize, update_set};
+use crate::types::{AcquireClone, SqliteAcquire};
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StackKind {
+    RawJpeg,
+    Burst,
+    Manual,
+}
+
+impl StackKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StackKind::RawJpeg => "raw-jpeg",
+            StackKind::Burst => "burst",
+            StackKind::Manual => "manual",
+        }
+    }
+}
+
+impl Display for StackKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl FromStr for StackKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw-jpeg" => Ok(StackKind::RawJpeg),
+            "burst" => Ok(StackKind::Burst),
+            "manual" => Ok(StackKind::Manual),
+            _ => Err(format!("unknown stack kind: {}", s)),
+        }
+    }
+}
+
+/// a group of media shown as one, e.g. a RAW and its JPEG or a burst
+#[derive(Debug, Serialize, Clone)]
+pub struct Stack {
+    pub id: i32,
+    pub uuid: Uuid,
+    /// the media shown in place of the stack
+    pub cover_id: i32,
+    /// see `StackKind`
+    pub kind: String,
+    #[serde(with = "date")]
+    pub created_at: NaiveDateTime,
+}
+
+sqlize!(Stack, "stack", id, [
+    uuid,
+    cover_id,
+    kind,
+    created_at
+]);
+
+impl Stack {
+    pub async fn from_uuid(db: impl SqliteAcquire<'_>, uuid: &Uuid) -> Result<Option<Self>, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        Ok(sqlx::query("SELECT * FROM stack WHERE uuid = $1;")
+            .bind(uuid)
+            .fetch_optional(&mut *conn)
+            .await?
+            .map(|row| row.borrow().into()))
+    }
+
+    pub async fn from_media_id(db: impl SqliteAcquire<'_>, media_id: i32) -> Result<Option<Self>, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        Ok(sqlx::query("SELECT stack.* FROM stack INNER JOIN stack_media ON stack.id = stack_media.stack_id WHERE stack_media.media_id = $1;")
+            .bind(media_id)
+            .fetch_optional(&mut *conn)
+            .await?
+            .map(|row| row.borrow().into()))
+    }
+
+    /// ids of all media that are in some stack
+    pub async fn stacked_media_ids(db: impl SqliteAcquire<'_>) -> Result<Vec<i32>, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        Ok(sqlx::query("SELECT media_id FROM stack_media;")
+            .fetch_all(&mut *conn)
+            .await?
+            .into_iter()
+            .map(|row| row.get(0))
+            .collect())
+    }
+
+    pub async fn media(&self, db: impl SqliteAcquire<'_>) -> Result<Vec<Media>, sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        Ok(sqlx::query("SELECT media.* FROM media INNER JOIN stack_media ON media.id = stack_media.media_id WHERE stack_media.stack_id = $1 ORDER BY media.created_at, media.id;")
+            .bind(self.id)
+            .fetch_all(&mut *conn)
+            .await?
+            .iter()
+            .map(|row| row.into())
+            .collect())
+    }
+
+    pub async fn add_media(&self, db: impl SqliteAcquire<'_>, media_id: i32) -> Result<(), sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        sqlx::query("INSERT INTO stack_media (stack_id, media_id) VALUES ($1, $2);")
+            .bind(self.id)
+            .bind(media_id)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn remove_media(&self, db: impl SqliteAcquire<'_>, media_id: i32) -> Result<(), sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        sqlx::query("DELETE FROM stack_media WHERE stack_id = $1 AND media_id = $2;")
+            .bind(self.id)
+            .bind(media_id)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete(&self, db: impl SqliteAcquire<'_>) -> Result<(), sqlx::Error> {
+        let mut conn = db.acquire().await?;
+        sqlx::query("DELETE FROM stack WHERE id = $1;")
+            .bind(self.id)
+            .execute(&mut *conn)
+            .await?;
+        Ok(())
+    }
+
+    /// creates a stack of `media_ids`, none of which may already be stacked
+    pub async fn create_with_media(db: &mut impl AcquireClone, kind: StackKind, cover_id: i32, media_ids: &[i32]) -> Result<Self, sqlx::Error> {
+        let mut stack = Stack {
+            id: 0,
+            uuid: Uuid::new_v4(),
+            cover_id,
+            kind: kind.to_string(),
+            created_at: chrono::Utc::now().naive_utc(),
+        };
+        stack.create(db.acquire_clone()).await?;
+        for media_id in media_ids {
+            stack.add_media(db.acquire_clone(), *media_id).await?;
+        }
+        Ok(stack)
+    }
+
+    /// takes media out of the stack, fixing up the cover and dissolving the stack if less than two remain
+    pub async fn split(&mut self, db: &mut impl AcquireClone, media_ids: &[i32]) -> Result<bool, sqlx::Error> {
+        for media_id in media_ids {
+            self.remove_media(db.acquire_clone(), *media_id).await?;
+        }
+        let remaining = self.media(db.acquire_clone()).await?;
+        if remaining.len() < 2 {
+            self.delete(db.acquire_clone()).await?;
+            return Ok(true);
+        }
+        if media_ids.contains(&self.cover_id) {
+            self.cover_id = remaining[0].id;
+            self.update_by_id(db.acquire_clone()).await?;
+        }
+        Ok(false)
+    }
+}
@@ -1,10 +1,12 @@
 use std::collections::HashMap;
+use chrono::NaiveTime;
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
 use toml::map::Map;
 use toml::Table;
 use crate::media_processors::format::pdf::PdfConfig;
 use crate::media_query::MediaQuery;
+use crate::schedule::{Cron, QuietHours};
 
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct AppConfig {
@@ -26,6 +28,16 @@ pub struct AppConfig {
     pub ffmpeg_path: String,
     pub scripts_dir: String,
 
+    // write tag and album changes back to XMP sidecars next to the originals
+    #[serde(default)]
+    pub write_sidecars: bool,
+
+    #[serde(default)]
+    pub queue: QueueConfig,
+
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+
     #[serde(default)]
     pub remote: Table,
 
@@ -33,6 +45,72 @@ pub struct AppConfig {
     pub custom: HashMap<String, CustomConfig>
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct QueueConfig {
+    /// runs before an item is given up on and moved to queue_failed
+    pub max_attempts: i32,
+    /// how long a running item is hidden from other runs, renewed while it runs so a runner that dies only holds it this long
+    pub lease_seconds: i64,
+    /// delay before the first retry, doubled on every attempt after that
+    pub backoff_seconds: i64,
+    pub max_backoff_seconds: i64,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            lease_seconds: 10 * 60,
+            backoff_seconds: 60,
+            max_backoff_seconds: 24 * 60 * 60,
+        }
+    }
+}
+
+impl QueueConfig {
+    /// how long to wait before retrying an item that has failed `attempts` times
+    pub fn backoff(&self, attempts: i32) -> chrono::Duration {
+        let exponent = (attempts - 1).clamp(0, 30) as u32;
+        let seconds = self.backoff_seconds.saturating_mul(1i64 << exponent).min(self.max_backoff_seconds);
+        chrono::Duration::seconds(seconds)
+    }
+}
+
+/// what the daemon does on its own after the startup run, times are local
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    /// when to scan, e.g. "0 3 * * *", never if unset
+    pub scan: Option<Cron>,
+    /// how often to look for new queue items
+    pub queue_interval_seconds: u64,
+    /// e.g. "08:00-23:00", `quiet_tasks` don't start new items during it, even in a run that began before, the item in progress finishes
+    pub quiet_hours: Option<QuietHours>,
+    pub quiet_tasks: Vec<String>,
+}
+
+impl Default for ScheduleConfig {
+    fn default() -> Self {
+        Self {
+            scan: None,
+            queue_interval_seconds: 60,
+            quiet_hours: None,
+            quiet_tasks: Vec::new(),
+        }
+    }
+}
+
+impl ScheduleConfig {
+    /// the tasks held back at `time`
+    pub fn held_tasks(&self, time: NaiveTime) -> &[String] {
+        match &self.quiet_hours {
+            Some(quiet_hours) if quiet_hours.contains(time) => &self.quiet_tasks,
+            _ => &[],
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, Default)]
 pub struct FormatConfig {
     pub pdf: PdfConfig,
@@ -0,0 +1,198 @@
+use std::fmt;
+use std::str::FromStr;
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Timelike};
+use serde::{Deserialize, Serialize};
+
+// nothing sensible needs more than this, "0 0 29 2 1" is the worst case
+const MAX_SEARCH_YEARS: i64 = 28;
+
+/// a five field cron expression, "minute hour day-of-month month day-of-week"
+///
+/// fields take `*`, numbers, ranges `1-5`, lists `1,15` and steps `*/15` or `8-18/2`, sunday is 0 or 7.
+/// like cron, when both day fields are restricted either one matching is enough
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Cron {
+    source: String,
+    // bit n set means n matches
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, ScheduleError> {
+    let invalid = || ScheduleError::InvalidField(field.to_string());
+    let number = |s: &str| s.parse::<u32>().ok().filter(|n| (min..=max).contains(n)).ok_or_else(invalid);
+
+    let mut bits = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0).ok_or_else(invalid)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (number(start)?, number(end)?),
+            // "5/15" is every 15 starting at 5
+            None if step > 1 => (number(range)?, max),
+            None => (number(range)?, number(range)?),
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for n in (start..=end).step_by(step as usize) {
+            bits |= 1 << n;
+        }
+    }
+    Ok(bits)
+}
+
+impl FromStr for Cron {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<&str> = s.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(ScheduleError::FieldCount(s.to_string()));
+        };
+        let mut weekday_bits = parse_field(weekdays, 0, 7)?;
+        // 7 is sunday too
+        if weekday_bits & 1 << 7 != 0 {
+            weekday_bits = (weekday_bits & !(1 << 7)) | 1;
+        }
+        Ok(Self {
+            source: s.to_string(),
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            weekdays: weekday_bits,
+            days_restricted: days != "*",
+            weekdays_restricted: weekdays != "*",
+        })
+    }
+}
+
+impl TryFrom<String> for Cron {
+    type Error = ScheduleError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<Cron> for String {
+    fn from(cron: Cron) -> Self {
+        cron.source
+    }
+}
+
+impl fmt::Display for Cron {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl Cron {
+    fn day_matches(&self, time: &NaiveDateTime) -> bool {
+        let day = self.days & 1 << time.day() != 0;
+        let weekday = self.weekdays & 1 << time.weekday().num_days_from_sunday() != 0;
+        if self.days_restricted && self.weekdays_restricted {
+            day || weekday
+        } else {
+            day && weekday
+        }
+    }
+
+    pub fn matches(&self, time: &NaiveDateTime) -> bool {
+        self.months & 1 << time.month() != 0
+            && self.day_matches(time)
+            && self.hours & 1 << time.hour() != 0
+            && self.minutes & 1 << time.minute() != 0
+    }
+
+    /// the first matching minute strictly after `time`, None if it never matches (e.g. "0 0 31 2 *")
+    pub fn next_after(&self, time: NaiveDateTime) -> Option<NaiveDateTime> {
+        let mut next = time.with_second(0)?.with_nanosecond(0)? + Duration::minutes(1);
+        let limit = next + Duration::days(366 * MAX_SEARCH_YEARS);
+        // skip whole days and hours rather than walking every minute
+        while next < limit {
+            if self.months & 1 << next.month() == 0 || !self.day_matches(&next) {
+                next = next.date().succ_opt()?.and_hms_opt(0, 0, 0)?;
+            } else if self.hours & 1 << next.hour() == 0 {
+                next = next.date().and_hms_opt(next.hour(), 0, 0)? + Duration::hours(1);
+            } else if self.minutes & 1 << next.minute() == 0 {
+                next += Duration::minutes(1);
+            } else {
+                return Some(next);
+            }
+        }
+        None
+    }
+}
+
+/// a daily time range like "22:00-07:00", it wraps past midnight if it ends before it starts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct QuietHours {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl FromStr for QuietHours {
+    type Err = ScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || ScheduleError::InvalidTimeRange(s.to_string());
+        let (start, end) = s.split_once('-').ok_or_else(invalid)?;
+        let time = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|_| invalid());
+        Ok(Self {
+            start: time(start)?,
+            end: time(end)?,
+        })
+    }
+}
+
+impl TryFrom<String> for QuietHours {
+    type Error = ScheduleError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<QuietHours> for String {
+    fn from(hours: QuietHours) -> Self {
+        hours.to_string()
+    }
+}
+
+impl fmt::Display for QuietHours {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.start.format("%H:%M"), self.end.format("%H:%M"))
+    }
+}
+
+impl QuietHours {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ScheduleError {
+    #[error("expected 5 fields (minute hour day month weekday): {0}")]
+    FieldCount(String),
+    #[error("invalid cron field: {0}")]
+    InvalidField(String),
+    #[error("invalid time range, expected HH:MM-HH:MM: {0}")]
+    InvalidTimeRange(String),
+}
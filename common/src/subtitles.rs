@@ -0,0 +1,143 @@
+use std::path::{Path, PathBuf};
+
+/// (start, end, text) in seconds, the same shape Whisper transcripts are stored in
+pub type Cue = (f32, f32, String);
+
+pub const SUBTITLE_EXTENSIONS: &[&str] = &["srt", "vtt"];
+
+// "01:02:03,456" (srt) or "01:02:03.456"/"02:03.456" (vtt)
+fn parse_timestamp(timestamp: &str) -> Option<f32> {
+    let timestamp = timestamp.trim().replace(',', ".");
+    let parts: Vec<&str> = timestamp.split(':').collect();
+    let (hours, minutes, seconds) = match parts[..] {
+        [hours, minutes, seconds] => (hours.parse::<f32>().ok()?, minutes.parse::<f32>().ok()?, seconds),
+        [minutes, seconds] => (0.0, minutes.parse::<f32>().ok()?, seconds),
+        _ => return None,
+    };
+    Some(hours * 3600.0 + minutes * 60.0 + seconds.parse::<f32>().ok()?)
+}
+
+// "01:02:03.456", srt separates the milliseconds with a comma
+fn format_timestamp(seconds: f32, separator: char) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!("{:02}:{:02}:{:02}{}{:03}", millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, separator, millis % 1000)
+}
+
+// a blank line would end the cue early, as would an arrow looking like a timing line
+fn cue_text(text: &str) -> String {
+    text.lines().filter(|l| !l.trim().is_empty()).collect::<Vec<_>>().join("\n").replace("-->", "->")
+}
+
+// drops markup like <i>, <c.yellow>, <00:01.000> and ASS overrides like {\an8}
+fn strip_markup(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut depth: Option<char> = None;
+    for c in text.chars() {
+        match (depth, c) {
+            (None, '<') => depth = Some('>'),
+            (None, '{') => depth = Some('}'),
+            (Some(close), c) if c == close => depth = None,
+            (Some(_), _) => {}
+            (None, c) => out.push(c),
+        }
+    }
+    out.replace("&amp;", "&").replace("&lt;", "<").replace("&gt;", ">").replace("&nbsp;", " ")
+}
+
+// "start --> end [settings]"
+fn parse_timing(line: &str) -> Option<(f32, f32)> {
+    let (start, rest) = line.split_once("-->")?;
+    let end = rest.split_whitespace().next()?;
+    Some((parse_timestamp(start)?, parse_timestamp(end)?))
+}
+
+/// parses SRT and WebVTT, both are blocks separated by blank lines with a timing line followed by the text
+pub fn parse(data: &str) -> Result<Vec<Cue>, SubtitleError> {
+    let data = data.trim_start_matches('\u{feff}').replace("\r\n", "\n").replace('\r', "\n");
+
+    let mut cues = Vec::new();
+    let mut has_timing = false;
+    for block in data.split("\n\n") {
+        let mut lines = block.lines().skip_while(|l| l.trim().is_empty());
+        // the cue number (srt) or identifier (vtt) is optional, the timing line isn't
+        let Some((start, end)) = lines.by_ref().take(2).find_map(parse_timing) else {
+            continue;
+        };
+        has_timing = true;
+        let text = lines
+            .map(|l| strip_markup(l).trim().to_string())
+            .filter(|l| !l.is_empty())
+            .collect::<Vec<_>>()
+            .join(" ");
+        if !text.is_empty() && end >= start {
+            cues.push((start, end, text));
+        }
+    }
+
+    if !has_timing && !data.trim().is_empty() && !data.trim_start().starts_with("WEBVTT") {
+        return Err(SubtitleError::InvalidSubtitles);
+    }
+
+    cues.sort_by(|a, b| a.0.total_cmp(&b.0));
+    Ok(cues)
+}
+
+pub fn to_vtt(cues: &[Cue]) -> String {
+    let mut vtt = String::from("WEBVTT\n");
+    for (start, end, text) in cues {
+        vtt.push_str(&format!("\n{} --> {}\n{}\n", format_timestamp(*start, '.'), format_timestamp(*end, '.'), cue_text(text)));
+    }
+    vtt
+}
+
+pub fn to_srt(cues: &[Cue]) -> String {
+    let mut srt = String::new();
+    for (i, (start, end, text)) in cues.iter().enumerate() {
+        if i > 0 {
+            srt.push('\n');
+        }
+        srt.push_str(&format!("{}\n{} --> {}\n{}\n", i + 1, format_timestamp(*start, ','), format_timestamp(*end, ','), cue_text(text)));
+    }
+    srt
+}
+
+/// subtitle files next to `path`, e.g. movie.srt, movie.en.vtt for movie.mkv, exact matches first
+pub fn sidecar_paths(path: &Path) -> Vec<PathBuf> {
+    let (Some(parent), Some(stem)) = (path.parent(), path.file_stem()) else {
+        return Vec::new();
+    };
+    let stem = stem.to_string_lossy().to_string();
+    let Ok(entries) = std::fs::read_dir(parent) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            let extension = p.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+            let name_stem = p.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            SUBTITLE_EXTENSIONS.contains(&extension.as_str()) && (name_stem == stem || name_stem.starts_with(&format!("{}.", stem))) && p.is_file()
+        })
+        .collect();
+    paths.sort_by_key(|p| (p.file_stem().map(|s| s.len()).unwrap_or(0), p.clone()));
+    paths
+}
+
+pub fn read(path: &Path) -> Result<Vec<Cue>, SubtitleError> {
+    let bytes = std::fs::read(path)?;
+    // older srt files are often latin-1
+    let data = match String::from_utf8(bytes) {
+        Ok(data) => data,
+        Err(e) => e.into_bytes().iter().map(|b| *b as char).collect(),
+    };
+    parse(&data)
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SubtitleError {
+    #[error("iO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("no cues found, not srt or vtt")]
+    InvalidSubtitles,
+}
@@ -261,3 +261,51 @@ pub fn media_query_validation() {
         );
     }
 }
+
+#[test]
+pub fn media_query_color() {
+    let query = "color:=#F80 limit:=10".parse::<common::media_query::media_query::MediaQuery>().expect("failed to parse color");
+    assert_eq!(query.to_string(), "color:=#ff8800 limit:=10");
+    assert!(query.validate().is_ok());
+
+    for input in ["color:=#ff88", "color:=orange", "color:>#ff8800"] {
+        assert!(input.parse::<common::media_query::media_query::MediaQuery>().is_err(), "input: {}", input);
+    }
+}
+
+#[test]
+pub fn media_query_without_companions() {
+    let query = "has_gps:=true order_by:=created_at limit:=10 page:=2".parse::<common::media_query::media_query::MediaQuery>().unwrap();
+    let listing = query.without_companions();
+    assert!(listing.validate().is_ok(), "{}", listing);
+    assert_eq!(listing.to_string(), "has_gps:=true is_companion:=false order_by:=created_at limit:=10 page:=2");
+
+    // asked for explicitly, left alone
+    let query = "is_companion:=true limit:=10".parse::<common::media_query::media_query::MediaQuery>().unwrap();
+    assert_eq!(query.without_companions().to_string(), "is_companion:=true limit:=10");
+
+    let query = "".parse::<common::media_query::media_query::MediaQuery>().unwrap();
+    assert_eq!(query.without_companions().to_string(), "is_companion:=false");
+}
+
+#[test]
+pub fn media_query_is_paged() {
+    let query = "has_gps:=true order_by:=created_at".parse::<common::media_query::media_query::MediaQuery>().unwrap();
+    assert!(!query.is_paged());
+    let query = "has_gps:=true limit:=10".parse::<common::media_query::media_query::MediaQuery>().unwrap();
+    assert!(query.is_paged());
+    let query = "limit:=10 page:=2".parse::<common::media_query::media_query::MediaQuery>().unwrap();
+    assert!(query.is_paged());
+    assert!(!query.to_count_query().is_paged());
+}
+
+#[test]
+pub fn test_transcript_search() {
+    // segments are matched the way sqlite matches the whole transcript
+    assert!(common::media_query::DSLString::Like.matches("%hello there%", "Well, HELLO there!"));
+    assert!(common::media_query::DSLString::Like.matches("h_llo%", "hallo world"));
+    assert!(!common::media_query::DSLString::Like.matches("h_llo%", "hllo world"));
+    assert!(!common::media_query::DSLString::Like.matches("%a%b%c", "aXXbYYcZ"));
+    assert!(common::media_query::DSLString::NotLike.matches("%kenobi%", "general"));
+    assert!(!common::media_query::DSLString::Equal.matches("Hello", "hello"));
+}
@@ -0,0 +1,53 @@
+use common::geocoding::{Geocoder, Place};
+
+// trimmed rows from cities1000.txt, admin1CodesASCII.txt and countryInfo.txt
+const CITIES: &str = "\
+2950159\tBerlin\tBerlin\t\t52.52437\t13.41053\tP\tPPLC\tDE\t\t16\t00\t11000\t11000000\t3426354\t74\t43\tEurope/Berlin\t2022-11-07
+2867714\tMunich\tMunich\t\t48.13743\t11.57549\tP\tPPLA\tDE\t\t02\t091\t09162\t09162000\t1260391\t\t524\tEurope/Berlin\t2023-10-12
+5391959\tSan Francisco\tSan Francisco\t\t37.77493\t-122.41942\tP\tPPLA2\tUS\t\tCA\t075\t67000\t\t864816\t16\t28\tAmerica/Los_Angeles\t2022-09-12
+2193733\tAuckland\tAuckland\t\t-36.84853\t174.76349\tP\tPPLA\tNZ\t\tE7\t\t\t\t417910\t\t26\tPacific/Auckland\t2022-09-12
+4032402\tApia\tApia\t\t-13.83333\t-171.76666\tP\tPPLC\tWS\t\t11\t\t\t\t40407\t\t2\tPacific/Apia\t2019-09-05
+";
+const ADMIN1: &str = "DE.16\tBerlin\tBerlin\t2950157\nDE.02\tBavaria\tBavaria\t2951839\nUS.CA\tCalifornia\tCalifornia\t5332921\n";
+const COUNTRIES: &str = "#ISO\tISO3\tISO-Numeric\tfips\tCountry\nDE\tDEU\t276\tGM\tGermany\nUS\tUSA\t840\tUS\tUnited States\n";
+
+#[test]
+fn nearest_city() {
+    let geocoder = Geocoder::parse(CITIES, Some(ADMIN1), Some(COUNTRIES)).unwrap();
+    assert_eq!(geocoder.len(), 5);
+
+    // Potsdamer Platz
+    assert_eq!(geocoder.lookup(52.5096, 13.3760, 50.0), Some(Place {
+        country: "Germany".to_string(),
+        region: Some("Berlin".to_string()),
+        city: "Berlin".to_string(),
+    }));
+    // Oakland is across the bay
+    assert_eq!(geocoder.lookup(37.8044, -122.2712, 50.0).map(|p| p.city), Some("San Francisco".to_string()));
+    // the middle of the Atlantic
+    assert_eq!(geocoder.lookup(30.0, -40.0, 50.0), None);
+    assert_eq!(geocoder.lookup(91.0, 0.0, 50.0), None);
+}
+
+#[test]
+fn missing_names_fall_back_to_codes() {
+    let geocoder = Geocoder::parse(CITIES, None, None).unwrap();
+    assert_eq!(geocoder.lookup(-36.85, 174.76, 50.0), Some(Place {
+        country: "NZ".to_string(),
+        region: None,
+        city: "Auckland".to_string(),
+    }));
+}
+
+#[test]
+fn antimeridian() {
+    let geocoder = Geocoder::parse(CITIES, None, None).unwrap();
+    // Apia is at -171.8, a search from the other side of the line has to wrap around
+    assert_eq!(geocoder.lookup(-13.8, 179.9, 1000.0).map(|p| p.city), Some("Apia".to_string()));
+}
+
+#[test]
+fn invalid_cities() {
+    assert!(Geocoder::parse("1\tBerlin\tBerlin", None, None).is_err());
+    assert!(Geocoder::parse("1\tBerlin\tBerlin\t\tnorth\t13.4\tP\tPPLC\tDE\t\t16", None, None).is_err());
+}
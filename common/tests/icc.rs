@@ -0,0 +1,35 @@
+use common::media_processors::icc::{convert_to_srgb, parse_profile, to_srgb, IccError};
+use common::media_processors::RgbImage;
+use moxcms::ColorProfile;
+
+fn pixels(values: &[[u8; 3]]) -> RgbImage {
+    RgbImage::from_raw(values.len() as u32, 1, values.concat()).unwrap()
+}
+
+#[test]
+fn display_p3_to_srgb() {
+    let icc = ColorProfile::new_display_p3().encode().unwrap();
+    let profile = parse_profile(&icc).expect("failed to parse Display P3 profile");
+
+    // sRGB's red is (234, 51, 35) in Display P3, grey is the same in both
+    let mut image = pixels(&[[234, 51, 35], [128, 128, 128]]);
+    to_srgb(&mut image, &profile).expect("failed to convert to sRGB");
+    let close = |actual: &[u8], expected: [u8; 3]| actual.iter().zip(expected).all(|(a, e)| a.abs_diff(e) <= 1);
+    assert!(close(&image.get_pixel(0, 0).0, [255, 0, 0]), "{:?}", image.get_pixel(0, 0));
+    assert_eq!(image.get_pixel(1, 0).0, [128, 128, 128]);
+}
+
+#[test]
+fn unsupported_profiles_leave_pixels_alone() {
+    assert!(parse_profile(b"not a profile").is_err());
+
+    let original = pixels(&[[234, 51, 35]]);
+    let mut image = original.clone();
+    let grey = ColorProfile::new_gray_with_gamma(2.2);
+    assert!(matches!(to_srgb(&mut image, &grey), Err(IccError::UnsupportedColorSpace(_))));
+    convert_to_srgb(&mut image, Some(grey));
+    assert_eq!(image, original);
+
+    convert_to_srgb(&mut image, None);
+    assert_eq!(image, original);
+}
@@ -0,0 +1,54 @@
+use common::media_processors::json_sidecar::{extensionless_sidecar_names, read_sidecar, sidecar_names, sidecar_path};
+
+#[test]
+fn sidecar_names_move_the_counter() {
+    let names = sidecar_names("IMG_20200101_120000(1).jpg");
+    assert_eq!(names[0], "IMG_20200101_120000.jpg(1).json");
+    assert!(names.contains(&"IMG_20200101_120000(1).jpg.json".to_string()));
+    assert!(names.contains(&"IMG_20200101_120000.jpg.supplemental-metadata(1).json".to_string()));
+
+    // not a counter
+    assert_eq!(sidecar_names("IMG_(a).jpg")[0], "IMG_(a).jpg.json");
+}
+
+#[test]
+fn sidecar_names_truncate() {
+    let names = sidecar_names("Screenshot_20201201-123456_Some Long Application Name.png");
+    // the supplemental name truncates to the same thing
+    assert_eq!(names, vec!["Screenshot_20201201-123456_Some Long Applicati.json"]);
+
+    let names = sidecar_names("Screenshot_20201201-123456_Some Long Application Name(2).png");
+    assert_eq!(names[0], "Screenshot_20201201-123456_Some Long Applicati(2).json");
+}
+
+#[test]
+fn sidecar_names_of_edits() {
+    let names = sidecar_names("IMG_0001-edited.jpg");
+    assert_eq!(names[0], "IMG_0001-edited.jpg.json");
+    assert!(names.contains(&"IMG_0001.jpg.json".to_string()));
+    // never without the extension
+    assert!(!names.contains(&"IMG_0001.json".to_string()));
+    assert_eq!(extensionless_sidecar_names("IMG_0001-edited(1).jpg"), vec!["IMG_0001-edited(1).json", "IMG_0001(1).json"]);
+}
+
+#[test]
+fn extensionless_sidecar_only_for_its_file() {
+    let dir = std::env::temp_dir().join(format!("kaleidoscope-json-sidecar-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let (heic, mov) = (dir.join("IMG_0001.HEIC"), dir.join("IMG_0001.MOV"));
+    std::fs::write(&heic, b"").unwrap();
+    std::fs::write(&mov, b"").unwrap();
+    std::fs::write(dir.join("IMG_0001.json"), r#"{"title": "IMG_0001.HEIC", "photoTakenTime": {"timestamp": "1600000000"}}"#).unwrap();
+
+    // the Live Photo's video doesn't get the still's sidecar
+    assert_eq!(sidecar_path(&heic), Some(dir.join("IMG_0001.json")));
+    assert_eq!(sidecar_path(&mov), None);
+    assert!(read_sidecar(&heic).unwrap().unwrap().taken_at.is_some());
+    assert!(read_sidecar(&mov).unwrap().is_none());
+
+    // named after the file, no need to check what's in it
+    std::fs::write(dir.join("IMG_0001.MOV.json"), r#"{"photoTakenTime": {"timestamp": "1600000000"}}"#).unwrap();
+    assert_eq!(sidecar_path(&mov), Some(dir.join("IMG_0001.MOV.json")));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,80 @@
+use std::fs::File;
+use std::path::PathBuf;
+use common::media_processors::format::standard::Standard;
+
+const JPEG: &[u8] = b"\xff\xd8\xff\xe0\x00\x10JFIF\x00\x01\x01\x00\x00\x01\x00\x01\x00\x00\xff\xd9";
+const MARKER: &[u8] = b"MotionPhoto_Data";
+
+fn mp4(len: usize) -> Vec<u8> {
+    let mut mp4 = vec![0, 0, 0, 0x18];
+    mp4.extend_from_slice(b"ftypmp42");
+    mp4.resize(len, 0);
+    mp4
+}
+
+fn write(name: &str, data: &[u8]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("kaleidoscope-motion-photo-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    std::fs::write(&path, data).unwrap();
+    path
+}
+
+/// a JPEG, Samsung's record for the video, the video, then the directory pointing back at the record
+fn samsung(video_len: usize) -> (Vec<u8>, u64) {
+    let mut data = JPEG.to_vec();
+    let record = data.len() as u32;
+    data.extend_from_slice(&[0, 0, 0x30, 0x0a]);
+    data.extend_from_slice(&(MARKER.len() as u32).to_le_bytes());
+    data.extend_from_slice(MARKER);
+    let video = data.len() as u64;
+    data.extend_from_slice(&mp4(video_len));
+
+    let directory = data.len() as u32;
+    data.extend_from_slice(b"SEFH");
+    data.extend_from_slice(&106u32.to_le_bytes());
+    data.extend_from_slice(&1u32.to_le_bytes());
+    data.extend_from_slice(&[0, 0, 0x30, 0x0a]);
+    data.extend_from_slice(&(directory - record).to_le_bytes());
+    data.extend_from_slice(&(directory - record).to_le_bytes());
+    data.extend_from_slice(&24u32.to_le_bytes());
+    data.extend_from_slice(b"SEFT");
+    (data, video)
+}
+
+#[test]
+fn samsung_directory() {
+    // further from the end than the marker search looks, only the directory finds it
+    let (data, video) = samsung(2 * 1024 * 1024);
+    let path = write("samsung.jpg", &data);
+
+    let mut file = File::open(&path).unwrap();
+    assert_eq!(Standard::samsung_directory_offset(&mut file, data.len() as u64).unwrap(), Some(video));
+    assert_eq!(Standard::motion_photo_offset(&path, JPEG).unwrap(), Some(video as u32));
+
+    // a broken directory falls back to the last marker
+    let (mut data, video) = samsung(1024);
+    let len = data.len();
+    data[len - 8..len - 4].copy_from_slice(&1000u32.to_le_bytes());
+    let path = write("samsung-broken.jpg", &data);
+    let mut file = File::open(&path).unwrap();
+    assert_eq!(Standard::samsung_directory_offset(&mut file, data.len() as u64).unwrap(), None);
+    assert_eq!(Standard::motion_photo_offset(&path, JPEG).unwrap(), Some(video as u32));
+}
+
+#[test]
+fn google_micro_video() {
+    let mut data = JPEG.to_vec();
+    let video = data.len();
+    data.extend_from_slice(&mp4(4096));
+    let xmp = format!(r#"<rdf:Description GCamera:MicroVideo="1" GCamera:MicroVideoOffset="{}"/>"#, data.len() - video);
+    let path = write("google.jpg", &data);
+    assert_eq!(Standard::motion_photo_offset(&path, xmp.as_bytes()).unwrap(), Some(video as u32));
+
+    // an offset that doesn't land on an MP4 isn't trusted
+    let xmp = format!(r#"<rdf:Description GCamera:MicroVideoOffset="{}"/>"#, data.len() - video - 4);
+    assert_eq!(Standard::motion_photo_offset(&path, xmp.as_bytes()).unwrap(), None);
+
+    let path = write("still.jpg", JPEG);
+    assert_eq!(Standard::motion_photo_offset(&path, JPEG).unwrap(), None);
+}
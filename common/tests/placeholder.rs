@@ -0,0 +1,39 @@
+use common::media_processors::{blurhash, palette, RgbImage};
+use common::media_processors::palette::Color;
+
+// a horizontal red ramp, a vertical blue ramp and a constant green
+fn gradient() -> RgbImage {
+    RgbImage::from_fn(16, 12, |x, y| image::Rgb([(x * 255 / 15) as u8, 128, (y * 255 / 11) as u8]))
+}
+
+#[test]
+fn blurhash_matches_reference() {
+    // what the reference implementation gives for the same pixels
+    assert_eq!(blurhash::encode(&gradient(), blurhash::X_COMPONENTS, blurhash::Y_COMPONENTS), "L$Hw+m2twxogs_SdjtfhfTfRfQfR");
+    assert_eq!(blurhash::encode(&gradient(), 1, 1), "00Hw+m");
+}
+
+#[test]
+fn dominant_colors_by_share() {
+    // half red, 30% blue, 20% white
+    let image = RgbImage::from_fn(10, 10, |x, _| match x {
+        0..=4 => image::Rgb([255, 0, 0]),
+        5..=7 => image::Rgb([0, 0, 255]),
+        _ => image::Rgb([255, 255, 255]),
+    });
+    let colors = palette::dominant_colors(&image, palette::PALETTE_SIZE);
+    let expected = vec![
+        (Color { r: 255, g: 0, b: 0 }, 0.5),
+        (Color { r: 0, g: 0, b: 255 }, 0.3),
+        (Color { r: 255, g: 255, b: 255 }, 0.2),
+    ];
+    assert_eq!(colors, expected);
+
+    // the same image always gives the same palette
+    assert_eq!(palette::dominant_colors(&image, palette::PALETTE_SIZE), colors);
+    let colors = palette::dominant_colors(&gradient(), palette::PALETTE_SIZE);
+    assert_eq!(palette::dominant_colors(&gradient(), palette::PALETTE_SIZE), colors);
+    assert!(!colors.is_empty() && colors.len() <= palette::PALETTE_SIZE);
+
+    assert!(palette::dominant_colors(&RgbImage::new(0, 0), palette::PALETTE_SIZE).is_empty());
+}
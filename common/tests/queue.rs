@@ -0,0 +1,177 @@
+use std::str::FromStr;
+use chrono::Duration;
+use common::models::queue::{Queue, QueueFailed, PRIORITY_USER};
+use common::scan_config::QueueConfig;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::SqlitePool;
+
+const TASK: &str = "thumbnail";
+
+// a single connection that's never closed, every connection to :memory: is its own database
+async fn pool() -> SqlitePool {
+    // queue rows point at media that these tests don't need
+    let options = SqliteConnectOptions::from_str("sqlite::memory:").unwrap().foreign_keys(false);
+    let pool = SqlitePoolOptions::new()
+        .max_connections(1)
+        .idle_timeout(None)
+        .max_lifetime(None)
+        .connect_with(options)
+        .await
+        .unwrap();
+    sqlx::migrate!("../db/migrations").run(&pool).await.unwrap();
+    pool
+}
+
+async fn queued(pool: &SqlitePool, media_id: i32, priority: i32, age: i64) -> Queue {
+    let mut queue = Queue::new(media_id, TASK).with_priority(priority);
+    queue.created_at -= Duration::seconds(age);
+    queue.create(pool).await.unwrap();
+    queue
+}
+
+#[tokio::test]
+async fn lease_by_priority_then_age() {
+    let pool = pool().await;
+    queued(&pool, 1, 0, 60).await;
+    queued(&pool, 2, 0, 120).await;
+    queued(&pool, 3, PRIORITY_USER, 0).await;
+
+    let lease = Duration::minutes(10);
+    let order: Vec<i32> = [
+        Queue::lease_next(&pool, TASK, &[], lease).await.unwrap(),
+        Queue::lease_next(&pool, TASK, &[], lease).await.unwrap(),
+        Queue::lease_next(&pool, TASK, &[], lease).await.unwrap(),
+    ].into_iter().map(|q| q.unwrap().media_id).collect();
+    assert_eq!(order, vec![3, 2, 1]);
+
+    // leased items stay queued but hidden
+    assert!(Queue::lease_next(&pool, TASK, &[], lease).await.unwrap().is_none());
+    assert_eq!(Queue::count(&pool, TASK).await.unwrap(), 3);
+    assert_eq!(Queue::count_available(&pool, TASK, &[]).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn expired_lease_is_taken_over() {
+    let pool = pool().await;
+    queued(&pool, 1, 0, 0).await;
+
+    // a runner that died, its lease already ran out
+    let mut first = Queue::lease_next(&pool, TASK, &[], Duration::seconds(-1)).await.unwrap().unwrap();
+    assert_eq!(first.attempts, 1);
+    let mut second = Queue::lease_next(&pool, TASK, &[], Duration::minutes(10)).await.unwrap().unwrap();
+    assert_eq!((second.id, second.attempts), (first.id, 2));
+
+    // only the current holder can keep it
+    assert!(!first.renew(&pool, Duration::minutes(10)).await.unwrap());
+    assert!(second.renew(&pool, Duration::minutes(10)).await.unwrap());
+    assert!(Queue::lease_next(&pool, TASK, &[], Duration::minutes(10)).await.unwrap().is_none());
+}
+
+#[tokio::test]
+async fn retry_later_and_release() {
+    let pool = pool().await;
+    queued(&pool, 1, 0, 0).await;
+    let lease = Duration::minutes(10);
+
+    let mut queue = Queue::lease_next(&pool, TASK, &[], lease).await.unwrap().unwrap();
+    queue.retry_later(&pool, "decode failed", Duration::hours(1)).await.unwrap();
+    assert!(Queue::lease_next(&pool, TASK, &[], lease).await.unwrap().is_none());
+
+    let mut queue = Queue::from_media_id(&pool, TASK, 1).await.unwrap().unwrap();
+    assert_eq!(queue.last_error.as_deref(), Some("decode failed"));
+    queue.retry_later(&pool, "decode failed", Duration::zero()).await.unwrap();
+
+    let mut queue = Queue::lease_next(&pool, TASK, &[], lease).await.unwrap().unwrap();
+    assert_eq!(queue.attempts, 2);
+    // an interrupted run doesn't count
+    queue.release(&pool).await.unwrap();
+    let queue = Queue::lease_next(&pool, TASK, &[], lease).await.unwrap().unwrap();
+    assert_eq!(queue.attempts, 2);
+}
+
+#[tokio::test]
+async fn replace_keeps_leased_items() {
+    let pool = pool().await;
+    let waiting = queued(&pool, 1, 0, 60).await;
+    queued(&pool, 2, 0, 0).await;
+
+    // a waiting item is replaced
+    Queue::replace(&pool, TASK, 1, PRIORITY_USER).await.unwrap();
+    let replaced = Queue::from_media_id(&pool, TASK, 1).await.unwrap().unwrap();
+    assert_ne!(replaced.id, waiting.id);
+    assert_eq!(replaced.priority, PRIORITY_USER);
+
+    // a leased one stays with its runner
+    let mut leased = Queue::lease_next(&pool, TASK, &[], Duration::minutes(10)).await.unwrap().unwrap();
+    assert_eq!(leased.media_id, 1);
+    Queue::replace(&pool, TASK, 1, 0).await.unwrap();
+    assert_eq!(Queue::count(&pool, TASK).await.unwrap(), 2);
+    assert!(leased.renew(&pool, Duration::minutes(10)).await.unwrap());
+    assert_eq!(Queue::from_media_id(&pool, TASK, 1).await.unwrap().unwrap().priority, PRIORITY_USER);
+
+    // and takes the higher priority
+    let leased = Queue::lease_next(&pool, TASK, &[], Duration::minutes(10)).await.unwrap().unwrap();
+    assert_eq!(leased.media_id, 2);
+    Queue::replace(&pool, TASK, 2, PRIORITY_USER).await.unwrap();
+    let folded = Queue::from_media_id(&pool, TASK, 2).await.unwrap().unwrap();
+    assert_eq!((folded.id, folded.attempts, folded.priority), (leased.id, 1, PRIORITY_USER));
+}
+
+#[test]
+fn backoff_doubles_up_to_max() {
+    let config = QueueConfig {
+        backoff_seconds: 60,
+        max_backoff_seconds: 600,
+        ..Default::default()
+    };
+    assert_eq!(config.backoff(1), Duration::seconds(60));
+    assert_eq!(config.backoff(2), Duration::seconds(120));
+    assert_eq!(config.backoff(4), Duration::seconds(480));
+    assert_eq!(config.backoff(5), Duration::seconds(600));
+    assert_eq!(config.backoff(100), Duration::seconds(600));
+}
+
+#[tokio::test]
+async fn fail_and_requeue() {
+    let pool = pool().await;
+    queued(&pool, 1, 0, 0).await;
+
+    let queue = Queue::lease_next(&pool, TASK, &[], Duration::minutes(10)).await.unwrap().unwrap();
+    queue.fail(&pool, "out of memory").await.unwrap();
+    assert_eq!(Queue::count(&pool, TASK).await.unwrap(), 0);
+
+    let failed = QueueFailed::all(&pool, Some(TASK)).await.unwrap();
+    assert_eq!(failed.len(), 1);
+    assert_eq!((failed[0].media_id, failed[0].attempts), (1, 1));
+    assert_eq!(failed[0].last_error.as_deref(), Some("out of memory"));
+
+    let queue = failed[0].requeue(&pool).await.unwrap();
+    assert_eq!(queue.attempts, 0);
+    assert!(QueueFailed::all(&pool, Some(TASK)).await.unwrap().is_empty());
+    assert_eq!(Queue::count_available(&pool, TASK, &[]).await.unwrap(), 1);
+}
+
+#[tokio::test]
+async fn failed_dependency_fails_dependents() {
+    let pool = pool().await;
+    let dependencies = [TASK];
+    queued(&pool, 1, 0, 0).await;
+    Queue::new(1, "vision_ocr").create(&pool).await.unwrap();
+    Queue::new(2, "vision_ocr").create(&pool).await.unwrap();
+
+    // media 1 waits for its thumbnail, media 2 has none queued
+    assert_eq!(Queue::count_available(&pool, "vision_ocr", &dependencies).await.unwrap(), 1);
+    let thumbnail = Queue::lease_next(&pool, TASK, &[], Duration::minutes(10)).await.unwrap().unwrap();
+    thumbnail.fail(&pool, "corrupt file").await.unwrap();
+    assert_eq!(Queue::count_available(&pool, "vision_ocr", &dependencies).await.unwrap(), 1);
+
+    assert_eq!(Queue::fail_blocked(&pool, "vision_ocr", &dependencies).await.unwrap(), 1);
+    assert!(Queue::from_media_id(&pool, "vision_ocr", 1).await.unwrap().is_none());
+    assert!(Queue::failed(&pool, 1, &["vision_ocr"]).await.unwrap());
+
+    // requeueing the thumbnail brings what waited on it back
+    let failed = QueueFailed::all(&pool, Some(TASK)).await.unwrap();
+    failed[0].requeue(&pool).await.unwrap();
+    assert!(Queue::from_media_id(&pool, "vision_ocr", 1).await.unwrap().is_some());
+    assert!(!Queue::failed(&pool, 1, &["vision_ocr"]).await.unwrap());
+}
@@ -0,0 +1,50 @@
+use common::media_processors::format::{resample, AnyFormat};
+use common::media_processors::RgbImage;
+use common::scan_config::AppConfig;
+
+fn image(width: u32, height: u32) -> RgbImage {
+    RgbImage::from_fn(width, height, |x, y| image::Rgb([(x % 256) as u8, (y % 256) as u8, 128]))
+}
+
+#[test]
+fn resample_keeps_aspect_ratio() {
+    let landscape = resample(&image(300, 200), Some(150));
+    assert_eq!(landscape.dimensions(), (150, 100));
+
+    let portrait = resample(&image(200, 300), Some(150));
+    assert_eq!(portrait.dimensions(), (100, 150));
+
+    // rounded, never down to nothing
+    assert_eq!(resample(&image(333, 100), Some(100)).dimensions(), (100, 30));
+    assert_eq!(resample(&image(1000, 1), Some(100)).dimensions(), (100, 1));
+}
+
+#[test]
+fn resample_never_upscales() {
+    let original = image(300, 200);
+    // native resolution
+    assert_eq!(resample(&original, None), original);
+    assert_eq!(resample(&original, Some(300)), original);
+    assert_eq!(resample(&original, Some(1000)), original);
+}
+
+#[test]
+fn decode_then_resample() {
+    let dir = std::env::temp_dir().join(format!("kaleidoscope-resample-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("gradient.png");
+    let original = image(300, 200);
+    original.save(&path).unwrap();
+
+    let format = AnyFormat::try_new(path).expect("png is supported");
+    let config = AppConfig::default();
+    // lossless and without a color profile, so exactly what was written
+    assert_eq!(format.decode(None, &config).unwrap(), original);
+
+    let decoded = format.decode(Some(150), &config).unwrap();
+    // formats that can decode smaller may, but never smaller than asked for
+    assert!(decoded.width().max(decoded.height()) >= 150);
+    assert_eq!(resample(&decoded, Some(150)).dimensions(), (150, 100));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,61 @@
+use chrono::{NaiveDateTime, NaiveTime};
+use common::schedule::{Cron, QuietHours};
+
+fn at(date: &str) -> NaiveDateTime {
+    NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap()
+}
+
+#[test]
+fn cron_next_daily() {
+    let cron: Cron = "30 3 * * *".parse().unwrap();
+    assert_eq!(cron.next_after(at("2025-06-01 02:00")), Some(at("2025-06-01 03:30")));
+    // strictly after, a run at exactly 03:30 schedules tomorrow's
+    assert_eq!(cron.next_after(at("2025-06-01 03:30")), Some(at("2025-06-02 03:30")));
+}
+
+#[test]
+fn cron_steps_ranges_and_weekdays() {
+    // every 15 minutes from 8 to 18 on weekdays
+    let cron: Cron = "*/15 8-18 * * 1-5".parse().unwrap();
+    // saturday evening -> monday morning
+    assert_eq!(cron.next_after(at("2025-06-07 19:00")), Some(at("2025-06-09 08:00")));
+    assert_eq!(cron.next_after(at("2025-06-09 08:01")), Some(at("2025-06-09 08:15")));
+
+    // sunday as 7
+    let cron: Cron = "0 0 * * 7".parse().unwrap();
+    assert_eq!(cron.next_after(at("2025-06-02 00:00")), Some(at("2025-06-08 00:00")));
+
+    // either day field matches when both are restricted: the 1st or any monday
+    let cron: Cron = "0 12 1 * 1".parse().unwrap();
+    assert_eq!(cron.next_after(at("2025-05-27 00:00")), Some(at("2025-06-01 12:00")));
+    assert_eq!(cron.next_after(at("2025-06-01 13:00")), Some(at("2025-06-02 12:00")));
+}
+
+#[test]
+fn cron_invalid() {
+    assert!("* * * *".parse::<Cron>().is_err());
+    assert!("60 * * * *".parse::<Cron>().is_err());
+    assert!("5-1 * * * *".parse::<Cron>().is_err());
+    assert!("*/0 * * * *".parse::<Cron>().is_err());
+    // valid, but there's no 31st of february
+    let cron: Cron = "0 0 31 2 *".parse().unwrap();
+    assert_eq!(cron.next_after(at("2025-01-01 00:00")), None);
+}
+
+#[test]
+fn quiet_hours_wrap_midnight() {
+    let time = |h, m| NaiveTime::from_hms_opt(h, m, 0).unwrap();
+
+    let night: QuietHours = "22:00-07:00".parse().unwrap();
+    assert!(night.contains(time(23, 0)));
+    assert!(night.contains(time(3, 0)));
+    assert!(!night.contains(time(7, 0)));
+    assert!(!night.contains(time(12, 0)));
+
+    let day: QuietHours = "08:00-23:30".parse().unwrap();
+    assert!(day.contains(time(8, 0)));
+    assert!(!day.contains(time(23, 30)));
+    assert_eq!(day.to_string(), "08:00-23:30");
+
+    assert!("8-23".parse::<QuietHours>().is_err());
+}
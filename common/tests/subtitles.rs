@@ -0,0 +1,41 @@
+use common::subtitles::{parse, to_srt, to_vtt};
+
+#[test]
+pub fn test_parse_srt() {
+    let srt = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>Hello</i>\r\nthere\r\n\r\n2\r\n00:01:03,250 --> 00:01:04,000\r\n{\\an8}General Kenobi\r\n";
+    let cues = parse(srt).expect("failed to parse srt");
+    assert_eq!(cues, vec![
+        (1.0, 2.5, "Hello there".to_string()),
+        (63.25, 64.0, "General Kenobi".to_string()),
+    ]);
+}
+
+#[test]
+pub fn test_parse_vtt() {
+    let vtt = "WEBVTT\nKind: captions\n\nNOTE a comment\n\nintro\n00:01.000 --> 00:02.000 align:start\nfoo &amp; <c.yellow>bar</c>\n\n01:00:00.000 --> 01:00:01.000\nbaz\n";
+    let cues = parse(vtt).expect("failed to parse vtt");
+    assert_eq!(cues, vec![
+        (1.0, 2.0, "foo & bar".to_string()),
+        (3600.0, 3601.0, "baz".to_string()),
+    ]);
+}
+
+#[test]
+pub fn test_parse_invalid() {
+    assert!(parse("not subtitles at all").is_err());
+    assert_eq!(parse("").unwrap(), vec![]);
+}
+
+#[test]
+pub fn test_to_vtt() {
+    let cues = vec![(1.5, 3723.004, "one\n\ntwo".to_string())];
+    assert_eq!(to_vtt(&cues), "WEBVTT\n\n00:00:01.500 --> 01:02:03.004\none\ntwo\n");
+    assert_eq!(parse(&to_vtt(&cues)).unwrap(), vec![(1.5, 3723.004, "one two".to_string())]);
+}
+
+#[test]
+pub fn test_to_srt() {
+    let cues = vec![(1.5, 3723.004, "one".to_string()), (4000.0, 4001.0, "two".to_string())];
+    assert_eq!(to_srt(&cues), "1\n00:00:01,500 --> 01:02:03,004\none\n\n2\n01:06:40,000 --> 01:06:41,000\ntwo\n");
+    assert_eq!(parse(&to_srt(&cues)).unwrap(), cues);
+}
@@ -0,0 +1,109 @@
+use std::time::{Duration, UNIX_EPOCH};
+use common::media_processors::format::{AnyFormat, NO_SIDECARS};
+use common::media_processors::xmp::{update_sidecar, Xmp};
+
+const SIDECAR: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about=""
+    xmlns:xmp="http://ns.adobe.com/xap/1.0/"
+    xmlns:dc="http://purl.org/dc/elements/1.1/"
+    xmlns:lr="http://ns.adobe.com/lightroom/1.0/"
+    xmp:Rating="4">
+   <xmp:Label>Red</xmp:Label>
+   <dc:subject>
+    <rdf:Bag>
+     <rdf:li>New York</rdf:li>
+     <rdf:li>日本</rdf:li>
+     <rdf:li>old</rdf:li>
+    </rdf:Bag>
+   </dc:subject>
+   <lr:hierarchicalSubject>
+    <rdf:Bag>
+     <rdf:li>Places|New York</rdf:li>
+     <rdf:li>Things|old</rdf:li>
+     <rdf:li>Kaleidoscope|Albums|Trip</rdf:li>
+    </rdf:Bag>
+   </lr:hierarchicalSubject>
+  </rdf:Description>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+
+fn strings(values: &[&str]) -> Vec<String> {
+    values.iter().map(|v| v.to_string()).collect()
+}
+
+#[test]
+pub fn test_update_sidecar_merges_keywords() {
+    let xmp = update_sidecar(SIDECAR, &strings(&["New_York", "beach"]), &strings(&["Summer"])).expect("failed to update sidecar");
+
+    // other tools' spelling survives, only the dropped tag goes
+    assert!(xmp.contains("<rdf:li>New York</rdf:li>"));
+    assert!(!xmp.contains("New_York"));
+    assert!(xmp.contains("<rdf:li>日本</rdf:li>"));
+    assert!(xmp.contains("<rdf:li>beach</rdf:li>"));
+    assert!(!xmp.contains("old"));
+
+    assert!(xmp.contains("<rdf:li>Places|New York</rdf:li>"));
+    assert!(xmp.contains("<rdf:li>Kaleidoscope|Albums|Summer</rdf:li>"));
+    assert!(!xmp.contains("Trip"));
+    assert_eq!(xmp.matches("<dc:subject>").count(), 1);
+    assert_eq!(xmp.matches("<lr:hierarchicalSubject>").count(), 1);
+
+    let parsed = Xmp::parse(&xmp).expect("failed to parse updated sidecar");
+    assert_eq!(parsed.rating, Some(4));
+    assert_eq!(parsed.label.as_deref(), Some("Red"));
+    assert_eq!(parsed.keywords, strings(&["New_York", "beach"]));
+
+    // writing the same tags again changes nothing
+    let again = update_sidecar(&xmp, &strings(&["New_York", "beach"]), &strings(&["Summer"])).expect("failed to update sidecar");
+    assert_eq!(again, xmp);
+}
+
+#[test]
+pub fn test_update_sidecar_self_closing_description() {
+    let sidecar = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+ <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+  <rdf:Description rdf:about="" xmlns:xmp="http://ns.adobe.com/xap/1.0/" xmp:Rating="2"/>
+ </rdf:RDF>
+</x:xmpmeta>
+"#;
+    let xmp = update_sidecar(sidecar, &strings(&["cat"]), &strings(&["Pets"])).expect("failed to update sidecar");
+    assert!(xmp.contains("</rdf:Description>"));
+    assert!(xmp.contains("xmlns:dc=\"http://purl.org/dc/elements/1.1/\""));
+    assert!(xmp.contains("xmlns:lr=\"http://ns.adobe.com/lightroom/1.0/\""));
+
+    let parsed = Xmp::parse(&xmp).expect("failed to parse updated sidecar");
+    assert_eq!(parsed.rating, Some(2));
+    assert_eq!(parsed.keywords, strings(&["cat"]));
+
+    // and removing everything leaves a valid document
+    let xmp = update_sidecar(&xmp, &[], &[]).expect("failed to update sidecar");
+    assert!(!xmp.contains("dc:subject>"));
+    assert!(!xmp.contains("lr:hierarchicalSubject>"));
+    assert_eq!(Xmp::parse(&xmp).expect("failed to parse updated sidecar").keywords, Vec::<String>::new());
+}
+
+#[test]
+pub fn test_sidecar_sources_change_with_sidecars() {
+    let dir = std::env::temp_dir().join(format!("kaleidoscope-sidecar-sources-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let photo = dir.join("IMG_0001.jpg");
+    std::fs::write(&photo, b"").unwrap();
+    let format = AnyFormat::try_new(photo).expect("jpg is supported");
+    assert_eq!(format.sidecar_sources(), NO_SIDECARS);
+
+    let sidecar = dir.join("IMG_0001.jpg.xmp");
+    std::fs::write(&sidecar, SIDECAR).unwrap();
+    let file = std::fs::File::options().write(true).open(&sidecar).unwrap();
+    file.set_modified(UNIX_EPOCH + Duration::from_secs(1_700_000_000)).unwrap();
+    let added = format.sidecar_sources();
+    assert_ne!(added, NO_SIDECARS);
+    assert_eq!(format.sidecar_sources(), added);
+
+    // edited by another tool, the photo itself untouched
+    file.set_modified(UNIX_EPOCH + Duration::from_secs(1_700_000_060)).unwrap();
+    assert_ne!(format.sidecar_sources(), added);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
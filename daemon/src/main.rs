@@ -1,6 +1,10 @@
-use common::ipc::{IpcFileRequest, IpcFileResponse, IpcRequest, QueueProgress};
+use common::ipc::{IpcFileRequest, IpcFileResponse, IpcQueueControlResponse, IpcQueueResponse, IpcRequest, QueueProgress, QueueState};
+use common::media_processors::format::pdf::Pdf;
+use common::media_processors::format::MediaType;
+use common::media_processors::xmp;
 use common::models::media::Media;
-use common::models::queue::Queue;
+use common::models::queue::{Queue, PRIORITY_USER};
+use common::models::stack::Stack;
 use common::scan_config::AppConfig;
 use nix::libc::pid_t;
 use once_cell::sync::Lazy;
@@ -9,9 +13,13 @@ use sqlx::SqlitePool;
 use std::fs::Permissions;
 use std::io::SeekFrom;
 use std::os::unix::fs::{MetadataExt, PermissionsExt};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
+use sqlx::types::chrono::Local;
+use tasks::control::QueueControl;
 use tasks::ops::RunProgress;
+use tasks::tasks::thumbnail::ThumbnailGenerationConfig;
 use tasks::tasks::AnyTask;
 use tokio::fs::File;
 use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, Take};
@@ -19,10 +27,13 @@ use tokio::net::{UnixListener, UnixStream};
 use tokio::process::Command;
 use tokio::sync::oneshot::Receiver;
 use tokio::sync::{mpsc, RwLock};
+use tokio::task::JoinHandle;
 
 static QUEUE_PROGRESS: Lazy<Arc<RwLock<QueueProgress>>> =
     Lazy::new(|| Arc::new(RwLock::new(QueueProgress::Initial)));
 
+static QUEUE_CONTROL: Lazy<Arc<QueueControl>> = Lazy::new(|| Arc::new(QueueControl::default()));
+
 #[tokio::main]
 async fn main() {
     let env_var = common::env::EnvVar::from_env();
@@ -57,6 +68,10 @@ async fn main() {
         panic!("client_user must not be root!");
     }
 
+    if let Err(e) = ThumbnailGenerationConfig::from_app_config(&config) {
+        panic!("{}", e);
+    }
+
     let pool = SqlitePool::connect(&format!("sqlite://{}", config.db_path))
         .await
         .unwrap();
@@ -73,6 +88,7 @@ async fn main() {
     config.canonicalize();
 
     let server_binary = "kaleidoscope-server";
+    let scan_binary = "scan";
 
     let (tx, rx) = tokio::sync::oneshot::channel();
 
@@ -80,10 +96,11 @@ async fn main() {
     println!("starting unix socket server");
     let mut handle = tokio::spawn(start_server(pool.clone(), config2, rx, env_var.dev_mode));
 
-    let _ = tokio::spawn(queue_runner(pool, config.clone()));
-
     let my_path = std::env::current_exe().unwrap();
     let my_dir = my_path.parent().unwrap();
+
+    let _ = tokio::spawn(scheduler(pool, config.clone(), config_path.clone(), my_dir.join(scan_binary)));
+
     let server_path = my_dir.join(server_binary);
     let server_path = server_path.to_str().unwrap();
     println!("starting command (kaleidoscope) server");
@@ -211,6 +228,39 @@ pub async fn handle_slave(
 
                 return_on_err!(writer.write_all(b"\n").await, dev_mode);
             }
+            IpcRequest::RenderPage { file, page, max_size } => {
+                let (res, bytes) = match handle_render_page_request(&config, &pool, &file, page, max_size).await {
+                    Ok((res, bytes)) => (res, Some(bytes)),
+                    Err(res) => (res, None),
+                };
+
+                return_on_err!(
+                    writer
+                        .write_all(serde_json::to_string(&res).unwrap().as_bytes())
+                        .await,
+                    dev_mode
+                );
+
+                return_on_err!(writer.write_all(b"\n").await, dev_mode);
+
+                if let Some(bytes) = bytes {
+                    return_on_err!(writer.write_all(&bytes).await, dev_mode);
+                }
+            }
+            IpcRequest::WriteSidecar { file } => {
+                let res = handle_write_sidecar_request(&config, &pool, &file)
+                    .await
+                    .unwrap_or_else(|res| res);
+
+                return_on_err!(
+                    writer
+                        .write_all(serde_json::to_string(&res).unwrap().as_bytes())
+                        .await,
+                    dev_mode
+                );
+
+                return_on_err!(writer.write_all(b"\n").await, dev_mode);
+            }
             IpcRequest::QueueProgress => {
                 let lock = QUEUE_PROGRESS.read().await;
                 let res = lock.clone();
@@ -223,6 +273,33 @@ pub async fn handle_slave(
                     dev_mode
                 );
 
+                return_on_err!(writer.write_all(b"\n").await, dev_mode);
+            }
+            IpcRequest::Enqueue { .. } | IpcRequest::ClearQueue { .. } | IpcRequest::RerunTask { .. } | IpcRequest::PurgeTask { .. } => {
+                let res = match handle_queue_request(&config, &pool, &req).await {
+                    Ok(count) => IpcQueueResponse::Success { count },
+                    Err(error) => IpcQueueResponse::Error { error },
+                };
+
+                return_on_err!(
+                    writer
+                        .write_all(serde_json::to_string(&res).unwrap().as_bytes())
+                        .await,
+                    dev_mode
+                );
+
+                return_on_err!(writer.write_all(b"\n").await, dev_mode);
+            }
+            IpcRequest::PauseQueue | IpcRequest::ResumeQueue | IpcRequest::DrainQueue | IpcRequest::CancelQueue | IpcRequest::CancelTask { .. } => {
+                let res = handle_queue_control_request(&req);
+
+                return_on_err!(
+                    writer
+                        .write_all(serde_json::to_string(&res).unwrap().as_bytes())
+                        .await,
+                    dev_mode
+                );
+
                 return_on_err!(writer.write_all(b"\n").await, dev_mode);
             }
         }
@@ -322,14 +399,246 @@ pub async fn handle_file_request(
     ))
 }
 
-pub async fn queue_runner(pool: SqlitePool, app_config: AppConfig) {
+pub async fn handle_write_sidecar_request(
+    app_config: &AppConfig,
+    pool: &SqlitePool,
+    req: &IpcFileRequest,
+) -> Result<IpcFileResponse, IpcFileResponse> {
+    if !app_config.write_sidecars {
+        return Err(IpcFileResponse::Error {
+            error: "writing sidecars is disabled".to_string(),
+        });
+    }
+
+    let (media, file) = file_request_permissions(app_config, pool, req).await?;
+
+    let file_size = file
+        .metadata()
+        .await
+        .map_err(|e| IpcFileResponse::Error {
+            error: format!("couldn't get metadata: {} - {:?}", media.path, e),
+        })?
+        .len();
+
+    let mut tags: Vec<String> = media.tags(pool).await.unwrap().into_iter().map(|t| t.tag).collect();
+    let mut albums = media.albums(pool).await.unwrap();
+
+    // a RAW+JPEG pair can share IMG_1234.xmp, whichever is written last mustn't drop the other's tags
+    let sidecar = xmp::sidecar_path(Path::new(&media.path));
+    if let (Some(sidecar), Some(stack)) = (&sidecar, Stack::from_media_id(pool, media.id).await.unwrap()) {
+        for member in stack.media(pool).await.unwrap() {
+            if member.id == media.id || xmp::sidecar_path(Path::new(&member.path)).as_ref() != Some(sidecar) {
+                continue;
+            }
+            for tag in member.tags(pool).await.unwrap() {
+                if !tags.contains(&tag.tag) {
+                    tags.push(tag.tag);
+                }
+            }
+            for album in member.albums(pool).await.unwrap() {
+                if !albums.contains(&album) {
+                    albums.push(album);
+                }
+            }
+        }
+    }
+
+    let path = media.path.clone();
+    tokio::task::spawn_blocking(move || xmp::write_sidecar(Path::new(&path), &tags, &albums))
+        .await
+        .unwrap()
+        .map_err(|e| IpcFileResponse::Error {
+            error: format!("couldn't write sidecar: {} - {:?}", media.path, e),
+        })?;
+
+    Ok(IpcFileResponse::Success {
+        file: IpcFileRequest {
+            db_id: req.db_id,
+            path: media.path.clone(),
+        },
+        file_size,
+        response_size: 0,
+    })
+}
+
+pub async fn handle_render_page_request(
+    app_config: &AppConfig,
+    pool: &SqlitePool,
+    req: &IpcFileRequest,
+    page: u16,
+    max_size: u32,
+) -> Result<(IpcFileResponse, Vec<u8>), IpcFileResponse> {
+    let (media, file) = file_request_permissions(app_config, pool, req).await?;
+
+    if media.media_type != MediaType::Pdf {
+        return Err(IpcFileResponse::Error {
+            error: format!("media is not a pdf: {}", media.path),
+        });
+    }
+
+    let file_size = file
+        .metadata()
+        .await
+        .map_err(|e| IpcFileResponse::Error {
+            error: format!("couldn't get metadata: {} - {:?}", media.path, e),
+        })?
+        .len();
+
+    // rendering is blocking and can take a while for large pages
+    let path = media.path.clone();
+    let config = app_config.clone();
+    let bytes = tokio::task::spawn_blocking(move || Pdf::render_page_jpeg(Path::new(&path), page, max_size, &config))
+        .await
+        .unwrap()
+        .map_err(|e| IpcFileResponse::Error {
+            error: format!("couldn't render page {}: {} - {:?}", page, media.path, e),
+        })?;
+
+    Ok((
+        IpcFileResponse::Success {
+            file: IpcFileRequest {
+                db_id: req.db_id,
+                path: media.path.clone(),
+            },
+            file_size,
+            response_size: bytes.len() as u64,
+        },
+        bytes,
+    ))
+}
+
+pub async fn handle_queue_request(app_config: &AppConfig, pool: &SqlitePool, req: &IpcRequest) -> Result<u32, String> {
+    match req {
+        IpcRequest::Enqueue { task, query, priority } => {
+            query.validate().map_err(|e| format!("invalid query: {}", e))?;
+            // every match, not just a page of them
+            let medias = Media::get_all(pool, &query.to_count_query()).await.map_err(|e| e.to_string())?;
+            tasks::ops::enqueue(&mut &*pool, &medias, task, *priority).await.map_err(|e| e.to_string())
+        }
+        IpcRequest::ClearQueue { task } => {
+            let task = AnyTask::name_from_str(task).map_err(|e| e.to_string())?;
+            let count = Queue::delete_by_task(pool, task).await.map_err(|e| e.to_string())?;
+            Ok(count as u32)
+        }
+        IpcRequest::RerunTask { media_id, task } => {
+            let task = AnyTask::name_from_str(task).map_err(|e| e.to_string())?;
+            let media = Media::from_id(pool, media_id).await.map_err(|e| format!("media not found: {}", e))?;
+            if !AnyTask::compatible(task, &media).await {
+                return Err(format!("media is not compatible with task '{}'", task));
+            }
+            tasks::ops::enqueue(&mut &*pool, &[media], task, PRIORITY_USER).await.map_err(|e| e.to_string())
+        }
+        IpcRequest::PurgeTask { task, query } => {
+            query.validate().map_err(|e| format!("invalid query: {}", e))?;
+            if query.is_paged() {
+                return Err("purge applies to every match, limit and page aren't allowed".to_string());
+            }
+            let mut medias = Media::get_all(pool, &query.to_count_query()).await.map_err(|e| e.to_string())?;
+            tasks::ops::purge(&mut &*pool, &mut medias, task, &app_config.tasks, app_config).await.map_err(|e| e.to_string())
+        }
+        _ => Err("not a queue request".to_string()),
+    }
+}
+
+pub fn handle_queue_control_request(req: &IpcRequest) -> IpcQueueControlResponse {
+    match req {
+        IpcRequest::PauseQueue => QUEUE_CONTROL.pause(),
+        IpcRequest::ResumeQueue => QUEUE_CONTROL.resume(),
+        IpcRequest::DrainQueue => QUEUE_CONTROL.drain(),
+        IpcRequest::CancelQueue => QUEUE_CONTROL.cancel(),
+        IpcRequest::CancelTask { task } => match AnyTask::name_from_str(task) {
+            Ok(task) => QUEUE_CONTROL.cancel_task(task),
+            Err(e) => return IpcQueueControlResponse::Error { error: e.to_string() },
+        },
+        _ => return IpcQueueControlResponse::Error { error: "not a queue control request".to_string() },
+    }
+    IpcQueueControlResponse::Success {
+        state: QUEUE_CONTROL.state(),
+        canceled_tasks: QUEUE_CONTROL.canceled_tasks(),
+    }
+}
+
+/// runs the queue at startup, then scans on `schedule.scan` and runs the queue again whenever it has new items
+///
+/// runs go on in the background, a scan that comes due during a long or paused run still happens,
+/// what it queues waits for the next run
+pub async fn scheduler(pool: SqlitePool, app_config: AppConfig, config_path: String, scan_path: PathBuf) {
+    let schedule = &app_config.schedule;
+    let interval = Duration::from_secs(schedule.queue_interval_seconds.max(1));
+    let mut next_scan = schedule.scan.as_ref().and_then(|cron| cron.next_after(Local::now().naive_local()));
+    let mut last = None;
+    let mut running: Option<JoinHandle<Result<(u32, u32), String>>> = None;
+
+    loop {
+        // quiet hours can start during a run, its workers check before every item
+        QUEUE_CONTROL.hold(schedule.held_tasks(Local::now().time()));
+
+        if running.as_ref().is_some_and(|run| run.is_finished()) {
+            let run = running.take().unwrap();
+            last = Some(run.await.unwrap_or_else(|e| Err(format!("queue run panicked: {}", e))));
+        }
+
+        if next_scan.is_some_and(|next| next <= Local::now().naive_local()) {
+            // a run reports its own progress
+            if running.is_none() {
+                *QUEUE_PROGRESS.write().await = QueueProgress::Scanning;
+            }
+            run_scan(&scan_path, &config_path).await;
+            next_scan = schedule.scan.as_ref().and_then(|cron| cron.next_after(Local::now().naive_local()));
+        }
+
+        if running.is_none() {
+            let mut held_tasks = schedule.held_tasks(Local::now().time()).to_vec();
+            held_tasks.extend(QUEUE_CONTROL.canceled_tasks());
+            let tasks: Vec<&'static str> = AnyTask::BACKGROUND_TASK_NAMES
+                .into_iter()
+                .filter(|task| !held_tasks.iter().any(|held| held.as_str() == *task))
+                .collect();
+
+            // paused, drained or canceled, nothing starts until it's resumed
+            let state = QUEUE_CONTROL.state();
+            let mut available = 0;
+            for task in &tasks {
+                match Queue::count_available(&pool, task, AnyTask::dependencies_from_str(task)).await {
+                    Ok(count) => available += count,
+                    Err(e) => eprintln!("couldn't count queue for {}: {:?}", task, e),
+                }
+            }
+            if available > 0 && state == QueueState::Running {
+                let (pool, app_config) = (pool.clone(), app_config.clone());
+                running = Some(tokio::spawn(async move { queue_runner(&pool, &app_config, &tasks).await }));
+            } else {
+                *QUEUE_PROGRESS.write().await = QueueProgress::Idle {
+                    last: last.clone(),
+                    next_scan,
+                    state,
+                    held_tasks,
+                };
+            }
+        }
+
+        let wait = next_scan
+            .and_then(|next| (next - Local::now().naive_local()).to_std().ok())
+            .map_or(interval, |until_scan| until_scan.min(interval));
+        tokio::time::sleep(wait).await;
+    }
+}
+
+async fn run_scan(scan_path: &Path, config_path: &str) {
+    println!("starting scheduled scan");
+    match Command::new(scan_path).arg(config_path).arg("--scan").status().await {
+        Ok(status) if status.success() => println!("scheduled scan complete"),
+        Ok(status) => eprintln!("scheduled scan failed: {}", status),
+        Err(e) => eprintln!("couldn't start scan {:?}: {:?}", scan_path, e),
+    }
+}
+
+pub async fn queue_runner(pool: &SqlitePool, app_config: &AppConfig, tasks: &[&'static str]) -> Result<(u32, u32), String> {
     let (progress_tx, mut progress_rx) = mpsc::channel(10);
 
-    let tasks = AnyTask::BACKGROUND_TASK_NAMES;
-    
     let mut total = 0;
     for task in tasks {
-        total += Queue::count(&pool, task).await.expect("couldn't count queue");
+        total += Queue::count_available(pool, task, AnyTask::dependencies_from_str(task)).await.map_err(|e| e.to_string())?;
     }
     
     {
@@ -339,9 +648,14 @@ pub async fn queue_runner(pool: SqlitePool, app_config: AppConfig) {
         };
     }
 
-    let handle = tokio::spawn(async move {
-        tasks::ops::run_queue(&mut &pool, &tasks, &app_config.tasks, &app_config.remote, &app_config, Some(progress_tx)).await
-    });
+    let handle = {
+        let pool = pool.clone();
+        let app_config = app_config.clone();
+        let tasks = tasks.to_vec();
+        tokio::spawn(async move {
+            tasks::ops::run_queue(&pool, &tasks, &app_config.tasks, &app_config.remote, &app_config, Some(progress_tx), Some(QUEUE_CONTROL.clone())).await
+        })
+    };
 
     while let Some(progress) = progress_rx.recv().await {
         let done = progress.done();
@@ -352,8 +666,12 @@ pub async fn queue_runner(pool: SqlitePool, app_config: AppConfig) {
         }
     }
 
-    let (success, failed) = handle.await.unwrap().unwrap();
+    let result = match handle.await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(e) => Err(format!("queue run panicked: {}", e)),
+    };
 
     let mut lock = QUEUE_PROGRESS.write().await;
-    *lock = QueueProgress::Done(Ok((success, failed)));
+    *lock = QueueProgress::Done(result.clone());
+    result
 }
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE media_extra DROP COLUMN transcode_version;
+ALTER TABLE media_extra DROP COLUMN transcode_format;
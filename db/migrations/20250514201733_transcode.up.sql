@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE media_extra ADD COLUMN transcode_version INT NOT NULL DEFAULT -1;
+ALTER TABLE media_extra ADD COLUMN transcode_format TEXT DEFAULT NULL;
@@ -0,0 +1,10 @@
+-- Add up migration script here
+CREATE TABLE media_metadata (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    media_id INTEGER NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    FOREIGN KEY (media_id) REFERENCES media(id) ON DELETE CASCADE
+);
+
+CREATE INDEX media_metadata_media_id ON media_metadata (media_id);
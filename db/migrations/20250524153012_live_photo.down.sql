@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TRIGGER media_companion_delete;
+DROP INDEX media_companion_of;
+ALTER TABLE media DROP COLUMN content_identifier;
+ALTER TABLE media DROP COLUMN companion_of;
+ALTER TABLE media DROP COLUMN motion_offset;
@@ -0,0 +1,12 @@
+-- Add up migration script here
+ALTER TABLE media ADD COLUMN content_identifier TEXT DEFAULT NULL;
+ALTER TABLE media ADD COLUMN companion_of INTEGER DEFAULT NULL;
+ALTER TABLE media ADD COLUMN motion_offset INTEGER DEFAULT NULL;
+
+CREATE INDEX media_companion_of ON media(companion_of);
+
+-- a companion whose still is gone goes back to being regular media
+CREATE TRIGGER media_companion_delete AFTER DELETE ON media
+BEGIN
+    UPDATE media SET companion_of = NULL WHERE companion_of = OLD.id;
+END;
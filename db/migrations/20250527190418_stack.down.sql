@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TRIGGER stack_media_delete;
+DROP TABLE stack_media;
+DROP TABLE stack;
@@ -0,0 +1,27 @@
+-- Add up migration script here
+CREATE TABLE stack (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    uuid TEXT NOT NULL UNIQUE,
+    cover_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY (cover_id) REFERENCES media(id)
+);
+
+CREATE TABLE stack_media (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    stack_id INTEGER NOT NULL,
+    media_id INTEGER NOT NULL UNIQUE,
+    FOREIGN KEY (stack_id) REFERENCES stack(id) ON DELETE CASCADE,
+    FOREIGN KEY (media_id) REFERENCES media(id) ON DELETE CASCADE
+);
+
+CREATE INDEX stack_media_stack_id ON stack_media (stack_id);
+
+-- when media goes away its stack gets a new cover, a stack of one isn't a stack
+CREATE TRIGGER stack_media_delete BEFORE DELETE ON media
+BEGIN
+    DELETE FROM stack_media WHERE media_id = OLD.id;
+    UPDATE stack SET cover_id = (SELECT media_id FROM stack_media WHERE stack_id = stack.id ORDER BY id LIMIT 1) WHERE cover_id = OLD.id;
+    DELETE FROM stack WHERE (SELECT COUNT(*) FROM stack_media WHERE stack_id = stack.id) < 2;
+END;
@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE media DROP COLUMN rating;
+ALTER TABLE media DROP COLUMN label;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE media ADD COLUMN rating INTEGER DEFAULT NULL;
+ALTER TABLE media ADD COLUMN label TEXT DEFAULT NULL;
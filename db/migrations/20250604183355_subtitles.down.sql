@@ -0,0 +1,3 @@
+-- Add down migration script here
+ALTER TABLE media_extra DROP COLUMN subtitles_version;
+ALTER TABLE media_extra DROP COLUMN subtitles;
@@ -0,0 +1,3 @@
+-- Add up migration script here
+ALTER TABLE media_extra ADD COLUMN subtitles_version INT NOT NULL DEFAULT -1;
+ALTER TABLE media_extra ADD COLUMN subtitles TEXT DEFAULT NULL;
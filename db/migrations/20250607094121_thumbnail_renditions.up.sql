@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE media ADD COLUMN thumbnail_renditions TEXT DEFAULT NULL;
@@ -0,0 +1,4 @@
+-- Add down migration script here
+DROP TABLE media_color;
+ALTER TABLE media DROP COLUMN palette;
+ALTER TABLE media DROP COLUMN blurhash;
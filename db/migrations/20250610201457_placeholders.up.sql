@@ -0,0 +1,15 @@
+-- Add up migration script here
+ALTER TABLE media ADD COLUMN blurhash TEXT DEFAULT NULL;
+ALTER TABLE media ADD COLUMN palette TEXT DEFAULT NULL;
+
+CREATE TABLE media_color (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    media_id INTEGER NOT NULL,
+    l REAL NOT NULL,
+    a REAL NOT NULL,
+    b REAL NOT NULL,
+    share REAL NOT NULL,
+    FOREIGN KEY (media_id) REFERENCES media(id) ON DELETE CASCADE
+);
+
+CREATE INDEX media_color_media_id ON media_color (media_id);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP INDEX media_place;
+ALTER TABLE media DROP COLUMN geocode_version;
+ALTER TABLE media DROP COLUMN city;
+ALTER TABLE media DROP COLUMN region;
+ALTER TABLE media DROP COLUMN country;
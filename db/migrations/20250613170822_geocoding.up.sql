@@ -0,0 +1,7 @@
+-- Add up migration script here
+ALTER TABLE media ADD COLUMN country TEXT DEFAULT NULL;
+ALTER TABLE media ADD COLUMN region TEXT DEFAULT NULL;
+ALTER TABLE media ADD COLUMN city TEXT DEFAULT NULL;
+ALTER TABLE media ADD COLUMN geocode_version INTEGER NOT NULL DEFAULT -1;
+
+CREATE INDEX media_place ON media (country, region, city);
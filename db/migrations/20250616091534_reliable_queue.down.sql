@@ -0,0 +1,6 @@
+-- Add down migration script here
+DROP TABLE queue_failed;
+DROP INDEX queue_task_available_at;
+ALTER TABLE queue DROP COLUMN available_at;
+ALTER TABLE queue DROP COLUMN last_error;
+ALTER TABLE queue DROP COLUMN attempts;
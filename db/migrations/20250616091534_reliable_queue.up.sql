@@ -0,0 +1,21 @@
+-- Add up migration script here
+ALTER TABLE queue ADD COLUMN attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE queue ADD COLUMN last_error TEXT DEFAULT NULL;
+-- NULL means right away, otherwise hidden until then (leased by a runner, or backing off after a failure)
+ALTER TABLE queue ADD COLUMN available_at TEXT DEFAULT NULL;
+
+CREATE INDEX queue_task_available_at ON queue (task, available_at);
+
+CREATE TABLE queue_failed
+(
+    id         INTEGER PRIMARY KEY AUTOINCREMENT,
+    media_id   INT     NOT NULL,
+    task       TEXT    NOT NULL,
+    created_at TEXT    NOT NULL,
+    failed_at  TEXT    NOT NULL,
+    attempts   INTEGER NOT NULL,
+    last_error TEXT DEFAULT NULL,
+    FOREIGN KEY (media_id) REFERENCES media (id) ON DELETE CASCADE
+);
+
+CREATE INDEX queue_failed_task ON queue_failed (task);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+DROP INDEX queue_media_task;
+ALTER TABLE queue DROP COLUMN priority;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+-- higher runs first, user requested work is queued above the background backlog
+ALTER TABLE queue ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX queue_media_task ON queue (media_id, task);
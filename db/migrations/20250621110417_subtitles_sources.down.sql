@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE media_extra DROP COLUMN subtitles_sources;
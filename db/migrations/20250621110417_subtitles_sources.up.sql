@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE media_extra ADD COLUMN subtitles_sources TEXT DEFAULT NULL;
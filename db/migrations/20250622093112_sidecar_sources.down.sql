@@ -0,0 +1,2 @@
+-- Add down migration script here
+ALTER TABLE media DROP COLUMN sidecar_sources;
@@ -0,0 +1,2 @@
+-- Add up migration script here
+ALTER TABLE media ADD COLUMN sidecar_sources TEXT DEFAULT NULL;
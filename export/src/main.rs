@@ -11,7 +11,7 @@ use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
 use common::types::AcquireClone;
-use tasks::tasks::thumbnail::ThumbnailGenerator;
+use tasks::tasks::thumbnail::{ThumbnailGenerationConfig, ThumbnailGenerator};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -30,6 +30,14 @@ async fn main() {
     let mut config: AppConfig = AppConfig::from_path(args.config);
     config.canonicalize();
 
+    let thumbnail_config = match ThumbnailGenerationConfig::from_app_config(&config) {
+        Ok(thumbnail_config) => thumbnail_config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
     let stdin = std::io::stdin().lines();
 
     let mut queries: Vec<MediaQuery> = Vec::new();
@@ -84,6 +92,17 @@ async fn main() {
         included_media.extend(medias);
     }
 
+    // companions are hidden from queries, bring them along with their still
+    let mut companions = Vec::new();
+    for media in &included_media {
+        if let Some(companion) = media.companion(&mut export_db).await.expect("could not get companion") {
+            if included_media_set.insert(companion.uuid) {
+                companions.push(companion);
+            }
+        }
+    }
+    included_media.extend(companions);
+
     let all_media = Media::all(&mut export_db)
         .await
         .expect("could not get all medias");
@@ -123,22 +142,14 @@ async fn main() {
     for media in included_media {
         // 5. copy thumbnails
         if media.has_thumbnail {
-            let thumb_path = ThumbnailGenerator::thumb_path(&media, &config);
-            let full_path = ThumbnailGenerator::full_path(&media, &config);
-
-            let to_thumb = ThumbnailGenerator::thumb_path(&media, &new_config);
-            let to_full = ThumbnailGenerator::full_path(&media, &new_config);
-
-            if thumb_path.exists() || full_path.exists() {
-                fs::create_dir_all(to_full.parent().unwrap()).unwrap();
-            }
-
-            if thumb_path.exists() {
-                fs::copy(&thumb_path, &to_thumb).unwrap();
-            }
-
-            if full_path.exists() {
-                fs::copy(&full_path, &to_full).unwrap();
+            for rendition in thumbnail_config.renditions() {
+                let from = ThumbnailGenerator::rendition_path(&media, &config, &rendition);
+                let to = ThumbnailGenerator::rendition_path(&media, &new_config, &rendition);
+
+                if from.exists() {
+                    fs::create_dir_all(to.parent().unwrap()).unwrap();
+                    fs::copy(&from, &to).unwrap();
+                }
             }
         }
         // 6. copy actual media
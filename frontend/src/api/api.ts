@@ -20,6 +20,10 @@ export interface Media {
     format: FormatType;
     import_id: number;
     has_thumbnail: boolean;
+    // placeholder shown while the thumbnail loads, null until the thumbnail task has run
+    blurhash: string | null;
+    // dominant colors as comma separated hex, most dominant first
+    palette: string | null;
 }
 
 export type FormatType = 'standard' | 'heif' | 'video' | 'raw' | 'unknown';
@@ -29,6 +33,7 @@ export enum MediaType {
     Video = 'video',
     Pdf = 'pdf',
     Audio = 'audio',
+    Book = 'book',
     Other = 'other'
 }
 
@@ -51,6 +56,8 @@ export interface MediaView {
 export interface MediaIndexResponse {
     media: Media[];
     count: number;
+    // [start, end, text] transcript segments matching full_search, by media uuid
+    transcript_hits?: Record<string, [number, number, string][]>;
 }
 
 export interface MediaViewIndexResponse {
@@ -126,13 +133,29 @@ export interface QueueProgressProgress {
     time: number
 }
 
-export interface QueueProgressDone {
-    status: 'Done',
-    // [success,failed]
-    Ok: [number, number]
+// [success,failed] or why the run stopped
+export type QueueRunResult = { Ok: [number, number] } | { Err: string };
+
+export type QueueProgressDone = { status: 'Done' } & QueueRunResult;
+
+export interface QueueProgressScanning {
+    status: 'Scanning';
+}
+
+export type QueueState = 'running' | 'paused' | 'draining' | 'canceled';
+
+// between scheduled runs
+export interface QueueProgressIdle {
+    status: 'Idle',
+    last: QueueRunResult | null,
+    // unix timestamp
+    next_scan: number | null,
+    state: QueueState,
+    // quiet hours and canceled tasks
+    held_tasks: string[]
 }
 
-export type QueueProgress = QueueProgressInitial | QueueProgressProgress | QueueProgressStarting | QueueProgressDone;
+export type QueueProgress = QueueProgressInitial | QueueProgressProgress | QueueProgressStarting | QueueProgressDone | QueueProgressScanning | QueueProgressIdle;
 
 export interface MediaExtra {
     id: number;
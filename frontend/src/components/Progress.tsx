@@ -2,8 +2,8 @@ import styles from './Progress.module.css'
 import {FontAwesomeIcon} from "@fortawesome/react-fontawesome";
 import {faHourglass} from "@fortawesome/free-solid-svg-icons";
 import {useEffect, useState} from "react";
-import {Api, QueueProgress} from "@/api/api";
-import {capitalize, durationHumanReadable} from "@/utility/mediaMetadata";
+import {Api, QueueProgress, QueueRunResult} from "@/api/api";
+import {capitalize, durationHumanReadable, timestampToDate} from "@/utility/mediaMetadata";
 
 function ProgressBar({total, progress}: { total: number, progress: number }) {
     return <div className={styles.progressBarContainer}>
@@ -16,6 +16,13 @@ function ProgressBar({total, progress}: { total: number, progress: number }) {
     </div>
 }
 
+function RunResult({result}: { result: QueueRunResult }) {
+    if ('Err' in result) {
+        return <div>Failed: {result.Err}</div>;
+    }
+    return <div>Succeeded: {result.Ok[0]}, Failed: {result.Ok[1]}</div>;
+}
+
 export default function Progress({api}: { api: Api }) {
     const [visible, setVisible] = useState(false);
     const [progress, setProgress] = useState<QueueProgress | null>(null);
@@ -59,7 +66,15 @@ export default function Progress({api}: { api: Api }) {
                         return <ProgressBar total={progress.total} progress={0}/>
                     }
                     if (progress.status === "Done") {
-                        return <div>Succeeded: {progress.Ok[0]}, Failed: {progress.Ok[1]}</div>;
+                        return <RunResult result={progress}/>;
+                    }
+                    if (progress.status === "Idle") {
+                        return <>
+                            <div>Queue: {capitalize(progress.state)}</div>
+                            {progress.last && <RunResult result={progress.last}/>}
+                            {progress.next_scan !== null && <div>Next scan: {timestampToDate(progress.next_scan)}</div>}
+                            {progress.held_tasks.length > 0 && <div>Held: {progress.held_tasks.join(', ')}</div>}
+                        </>
                     }
                     if (progress.status === "Progress") {
                         return <>
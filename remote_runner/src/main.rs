@@ -1,20 +1,27 @@
 use axum::http::{header, HeaderMap, Method, StatusCode};
 use axum::{middleware, Extension, Json, Router};
-use axum::extract::{Path, Request};
+use axum::body::Body;
+use axum::extract::{DefaultBodyLimit, Path, Request};
 use axum::middleware::Next;
 use axum::response::{ErrorResponse, IntoResponse, Response};
-use axum::routing::{get, post};
+use axum::routing::{delete, get, post};
 use once_cell::sync::Lazy;
 use sqlx::{SqlitePool};
 use sqlx::types::Uuid;
+use std::time::Duration;
 use subtle::ConstantTimeEq;
 use common::env::EnvVar;
 use common::remote_models::job::{Job, JobStatus};
 use common::types::DbPool;
 use common::runner_config::RemoteRunnerGlobalConfig;
-use tasks::remote_utils::RemoteTaskStatus;
+use tokio_util::io::ReaderStream;
+use tasks::remote_utils::{is_safe_file_name, job_dir, remove_stale_job_dirs, RemoteTaskStatus};
 use tasks::tasks::AnyTask;
 
+// how long a finished job's files wait for the client to download them
+const JOB_FILES_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const JOB_FILES_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 static ENV: Lazy<EnvVar> = Lazy::new(|| {
     let env = EnvVar::from_env();
     env
@@ -70,6 +77,17 @@ async fn main() {
         println!("Cancelled {} jobs", cancelled);
     }
 
+    tokio::spawn(async {
+        loop {
+            match remove_stale_job_dirs(&CONFIG.data_dir, JOB_FILES_TTL).await {
+                Ok(0) => {}
+                Ok(removed) => println!("Removed files of {} stale jobs", removed),
+                Err(e) => println!("Error removing stale job files: {:?}", e),
+            }
+            tokio::time::sleep(JOB_FILES_SWEEP_INTERVAL).await;
+        }
+    });
+
     println!("Remote Runner Listening on: {}", &CONFIG.listen_addr);
 
     let app = Router::new()
@@ -77,7 +95,11 @@ async fn main() {
         .route("/task/{task_name}/background", post(task_bg_run))
         .route("/task/{task_name}/custom", post(task_custom_run))
         .route("/job/{job_uuid}", get(job_status))
+        .route("/job/{job_uuid}/files", delete(job_files_delete))
+        .route("/job/{job_uuid}/files/{file_name}", get(job_file))
         .layer(Extension(pool))
+        // media files (e.g. videos to transcode) are well over the default limit
+        .layer(DefaultBodyLimit::disable())
         .layer(middleware::from_fn(auth_middleware));
 
     let listener = tokio::net::TcpListener::bind(&CONFIG.listen_addr).await.unwrap();
@@ -128,6 +150,24 @@ async fn job_status(Extension(pool): Extension<DbPool>, Path(job_uuid): Path<Uui
     Ok(Json(job))
 }
 
+async fn job_file(Path((job_uuid, file_name)): Path<(Uuid, String)>) -> Result<Response, (StatusCode, String)> {
+    if !is_safe_file_name(&file_name) {
+        return Err((StatusCode::BAD_REQUEST, "invalid file name".to_string()));
+    }
+    let path = job_dir(&CONFIG.data_dir, &job_uuid).join(file_name);
+    let file = tokio::fs::File::open(path).await.map_err(|_| (StatusCode::NOT_FOUND, "file not found".to_string()))?;
+    Ok(Body::from_stream(ReaderStream::new(file)).into_response())
+}
+
+async fn job_files_delete(Path(job_uuid): Path<Uuid>) -> Result<(), (StatusCode, String)> {
+    let dir = job_dir(&CONFIG.data_dir, &job_uuid);
+    if !dir.exists() {
+        return Err((StatusCode::NOT_FOUND, "no files for that job".to_string()));
+    }
+    tokio::fs::remove_dir_all(dir).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("error removing files: {:?}", e)))?;
+    Ok(())
+}
+
 async fn task_bg_run(
     Extension(pool): Extension<DbPool>,
     Path(task_name): Path<String>,
@@ -1,7 +1,7 @@
 mod media_operations;
 
 use std::collections::{HashMap, HashSet};
-use crate::media_operations::{add_media, remove_media, update_media, AddMediaError};
+use crate::media_operations::{add_media, create_stacks, link_companions, remove_media, update_media, AddMediaError};
 use common::directory_tree::{DirectoryTree, DIRECTORY_TREE_DB_KEY, LAST_IMPORT_ID_DB_KEY};
 use common::models::kv::Kv;
 use common::models::media::Media;
@@ -14,7 +14,7 @@ use clap::Parser;
 use walkdir::WalkDir;
 use common::{debug_sql, question_marks, update_set};
 use common::env::setup_log;
-use common::media_processors::format::{match_format, FormatType};
+use common::media_processors::format::{match_format, AnyFormat, FormatType};
 use common::types::DbPool;
 use tasks::ops::{add_outdated_queues, add_to_compatible_queues};
 use tasks::tasks::AnyTask;
@@ -142,6 +142,38 @@ async fn main() {
     
     info!("--- updating metadata complete report: {} ---", report);
 
+    info!("--- updating database: sidecars ---");
+
+    // only stats the sidecars, the media itself is read again when one of them changed
+    let mut sidecars_updated = 0;
+    for media in Media::all(&mut db).await.unwrap().iter_mut() {
+        let path = Path::new(&media.path);
+        let Some(format) = AnyFormat::try_new(path.to_path_buf()) else {
+            continue;
+        };
+        if !path.exists() || !media.sidecars_changed(&format.sidecar_sources()) {
+            continue;
+        }
+        match update_media(media, &config, &mut db).await {
+            Ok(_) => sidecars_updated += 1,
+            Err(e) => error!("  error updating media: {:?} - {:?}", media, e),
+        }
+    }
+
+    info!("--- updating database: sidecars complete, updated {} ---", sidecars_updated);
+
+    info!("--- updating database: companions ---");
+
+    let linked = link_companions(&mut db).await.unwrap();
+
+    info!("--- updating database: companions complete, linked {} ---", linked);
+
+    info!("--- updating database: stacks ---");
+
+    let (pairs, bursts) = create_stacks(&mut db, import_id).await.unwrap();
+
+    info!("--- updating database: stacks complete, raw+jpeg[{}]|burst[{}] ---", pairs, bursts);
+
     info!("--- updating database: tasks ---");
 
     let media = Media::all(&mut db).await.unwrap();
@@ -1,17 +1,29 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::Path;
 use log::{debug, error};
 use sqlx::SqliteConnection;
 use sqlx::types::chrono::Utc;
 use sqlx::types::Uuid;
-use common::media_processors::format::{AnyFormat, MetadataError};
-use common::models::media::Media;
+use common::media_processors::format::{AnyFormat, FormatType, MediaType, MetadataError};
+use common::models::media::{Media, Metadata};
+use common::models::stack::{Stack, StackKind};
 use common::models::system_time_to_naive_datetime;
 use common::scan_config::AppConfig;
 use tasks::ops::add_to_compatible_queues;
 use tasks::tasks::{BackgroundTask, AnyTask, Task};
 use tasks::tasks::thumbnail::ThumbnailGenerator;
 
+// tags imported from XMP keywords are marked with this so a rescan can replace them
+const XMP_TAG_TASK: &str = "xmp";
+
+// Live Photo videos are ~3 seconds and written alongside the still
+const LIVE_PHOTO_MAX_DURATION: u32 = 5000;
+const LIVE_PHOTO_MAX_GAP: i64 = 5;
+
+// consecutive shots from the same camera closer than this are a burst
+const BURST_MAX_GAP_MS: i64 = 1000;
+const BURST_MIN_SIZE: usize = 3;
+
 pub async fn add_media(path: &Path, config: &AppConfig, import_id: i32, media_map: &mut HashMap<String, Media>, db: &mut SqliteConnection) -> Result<(), AddMediaError> {
     let format = AnyFormat::try_new(path.to_path_buf()).ok_or(AddMediaError::UnsupportedFormat)?;
 
@@ -28,6 +40,8 @@ pub async fn add_media(path: &Path, config: &AppConfig, import_id: i32, media_ma
         media_map.remove(&path_str);
     }
 
+    // before reading, an edit made while reading is picked up next time
+    let sidecar_sources = format.sidecar_sources();
     let metadata = format.get_metadata(config)?;
 
     if let Some(media) = media_map.get(&path_str) {
@@ -43,6 +57,8 @@ pub async fn add_media(path: &Path, config: &AppConfig, import_id: i32, media_ma
 
     let hash = hash(path);
 
+    let xmp = metadata.xmp.unwrap_or_default();
+
     let mut media = Media {
         id: 0,
         uuid,
@@ -66,9 +82,24 @@ pub async fn add_media(path: &Path, config: &AppConfig, import_id: i32, media_ma
         metadata_version: format.metadata_version(),
         thumbnail_version: -1,
         import_id,
+        content_identifier: metadata.content_identifier,
+        companion_of: None,
+        motion_offset: metadata.motion_offset,
+        rating: xmp.rating,
+        label: xmp.label,
+        thumbnail_renditions: None,
+        blurhash: None,
+        palette: None,
+        country: None,
+        region: None,
+        city: None,
+        geocode_version: -1,
+        sidecar_sources: Some(sidecar_sources),
     };
 
     media.create(&mut *db).await.unwrap();
+    media.set_metadata(&mut *db, &metadata.fields).await.unwrap();
+    media.set_task_tags(&mut *db, XMP_TAG_TASK, &xmp.keywords).await.unwrap();
     media_map.insert(path_str.to_string(), media.clone());
 
     add_to_compatible_queues(&mut *db, &media, &AnyTask::BACKGROUND_TASK_NAMES).await.unwrap();
@@ -88,18 +119,36 @@ pub async fn update_media(media: &mut Media, config: &AppConfig, db: &mut Sqlite
     }
 
     // if format has changed, we need to update metadata and thumbnail regardless of version
-    
-    if media.metadata_version < format.metadata_version() || format_change {
+    // sidecars are edited by other tools without touching the file, so those are read again too
+    let sidecar_sources = format.sidecar_sources();
+
+    if media.metadata_version < format.metadata_version() || format_change || media.sidecars_changed(&sidecar_sources) {
         debug!("          updating metadata for {:?}: {} --> {}", media.uuid, media.metadata_version, format.metadata_version());
         let metadata = format.get_metadata(config)?;
+        media.created_at = metadata.created_at;
         media.width = metadata.width;
         media.height = metadata.height;
         media.size = metadata.size;
         media.duration = metadata.duration.map(|d| d.as_millis() as u32);
+        // moved (or lost its location), the geocode task picks it up again
+        if media.latitude != metadata.latitude || media.longitude != metadata.longitude {
+            media.country = None;
+            media.region = None;
+            media.city = None;
+            media.geocode_version = -1;
+        }
         media.longitude = metadata.longitude;
         media.latitude = metadata.latitude;
         media.is_screenshot = metadata.is_screenshot;
+        media.content_identifier = metadata.content_identifier;
+        media.motion_offset = metadata.motion_offset;
+        let xmp = metadata.xmp.unwrap_or_default();
+        media.rating = xmp.rating;
+        media.label = xmp.label;
         media.metadata_version = format.metadata_version();
+        media.sidecar_sources = Some(sidecar_sources);
+        media.set_metadata(&mut *db, &metadata.fields).await.unwrap();
+        media.set_task_tags(&mut *db, XMP_TAG_TASK, &xmp.keywords).await.unwrap();
     }
 
     // we only add to the thumbnail queue if the format has changed, thumbnail version checking is handled by the ThumbnailGenerator task itself in a later step
@@ -117,6 +166,115 @@ pub async fn update_media(media: &mut Media, config: &AppConfig, db: &mut Sqlite
 }
 
 
+/// pairs the video half of Live Photos with their still, by content identifier or failing that by name and time
+pub async fn link_companions(db: &mut SqliteConnection) -> Result<u32, sqlx::Error> {
+    let media = Media::all(&mut *db).await?;
+
+    let stills: Vec<&Media> = media.iter().filter(|m| m.media_type == MediaType::Photo).collect();
+    let by_identifier: HashMap<&str, &Media> = stills
+        .iter()
+        .filter_map(|m| Some((m.content_identifier.as_deref()?, *m)))
+        .collect();
+    let mut by_name: HashMap<(&Path, String), Vec<&Media>> = HashMap::new();
+    for still in stills.iter().copied() {
+        if let Some(key) = name_key(still) {
+            by_name.entry(key).or_default().push(still);
+        }
+    }
+
+    let mut linked = 0;
+    for video in media.iter().filter(|m| m.media_type == MediaType::Video && m.companion_of.is_none()) {
+        let by_id = video.content_identifier.as_deref().and_then(|id| by_identifier.get(id).copied());
+        let still = by_id.or_else(|| {
+            if video.duration.unwrap_or(u32::MAX) > LIVE_PHOTO_MAX_DURATION {
+                return None;
+            }
+            by_name.get(&name_key(video)?)?.iter().copied().find(|still| {
+                // two different identifiers means two different Live Photos that happen to share a name
+                let conflicting = still.content_identifier.is_some() && video.content_identifier.is_some();
+                !conflicting && (still.created_at - video.created_at).num_seconds().abs() <= LIVE_PHOTO_MAX_GAP
+            })
+        });
+
+        if let Some(still) = still {
+            debug!("      linking {:?} as the companion of {:?}", video.path, still.path);
+            let mut video = video.clone();
+            video.companion_of = Some(still.id);
+            video.update_by_id(&mut *db).await?;
+            linked += 1;
+        }
+    }
+
+    Ok(linked)
+}
+
+// IMG_1234.HEIC and IMG_1234.MOV (or IMG_1234.ARW and IMG_1234.JPG) share a directory and a stem
+fn name_key(media: &Media) -> Option<(&Path, String)> {
+    let path = Path::new(&media.path);
+    Some((path.parent()?, path.file_stem()?.to_string_lossy().to_lowercase()))
+}
+
+/// stacks RAW+JPEG pairs and bursts, only groups with media from this import are considered so splits made by hand stick
+pub async fn create_stacks(db: &mut SqliteConnection, import_id: i32) -> Result<(u32, u32), sqlx::Error> {
+    let media = Media::all(&mut *db).await?;
+    let mut stacked: HashSet<i32> = Stack::stacked_media_ids(&mut *db).await?.into_iter().collect();
+
+    let photos: Vec<&Media> = media.iter().filter(|m| m.media_type == MediaType::Photo).collect();
+
+    let mut by_name: HashMap<(&Path, String), Vec<&Media>> = HashMap::new();
+    for photo in photos.iter().copied() {
+        if let Some(key) = name_key(photo) {
+            by_name.entry(key).or_default().push(photo);
+        }
+    }
+
+    let mut pairs = 0;
+    for group in by_name.values() {
+        if group.iter().any(|m| stacked.contains(&m.id)) || !group.iter().any(|m| m.import_id == import_id) {
+            continue;
+        }
+        let Some(cover) = group.iter().find(|m| m.format != FormatType::Raw) else {
+            continue;
+        };
+        if !group.iter().any(|m| m.format == FormatType::Raw) {
+            continue;
+        }
+        let ids: Vec<i32> = group.iter().map(|m| m.id).collect();
+        debug!("      stacking raw+jpeg {:?}", cover.path);
+        Stack::create_with_media(&mut *db, StackKind::RawJpeg, cover.id, &ids).await?;
+        stacked.extend(ids);
+        pairs += 1;
+    }
+
+    let cameras: HashMap<i32, String> = Metadata::by_key(&mut *db, "camera").await?.into_iter().map(|m| (m.media_id, m.value)).collect();
+    let mut by_camera: HashMap<&str, Vec<&Media>> = HashMap::new();
+    for photo in photos.iter().copied().filter(|m| !stacked.contains(&m.id)) {
+        if let Some(camera) = cameras.get(&photo.id) {
+            by_camera.entry(camera.as_str()).or_default().push(photo);
+        }
+    }
+
+    let mut bursts = 0;
+    for shots in by_camera.values_mut() {
+        shots.sort_by_key(|m| m.created_at);
+        let mut runs: Vec<Vec<&Media>> = Vec::new();
+        for shot in shots.iter().copied() {
+            match runs.last_mut() {
+                Some(run) if (shot.created_at - run.last().unwrap().created_at).num_milliseconds() < BURST_MAX_GAP_MS => run.push(shot),
+                _ => runs.push(vec![shot]),
+            }
+        }
+        for run in runs.into_iter().filter(|run| run.len() >= BURST_MIN_SIZE && run.iter().any(|m| m.import_id == import_id)) {
+            let ids: Vec<i32> = run.iter().map(|m| m.id).collect();
+            debug!("      stacking burst of {} starting at {:?}", run.len(), run[0].path);
+            Stack::create_with_media(&mut *db, StackKind::Burst, run[0].id, &ids).await?;
+            bursts += 1;
+        }
+    }
+
+    Ok((pairs, bursts))
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum AddMediaError {
     #[error("iO error: {0}")]
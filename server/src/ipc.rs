@@ -3,7 +3,7 @@ use serde::Deserialize;
 use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader, BufWriter, Take};
 use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::UnixStream;
-use common::ipc::{IpcFileRequest, IpcFileResponse, IpcQueueProgressResponse, IpcRequest};
+use common::ipc::{IpcFileRequest, IpcFileResponse, IpcQueueControlResponse, IpcQueueProgressResponse, IpcQueueResponse, IpcRequest, QueueState};
 use common::models::media::Media;
 
 pub struct BufUnixStream {
@@ -88,8 +88,69 @@ pub async fn request_file(stream: &mut BufUnixStream, media: &Media, start: u64,
     Ok( response_size )
 }
 
+// renders a pdf page in the daemon, returns the JPEG bytes
+pub async fn request_page(stream: &mut BufUnixStream, media: &Media, page: u16, max_size: u32) -> Result<Vec<u8>, String> {
+    let req = IpcRequest::RenderPage {
+        file: IpcFileRequest {
+            db_id: media.id,
+            path: media.path.clone(),
+        },
+        page,
+        max_size,
+    };
+
+    let res = req_res(stream, req).await?;
+
+    let response_size = match res {
+        IpcFileResponse::Error { error } => return Err( error ),
+        IpcFileResponse::Success { response_size, .. } => response_size,
+    };
+
+    let mut bytes = vec![0; response_size as usize];
+    stream.reader.read_exact(&mut bytes).await.map_err(|e| format!("Unable to read page from socket: {:?}", e))?;
+
+    Ok( bytes )
+}
+
+// has the daemon write the media's tags and albums to its XMP sidecar
+pub async fn request_write_sidecar(stream: &mut BufUnixStream, media: &Media) -> Result<(), String> {
+    let req = IpcRequest::WriteSidecar {
+        file: IpcFileRequest {
+            db_id: media.id,
+            path: media.path.clone(),
+        },
+    };
+
+    let res = req_res(stream, req).await?;
+
+    match res {
+        IpcFileResponse::Error { error } => Err( error ),
+        IpcFileResponse::Success { .. } => Ok( () ),
+    }
+}
+
 pub async fn request_queue_progress(stream: &mut BufUnixStream) -> Result<IpcQueueProgressResponse, String> {
     let req = IpcRequest::QueueProgress;
     let res: IpcQueueProgressResponse = req_res(stream, req).await?;
     Ok( res )
-}
\ No newline at end of file
+}
+
+// pauses, resumes, drains or cancels the daemon's queue, returns the resulting state
+pub async fn request_queue_control(stream: &mut BufUnixStream, req: IpcRequest) -> Result<(QueueState, Vec<String>), String> {
+    let res = req_res(stream, req).await?;
+
+    match res {
+        IpcQueueControlResponse::Error { error } => Err( error ),
+        IpcQueueControlResponse::Success { state, canceled_tasks } => Ok( (state, canceled_tasks) ),
+    }
+}
+
+// enqueues, clears or reruns through the daemon, returns how many items were queued or removed
+pub async fn request_queue_change(stream: &mut BufUnixStream, req: IpcRequest) -> Result<u32, String> {
+    let res = req_res(stream, req).await?;
+
+    match res {
+        IpcQueueResponse::Error { error } => Err( error ),
+        IpcQueueResponse::Success { count } => Ok( count ),
+    }
+}
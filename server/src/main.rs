@@ -2,12 +2,14 @@ mod ipc;
 mod migrations;
 mod stream;
 
+use std::collections::HashMap;
 use std::io::{BufRead, Cursor, Read, Write};
+use std::str::FromStr;
 use axum::{Extension, Json, Router, routing::get};
 use axum::body::Body;
 use axum::extract::{Path, Query, State};
 use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Method, StatusCode};
-use axum::response::{IntoResponse, Response};
+use axum::response::{IntoResponse, Redirect, Response};
 use axum::routing::{delete, head, post};
 use axum_extra::headers::Range;
 use axum_extra::TypedHeader;
@@ -22,25 +24,34 @@ use tokio::net::UnixStream;
 use tower_http::cors::{Any, CorsLayer};
 use uuid::Uuid;
 use common::models::album::Album;
-use common::models::media::Media;
+use common::models::media::{Media, Metadata};
 use common::types::DbPool;
 use tokio_util::io::ReaderStream;
 use common::directory_tree::{DirectoryTree, DIRECTORY_TREE_DB_KEY, LAST_IMPORT_ID_DB_KEY};
-use common::env::EnvVar;
-use common::ipc::{IpcQueueProgressResponse, QueueProgress, RunProgressSer};
-use common::media_processors::format::MediaType;
-use common::media_query::{MediaQuery, MediaQueryType};
+use common::env::{setup_log, EnvVar};
+use common::ipc::{IpcQueueProgressResponse, IpcRequest, QueueProgress, QueueState, RunProgressSer};
+use common::media_processors::format::{FormatType, MediaType};
+use common::media_processors::format::pdf::{FULL_SIZE as PDF_FULL_SIZE, TEXT_KEY as PDF_TEXT_KEY};
+use common::media_query::{DSLString, MediaQuery, MediaQueryType};
 use common::models::custom_metadata::CustomMetadata;
 use common::models::kv::Kv;
 use common::models::media_extra::MediaExtra;
 use common::models::media_tag::MediaTag;
 use common::models::media_view::MediaView;
+use common::models::stack::{Stack, StackKind};
 use common::models::timeline::Timeline;
+use common::models::place::{PlaceCount, Places};
+use common::models::queue::{Queue, QueueSummary};
 use common::scan_config::AppConfig;
-use tasks::tasks::thumbnail::ThumbnailGenerator;
+use common::subtitles::{self, Cue};
+use tasks::tasks::thumbnail::{Rendition, ThumbnailGenerationConfig, ThumbnailGenerator};
+use tasks::tasks::transcode::{Transcode, TranscodeFormat, HLS_MASTER_FILE, MP4_FILE};
 use crate::ipc::BufUnixStream;
 use crate::stream::RemoteMediaFile;
 
+// largest page render we allow clients to request
+const MAX_PAGE_SIZE: u32 = 4096;
+
 static ENV: Lazy<EnvVar> = Lazy::new(|| {
     let env = EnvVar::from_env();
     env
@@ -50,6 +61,10 @@ static CONFIG: Lazy<AppConfig> = Lazy::new(|| {
     ENV.config.as_ref().expect("No config provided").clone()
 });
 
+static THUMBNAIL_CONFIG: Lazy<ThumbnailGenerationConfig> = Lazy::new(|| {
+    ThumbnailGenerationConfig::from_app_config(&CONFIG).expect("thumbnail config is checked at startup")
+});
+
 #[tokio::main]
 async fn main() {
     // ensure we aren't running as root
@@ -58,12 +73,19 @@ async fn main() {
         std::process::exit(1);
     }
     
+    setup_log("kaleidoscope_server");
+
     if ENV.dev_mode {
         println!("Running in dev mode");
     }
 
     println!("Config: {:?}", &CONFIG);
 
+    if let Err(e) = ThumbnailGenerationConfig::from_app_config(&CONFIG) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
     let pool = SqlitePool::connect(&format!("sqlite://{}", CONFIG.db_path)).await.unwrap();
 
     println!("Listening on: {}", &CONFIG.listen_addr);
@@ -78,20 +100,43 @@ async fn main() {
         .route("/media", get(media_index))
         // .route("/media/map", get(media_map))
         .route("/media/timeline", get(media_timeline))
+        .route("/places", get(places))
         .route("/media/{uuid}", get(media))
         .route("/media/{uuid}/raw", get(media_raw))
         .route("/media/{uuid}/full", get(media_full))
         .route("/media/{uuid}/thumb", get(media_thumb))
+        .route("/media/{uuid}/page/{page}", get(media_page))
+        .route("/media/{uuid}/playable", get(media_playable))
+        .route("/media/{uuid}/hls/{file}", get(media_hls))
+        .route("/media/{uuid}/motion", get(media_motion))
+        .route("/media/{uuid}/subtitles.vtt", get(media_subtitles))
+        .route("/media/{uuid}/transcript.vtt", get(media_transcript_vtt))
+        .route("/media/{uuid}/transcript.srt", get(media_transcript_srt))
+        .route("/media/{uuid}/transcript/search", get(media_transcript_search))
+        .route("/media/{uuid}/tasks/{task}", post(media_rerun_task))
         .route("/tag", get(tag_index))
         .route("/tag/{tag_name}/media", post(add_tag).delete(remove_tag))
         .route("/tag/{tag_name}", delete(delete_tag))
         .route("/album", get(album_index).post(album_create))
         .route("/album/{uuid}", get(album).delete(album_delete))
         .route("/album/{uuid}/media", post(album_add_media).delete(album_delete_media))
+        .route("/stack", post(stack_create))
+        .route("/stack/{uuid}", get(stack).delete(stack_delete))
+        .route("/stack/{uuid}/cover", post(stack_cover))
+        .route("/stack/{uuid}/media", delete(stack_delete_media))
         .route("/media_view", get(media_view_index).post(media_view_create).delete(media_view_delete))
         .route("/directory_tree", get(directory_tree))
         .route("/info", get(info))
         .route("/queue-status", get(queue_status))
+        .route("/queue", get(queue_index).post(queue_enqueue))
+        .route("/queue/{task}", delete(queue_clear))
+        .route("/queue/{task}/purge", post(queue_purge))
+        .route("/queue/pause", post(queue_pause))
+        .route("/queue/resume", post(queue_resume))
+        .route("/queue/drain", post(queue_drain))
+        .route("/queue/cancel", post(queue_cancel))
+        .route("/queue/cancel/{task}", post(queue_cancel_task))
+        .route("/thumbnail/renditions", get(thumbnail_renditions))
         .layer(Extension(pool))
         .layer(cors);
 
@@ -109,6 +154,9 @@ pub struct MediaQueryQuery {
 struct MediaIndexResponse {
     media: Vec<Media>,
     count: u32,
+    // transcript segments matching full_search, by media uuid
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    transcript_hits: HashMap<Uuid, Vec<Cue>>,
 }
 async fn media_index(Extension(conn): Extension<DbPool>, query: Query<MediaQueryQuery>) -> Result<Json<MediaIndexResponse>, (StatusCode, String)> {
     let query = &query.query;
@@ -117,9 +165,43 @@ async fn media_index(Extension(conn): Extension<DbPool>, query: Query<MediaQuery
         return Err((StatusCode::BAD_REQUEST, format!("invalid query: {}", err)));
     }
 
-    let media = Media::get_all(&conn, &query).await.unwrap();
+    let query = &query.without_companions();
+    let media = Media::get_all(&conn, query).await.unwrap();
     let count = Media::count(&conn, &query.to_count_query()).await.unwrap();
-    Ok(Json(MediaIndexResponse { media, count }))
+    let transcript_hits = transcript_hits(&conn, &media, query).await?;
+    Ok(Json(MediaIndexResponse { media, count, transcript_hits }))
+}
+
+// the segments full_search matched in each transcript, so the viewer can seek to them
+async fn transcript_hits(conn: &DbPool, media: &[Media], query: &MediaQuery) -> Result<HashMap<Uuid, Vec<Cue>>, (StatusCode, String)> {
+    let searches: Vec<(&DSLString, &str)> = query
+        .full_searches()
+        .filter(|(op, _)| matches!(op, DSLString::Equal | DSLString::Like))
+        .collect();
+    let mut hits = HashMap::new();
+    if searches.is_empty() {
+        return Ok(hits);
+    }
+    // the whole page's extras at once
+    let uuids: HashMap<i32, Uuid> = media.iter().map(|m| (m.id, m.uuid)).collect();
+    let ids: Vec<i32> = uuids.keys().copied().collect();
+    let extras = MediaExtra::from_media_ids(conn, &ids).await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "problem with media extra query".to_string()))?;
+    for extra in extras {
+        let (Some(uuid), Some(transcript)) = (uuids.get(&extra.media_id), extra.whisper_transcript) else {
+            continue;
+        };
+        let Ok(cues) = serde_json::from_str::<Vec<Cue>>(&transcript) else {
+            continue;
+        };
+        let matched: Vec<Cue> = cues
+            .into_iter()
+            .filter(|(_, _, text)| searches.iter().all(|(op, search)| op.matches(search, text)))
+            .collect();
+        if !matched.is_empty() {
+            hits.insert(*uuid, matched);
+        }
+    }
+    Ok(hits)
 }
 
 #[derive(Debug, serde::Deserialize)]
@@ -129,13 +211,17 @@ struct MediaParams {
 
 #[derive(Debug, Deserialize)]
 struct MediaDirectQuery {
-    extra: Option<bool>
+    extra: Option<bool>,
+    // include the pdf text layer
+    text: Option<bool>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct MediaDirectResponse {
     media: Media,
     tags: Vec<MediaTag>,
+    metadata: Vec<Metadata>,
+    stack: Option<Stack>,
     extra: Option<MediaExtra>,
     customs: Vec<CustomMetadata>
 }
@@ -143,6 +229,11 @@ pub struct MediaDirectResponse {
 async fn media(Extension(conn): Extension<DbPool>, path: Path<MediaParams>, query: Query<MediaDirectQuery>) -> Result<Json<MediaDirectResponse>, (StatusCode, String)> {
     let media = Media::from_uuid(&conn, &path.uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "Media not found".to_string()))?;
     let tags = media.tags(&conn).await.unwrap();
+    let mut metadata = media.metadata(&conn).await.unwrap();
+    if !query.text.unwrap_or(false) {
+        metadata.retain(|m| media.format != FormatType::Pdf || m.key != PDF_TEXT_KEY);
+    }
+    let stack = Stack::from_media_id(&conn, media.id).await.unwrap();
     let (extra,customs) = if query.extra.unwrap_or(false) {
         let extra = media.extra(&conn).await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "problem with media_extra query".to_string()))?;
         let customs = media.customs(&conn).await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "problem with customs query".to_string()))?;
@@ -155,6 +246,8 @@ async fn media(Extension(conn): Extension<DbPool>, path: Path<MediaParams>, quer
         media,
         extra,
         tags,
+        metadata,
+        stack,
         customs
     }))
 }
@@ -163,6 +256,7 @@ async fn media_raw(Extension(conn): Extension<DbPool>, range: Option<TypedHeader
     let media = Media::from_uuid(&conn, &path.uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "Media not found".to_string()))?;
     let name = media.name.clone();
     let media_type = media.media_type;
+    let format = media.format;
 
     let stream = UnixStream::connect(&CONFIG.socket_path).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("ipc error connecting to socket: {:?}", e)))?;
     let mut buf_stream = BufUnixStream::new(stream);
@@ -183,24 +277,203 @@ async fn media_raw(Extension(conn): Extension<DbPool>, range: Option<TypedHeader
     if media_type == MediaType::Pdf {
         res.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("application/pdf"));
     } else {
+        if let Some(content_type) = download_content_type(format, &name) {
+            res.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+        }
         res.headers_mut().insert(header::CONTENT_DISPOSITION, HeaderValue::from_str(&format!("attachment; filename=\"{}\"", name)).unwrap());
     }
 
     Ok(res)
 }
 
+// content types for downloads that browsers and readers won't sniff correctly
+fn download_content_type(format: FormatType, name: &str) -> Option<&'static str> {
+    match format {
+        FormatType::Epub => Some("application/epub+zip"),
+        FormatType::Comic if name.to_lowercase().ends_with(".cbr") => Some("application/vnd.comicbook-rar"),
+        FormatType::Comic => Some("application/vnd.comicbook+zip"),
+        _ => None,
+    }
+}
+
 async fn media_full(Extension(conn): Extension<DbPool>, path: Path<MediaParams>) -> Result<(HeaderMap, Body), (StatusCode, String)> {
     let media = Media::from_uuid(&conn, &path.uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "Media not found".to_string()))?;
-    let path = ThumbnailGenerator::full_path(&media, &CONFIG);
-    Ok(serve_file(&path, "image/jpeg".to_string()).await)
+    serve_rendition(&media, &THUMBNAIL_CONFIG.full_rendition()).await
 }
 
-async fn media_thumb(Extension(conn): Extension<DbPool>, path: Path<MediaParams>) -> Result<(HeaderMap, Body), (StatusCode, String)> {
+#[derive(Debug, Deserialize)]
+struct MediaThumbQuery {
+    /// a rendition name, or a width in pixels which picks the smallest rendition at least that big
+    size: Option<String>,
+}
+
+async fn media_thumb(Extension(conn): Extension<DbPool>, path: Path<MediaParams>, query: Query<MediaThumbQuery>) -> Result<(HeaderMap, Body), (StatusCode, String)> {
     let media = Media::from_uuid(&conn, &path.uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "Media not found".to_string()))?;
-    let path = ThumbnailGenerator::thumb_path(&media, &CONFIG);
-    Ok(serve_file(&path, "image/jpeg".to_string()).await)
+    let rendition = match query.size.as_deref() {
+        None => THUMBNAIL_CONFIG.thumb_rendition(),
+        Some(size) => match size.parse::<u32>() {
+            Ok(size) => THUMBNAIL_CONFIG.rendition_for_size(size),
+            Err(_) => THUMBNAIL_CONFIG.rendition(size),
+        }
+        .ok_or((StatusCode::NOT_FOUND, format!("unknown rendition: {}", size)))?,
+    };
+    serve_rendition(&media, &rendition).await
+}
+
+async fn serve_rendition(media: &Media, rendition: &Rendition) -> Result<(HeaderMap, Body), (StatusCode, String)> {
+    let path = ThumbnailGenerator::rendition_path(media, &CONFIG, rendition);
+    if !path.exists() {
+        return Err((StatusCode::NOT_FOUND, "rendition not found".to_string()));
+    }
+    Ok(serve_file(&path, rendition.format.content_type().to_string()).await)
 }
 
+// the configured renditions, so clients can build a srcset
+async fn thumbnail_renditions() -> Json<Vec<Rendition>> {
+    Json(THUMBNAIL_CONFIG.renditions())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct MediaPageParams {
+    uuid: Uuid,
+    page: u16,
+}
+
+#[derive(Debug, Deserialize)]
+struct MediaPageQuery {
+    size: Option<u32>,
+}
+
+// pages are zero based, rendering happens in the daemon as we can't read the original
+async fn media_page(Extension(conn): Extension<DbPool>, path: Path<MediaPageParams>, query: Query<MediaPageQuery>) -> Result<Response, (StatusCode, String)> {
+    let media = Media::from_uuid(&conn, &path.uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "Media not found".to_string()))?;
+    if media.media_type != MediaType::Pdf {
+        return Err((StatusCode::BAD_REQUEST, "media is not a pdf".to_string()));
+    }
+    let size = query.size.unwrap_or(PDF_FULL_SIZE).clamp(1, MAX_PAGE_SIZE);
+
+    let stream = UnixStream::connect(&CONFIG.socket_path).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("ipc error connecting to socket: {:?}", e)))?;
+    let mut buf_stream = BufUnixStream::new(stream);
+
+    let bytes = ipc::request_page(&mut buf_stream, &media, path.page, size).await.map_err(|e| (StatusCode::BAD_REQUEST, format!("ipc error rendering page: {:?}", e)))?;
+
+    Ok(([(header::CONTENT_TYPE, "image/jpeg")], bytes).into_response())
+}
+
+// serves the transcoded rendition if there is one, otherwise the original
+async fn media_playable(Extension(conn): Extension<DbPool>, range: Option<TypedHeader<Range>>, path: Path<MediaParams>) -> Result<Response, (StatusCode, String)> {
+    let media = Media::from_uuid(&conn, &path.uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "Media not found".to_string()))?;
+    let extra = media.extra(&conn).await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "problem with media_extra query".to_string()))?;
+    let format = extra.and_then(|e| e.transcode_format).and_then(|f| TranscodeFormat::from_str(&f).ok());
+
+    // redirects are relative so they keep working behind a path prefix
+    match format {
+        Some(TranscodeFormat::Mp4) => {
+            let file = tokio::fs::File::open(Transcode::output_dir(&media, &CONFIG).join(MP4_FILE)).await.map_err(|_| (StatusCode::NOT_FOUND, "transcoded file not found".to_string()))?;
+            let body = KnownSize::file(file).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("error reading transcoded file: {:?}", e)))?;
+            let range = range.map(|TypedHeader(range)| range);
+            let mut res = Ranged::new(range, body).into_response();
+            res.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static("video/mp4"));
+            Ok(res)
+        }
+        Some(TranscodeFormat::Hls) => Ok(Redirect::temporary(&format!("hls/{}", HLS_MASTER_FILE)).into_response()),
+        None => Ok(Redirect::temporary("raw").into_response()),
+    }
+}
+
+// the moving part of a Live Photo (its companion video) or motion photo (the embedded MP4)
+async fn media_motion(Extension(conn): Extension<DbPool>, range: Option<TypedHeader<Range>>, path: Path<MediaParams>) -> Result<Response, (StatusCode, String)> {
+    let media = Media::from_uuid(&conn, &path.uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "Media not found".to_string()))?;
+    let companion = media.companion(&conn).await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "problem with companion query".to_string()))?;
+
+    let (media, offset, content_type) = match (companion, media.motion_offset) {
+        (Some(companion), _) => {
+            let content_type = if companion.name.to_lowercase().ends_with(".mov") { "video/quicktime" } else { "video/mp4" };
+            (companion, 0, content_type)
+        }
+        (None, Some(offset)) => (media, offset as u64, "video/mp4"),
+        (None, None) => return Err((StatusCode::NOT_FOUND, "media has no motion".to_string())),
+    };
+
+    let stream = UnixStream::connect(&CONFIG.socket_path).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("ipc error connecting to socket: {:?}", e)))?;
+    let mut buf_stream = BufUnixStream::new(stream);
+
+    let file_size = ipc::request_file_size(&mut buf_stream, &media).await.map_err(|e| (StatusCode::BAD_REQUEST, format!("ipc error requesting file size: {:?}", e)))?;
+    let len = file_size.checked_sub(offset).ok_or((StatusCode::INTERNAL_SERVER_ERROR, "motion offset is past the end of the file".to_string()))?;
+
+    let body = KnownSize::sized(RemoteMediaFile::with_offset(offset, len, media, buf_stream), len);
+    let range = range.map(|TypedHeader(range)| range);
+    let mut res = Ranged::new(range, body).into_response();
+    res.headers_mut().insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+    Ok(res)
+}
+
+// subtitles found by the subtitles task, or the Whisper transcript if there are none
+async fn media_subtitles(Extension(conn): Extension<DbPool>, path: Path<MediaParams>) -> Result<Response, (StatusCode, String)> {
+    let media = Media::from_uuid(&conn, &path.uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "Media not found".to_string()))?;
+    let extra = media.extra(&conn).await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "problem with media extra query".to_string()))?;
+    let cues = extra
+        .and_then(|e| e.subtitles.or(e.whisper_transcript))
+        .ok_or((StatusCode::NOT_FOUND, "media has no subtitles".to_string()))?;
+    let cues: Vec<Cue> = serde_json::from_str(&cues).map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "invalid subtitles".to_string()))?;
+    Ok(([(header::CONTENT_TYPE, "text/vtt; charset=utf-8")], subtitles::to_vtt(&cues)).into_response())
+}
+
+async fn media_transcript(conn: &DbPool, uuid: &Uuid) -> Result<Vec<Cue>, (StatusCode, String)> {
+    let media = Media::from_uuid(conn, uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "Media not found".to_string()))?;
+    let extra = media.extra(conn).await.map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "problem with media extra query".to_string()))?;
+    let transcript = extra
+        .and_then(|e| e.whisper_transcript)
+        .ok_or((StatusCode::NOT_FOUND, "media has no transcript".to_string()))?;
+    serde_json::from_str(&transcript).map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "invalid transcript".to_string()))
+}
+
+async fn media_transcript_vtt(Extension(conn): Extension<DbPool>, path: Path<MediaParams>) -> Result<Response, (StatusCode, String)> {
+    let cues = media_transcript(&conn, &path.uuid).await?;
+    Ok(([(header::CONTENT_TYPE, "text/vtt; charset=utf-8")], subtitles::to_vtt(&cues)).into_response())
+}
+
+async fn media_transcript_srt(Extension(conn): Extension<DbPool>, path: Path<MediaParams>) -> Result<Response, (StatusCode, String)> {
+    let cues = media_transcript(&conn, &path.uuid).await?;
+    Ok(([(header::CONTENT_TYPE, "application/x-subrip; charset=utf-8")], subtitles::to_srt(&cues)).into_response())
+}
+
+#[derive(Debug, Deserialize)]
+struct TranscriptSearchQuery {
+    q: String,
+}
+
+async fn media_transcript_search(Extension(conn): Extension<DbPool>, path: Path<MediaParams>, query: Query<TranscriptSearchQuery>) -> Result<Json<Vec<Cue>>, (StatusCode, String)> {
+    let cues = media_transcript(&conn, &path.uuid).await?;
+    // anywhere in the segment, % and _ are wildcards like in full_search
+    let pattern = format!("%{}%", query.q);
+    Ok(Json(cues.into_iter().filter(|(_, _, text)| DSLString::Like.matches(&pattern, text)).collect()))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct MediaHlsParams {
+    uuid: Uuid,
+    file: String,
+}
+
+async fn media_hls(Extension(conn): Extension<DbPool>, path: Path<MediaHlsParams>) -> Result<(HeaderMap, Body), (StatusCode, String)> {
+    let content_type = if path.file.ends_with(".m3u8") {
+        "application/vnd.apple.mpegurl"
+    } else if path.file.ends_with(".ts") {
+        "video/mp2t"
+    } else {
+        return Err((StatusCode::NOT_FOUND, "not an hls file".to_string()));
+    };
+    if !path.file.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.') || path.file.contains("..") {
+        return Err((StatusCode::BAD_REQUEST, "invalid file name".to_string()));
+    }
+    let media = Media::from_uuid(&conn, &path.uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "Media not found".to_string()))?;
+    let file_path = Transcode::output_dir(&media, &CONFIG).join(&path.file);
+    if !file_path.exists() {
+        return Err((StatusCode::NOT_FOUND, "hls file not found".to_string()));
+    }
+    Ok(serve_file(&file_path, content_type.to_string()).await)
+}
 
 async fn serve_file(path: &std::path::Path, content_type: String) -> (HeaderMap, Body) {
     let file = tokio::fs::File::open(path).await.unwrap();
@@ -256,7 +529,17 @@ async fn album_create(Extension(conn): Extension<DbPool>, payload: Json<AlbumCre
 
 async fn album_delete(Extension(conn): Extension<DbPool>, path: Path<AlbumParams>) -> Result<(), (StatusCode, String)> {
     let album = Album::from_uuid(&conn, &path.uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "Album not found".to_string()))?;
+    // the sidecars are rewritten once the album is gone
+    let medias: Vec<Media> = sqlx::query("SELECT media.* FROM media INNER JOIN album_media ON media.id = album_media.media_id WHERE album_media.album_id = ?")
+        .bind(album.id)
+        .fetch_all(&conn)
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| row.into())
+        .collect();
     album.delete(&conn).await.unwrap();
+    write_sidecars(&medias).await;
     Ok(())
 }
 
@@ -286,6 +569,8 @@ async fn album_add_media(Extension(conn): Extension<DbPool>, path: Path<AlbumPar
 
     transaction.commit().await.unwrap();
 
+    write_sidecars(&medias).await;
+
     Ok(Json(album))
 }
 
@@ -306,11 +591,108 @@ async fn album_delete_media(Extension(conn): Extension<DbPool>, path: Path<Album
 
     transaction.commit().await.unwrap();
 
+    write_sidecars(&medias).await;
+
     Ok(Json(album))
 }
 
 
 
+#[derive(Debug, serde::Deserialize)]
+struct StackParams {
+    uuid: Uuid
+}
+
+#[derive(Debug, Serialize)]
+struct StackResponse {
+    stack: Stack,
+    media: Vec<Media>,
+}
+
+async fn stack(Extension(conn): Extension<DbPool>, path: Path<StackParams>) -> Result<Json<StackResponse>, (StatusCode, String)> {
+    let stack = Stack::from_uuid(&conn, &path.uuid).await.unwrap().ok_or((StatusCode::NOT_FOUND, "Stack not found".to_string()))?;
+    let media = stack.media(&conn).await.unwrap();
+    Ok(Json(StackResponse { stack, media }))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct StackCreateParams {
+    medias: Vec<Uuid>,
+    // defaults to the first media
+    cover: Option<Uuid>,
+}
+
+async fn stack_create(Extension(conn): Extension<DbPool>, payload: Json<StackCreateParams>) -> Result<Json<StackResponse>, (StatusCode, String)> {
+    if payload.medias.len() < 2 {
+        return Err((StatusCode::BAD_REQUEST, "a stack needs at least two media".to_string()));
+    }
+
+    let mut medias = Vec::with_capacity(payload.medias.len());
+    for media_uuid in payload.medias.iter() {
+        let media = Media::from_uuid(&conn, media_uuid).await.map_err(|_| (StatusCode::NOT_FOUND, format!("Media not found: {}", media_uuid)))?;
+        if Stack::from_media_id(&conn, media.id).await.unwrap().is_some() {
+            return Err((StatusCode::CONFLICT, format!("Media already in a stack: {}", media_uuid)));
+        }
+        medias.push(media);
+    }
+
+    let cover = match &payload.cover {
+        Some(cover) => medias.iter().find(|m| &m.uuid == cover).ok_or((StatusCode::BAD_REQUEST, "cover must be one of the stack's media".to_string()))?,
+        None => &medias[0],
+    };
+    let ids: Vec<i32> = medias.iter().map(|m| m.id).collect();
+
+    let mut transaction = conn.begin().await.unwrap();
+    let stack = Stack::create_with_media(&mut transaction, StackKind::Manual, cover.id, &ids).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    let media = stack.media(&conn).await.unwrap();
+    Ok(Json(StackResponse { stack, media }))
+}
+
+async fn stack_delete(Extension(conn): Extension<DbPool>, path: Path<StackParams>) -> Result<(), (StatusCode, String)> {
+    let stack = Stack::from_uuid(&conn, &path.uuid).await.unwrap().ok_or((StatusCode::NOT_FOUND, "Stack not found".to_string()))?;
+    stack.delete(&conn).await.unwrap();
+    Ok(())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct StackCoverParams {
+    media: Uuid,
+}
+
+async fn stack_cover(Extension(conn): Extension<DbPool>, path: Path<StackParams>, payload: Json<StackCoverParams>) -> Result<Json<Stack>, (StatusCode, String)> {
+    let mut stack = Stack::from_uuid(&conn, &path.uuid).await.unwrap().ok_or((StatusCode::NOT_FOUND, "Stack not found".to_string()))?;
+    let media = Media::from_uuid(&conn, &payload.media).await.map_err(|_| (StatusCode::NOT_FOUND, format!("Media not found: {}", payload.media)))?;
+    if Stack::from_media_id(&conn, media.id).await.unwrap().map(|s| s.id) != Some(stack.id) {
+        return Err((StatusCode::BAD_REQUEST, "cover must be one of the stack's media".to_string()));
+    }
+    stack.cover_id = media.id;
+    stack.update_by_id(&conn).await.unwrap();
+    Ok(Json(stack))
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct StackMediaParams {
+    medias: Vec<Uuid>,
+}
+
+// splits media out of the stack, the stack is removed once fewer than two are left
+async fn stack_delete_media(Extension(conn): Extension<DbPool>, path: Path<StackParams>, payload: Json<StackMediaParams>) -> Result<Json<Option<Stack>>, (StatusCode, String)> {
+    let mut stack = Stack::from_uuid(&conn, &path.uuid).await.unwrap().ok_or((StatusCode::NOT_FOUND, "Stack not found".to_string()))?;
+
+    let mut ids = Vec::with_capacity(payload.medias.len());
+    for media_uuid in payload.medias.iter() {
+        ids.push(Media::from_uuid(&conn, media_uuid).await.map_err(|_| (StatusCode::NOT_FOUND, format!("Media not found: {}", media_uuid)))?.id);
+    }
+
+    let mut transaction = conn.begin().await.unwrap();
+    let dissolved = stack.split(&mut transaction, &ids).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    Ok(Json(if dissolved { None } else { Some(stack) }))
+}
+
 #[derive(Debug, Serialize)]
 struct MediaViewIndexResponse {
     media_views: Vec<MediaView>,
@@ -426,6 +808,24 @@ async fn timeline(conn: &DbPool, media_query: &MediaQuery, interval: &str) -> Re
     }
 }
 
+#[derive(Serialize, Deserialize)]
+struct PlacesQuery {
+    #[serde(default)]
+    query: MediaQuery,
+}
+
+async fn places(Extension(conn): Extension<DbPool>, query: Query<PlacesQuery>) -> Result<Json<Vec<PlaceCount>>, (StatusCode, String)> {
+    let media_query = &query.query;
+
+    if let Err(err) = media_query.validate() {
+        return Err((StatusCode::BAD_REQUEST, format!("invalid query: {}", err)));
+    }
+
+    // a Live Photo is one place visit, not two
+    let places = Places::all(&conn, &media_query.without_companions().to_count_query()).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    Ok(Json(places))
+}
+
 async fn queue_status() -> Json<QueueProgress> {
     let stream = UnixStream::connect(&CONFIG.socket_path).await.unwrap();
     let mut buf_stream = BufUnixStream::new(stream);
@@ -433,6 +833,129 @@ async fn queue_status() -> Json<QueueProgress> {
     Json(status)
 }
 
+#[derive(Deserialize)]
+struct QueueIndexQuery {
+    task: Option<String>,
+    // items listed per task, all of them are counted
+    #[serde(default = "default_queue_limit")]
+    limit: u32,
+}
+
+fn default_queue_limit() -> u32 {
+    50
+}
+
+#[derive(Serialize)]
+struct QueueItemResponse {
+    #[serde(flatten)]
+    queue: Queue,
+    media: Media,
+}
+
+#[derive(Serialize)]
+struct QueueTaskResponse {
+    #[serde(flatten)]
+    summary: QueueSummary,
+    items: Vec<QueueItemResponse>,
+}
+
+async fn queue_index(Extension(conn): Extension<DbPool>, query: Query<QueueIndexQuery>) -> Result<Json<Vec<QueueTaskResponse>>, (StatusCode, String)> {
+    let summaries = Queue::summary(&conn).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let mut tasks = Vec::new();
+    for summary in summaries {
+        if query.task.as_ref().is_some_and(|task| *task != summary.task) {
+            continue;
+        }
+        let mut items = Vec::new();
+        for queue in Queue::list(&conn, &summary.task, query.limit).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))? {
+            let media = Media::from_id(&conn, &queue.media_id).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            items.push(QueueItemResponse { queue, media });
+        }
+        tasks.push(QueueTaskResponse { summary, items });
+    }
+    Ok(Json(tasks))
+}
+
+#[derive(Deserialize)]
+struct EnqueueRequest {
+    task: String,
+    #[serde(default)]
+    query: MediaQuery,
+    #[serde(default)]
+    priority: i32,
+}
+
+async fn queue_change(req: IpcRequest) -> Result<Json<u32>, (StatusCode, String)> {
+    let stream = UnixStream::connect(&CONFIG.socket_path).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("ipc error connecting to socket: {:?}", e)))?;
+    let mut buf_stream = BufUnixStream::new(stream);
+    let count = ipc::request_queue_change(&mut buf_stream, req).await.map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    Ok(Json(count))
+}
+
+async fn queue_enqueue(Json(req): Json<EnqueueRequest>) -> Result<Json<u32>, (StatusCode, String)> {
+    if let Err(err) = req.query.validate() {
+        return Err((StatusCode::BAD_REQUEST, format!("invalid query: {}", err)));
+    }
+    queue_change(IpcRequest::Enqueue { task: req.task, query: req.query, priority: req.priority }).await
+}
+
+async fn queue_clear(task: Path<String>) -> Result<Json<u32>, (StatusCode, String)> {
+    queue_change(IpcRequest::ClearQueue { task: task.0 }).await
+}
+
+#[derive(Deserialize)]
+struct PurgeRequest {
+    query: MediaQuery,
+}
+
+async fn queue_purge(task: Path<String>, Json(req): Json<PurgeRequest>) -> Result<Json<u32>, (StatusCode, String)> {
+    if let Err(err) = req.query.validate() {
+        return Err((StatusCode::BAD_REQUEST, format!("invalid query: {}", err)));
+    }
+    if req.query.is_paged() {
+        return Err((StatusCode::BAD_REQUEST, "purge applies to every match, limit and page aren't allowed".to_string()));
+    }
+    queue_change(IpcRequest::PurgeTask { task: task.0, query: req.query }).await
+}
+
+async fn media_rerun_task(Extension(conn): Extension<DbPool>, Path((uuid, task)): Path<(Uuid, String)>) -> Result<Json<u32>, (StatusCode, String)> {
+    let media = Media::from_uuid(&conn, &uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "media not found".to_string()))?;
+    queue_change(IpcRequest::RerunTask { media_id: media.id, task }).await
+}
+
+#[derive(Serialize)]
+struct QueueControlResponse {
+    state: QueueState,
+    canceled_tasks: Vec<String>,
+}
+
+async fn queue_control(req: IpcRequest) -> Result<Json<QueueControlResponse>, (StatusCode, String)> {
+    let stream = UnixStream::connect(&CONFIG.socket_path).await.map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, format!("ipc error connecting to socket: {:?}", e)))?;
+    let mut buf_stream = BufUnixStream::new(stream);
+    let (state, canceled_tasks) = ipc::request_queue_control(&mut buf_stream, req).await.map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    Ok(Json(QueueControlResponse { state, canceled_tasks }))
+}
+
+async fn queue_pause() -> Result<Json<QueueControlResponse>, (StatusCode, String)> {
+    queue_control(IpcRequest::PauseQueue).await
+}
+
+async fn queue_resume() -> Result<Json<QueueControlResponse>, (StatusCode, String)> {
+    queue_control(IpcRequest::ResumeQueue).await
+}
+
+async fn queue_drain() -> Result<Json<QueueControlResponse>, (StatusCode, String)> {
+    queue_control(IpcRequest::DrainQueue).await
+}
+
+async fn queue_cancel() -> Result<Json<QueueControlResponse>, (StatusCode, String)> {
+    queue_control(IpcRequest::CancelQueue).await
+}
+
+async fn queue_cancel_task(task: Path<String>) -> Result<Json<QueueControlResponse>, (StatusCode, String)> {
+    queue_control(IpcRequest::CancelTask { task: task.0 }).await
+}
+
 async fn tag_index(Extension(conn): Extension<DbPool>) -> Json<Vec<(MediaTag, u32)>> {
     let index = MediaTag::count_index(&conn).await.unwrap();
     Json(index)
@@ -453,16 +976,49 @@ async fn add_tag(Extension(conn): Extension<DbPool>, tag: Path<String>, media_uu
         return Err((StatusCode::BAD_REQUEST, "duplicate tag".to_string()));
     }
     let tag= media.add_tag(&mut &conn, tag.0, None).await.unwrap();
+    write_sidecars(&[media]).await;
     Ok(Json(tag))
 }
 
 async fn remove_tag(Extension(conn): Extension<DbPool>, tag: Path<String>, media_uuid: Json<Uuid>) -> Result<Json<bool>, (StatusCode, String)> {
     let media = Media::from_uuid(&conn, &media_uuid).await.map_err(|_| (StatusCode::NOT_FOUND, "media not found".to_string()))?;
     let removed = media.remove_tag(&conn, &tag.0).await.unwrap();
+    if removed {
+        write_sidecars(&[media]).await;
+    }
     Ok(Json(removed))
 }
 
 async fn delete_tag(Extension(conn): Extension<DbPool>, tag: Path<String>) -> Result<Json<u64>, (StatusCode, String)> {
+    let medias: Vec<Media> = sqlx::query("SELECT media.* FROM media INNER JOIN media_tag ON media.id = media_tag.media_id WHERE media_tag.tag = ?")
+        .bind(&*tag)
+        .fetch_all(&conn)
+        .await
+        .unwrap()
+        .iter()
+        .map(|row| row.into())
+        .collect();
     let res = sqlx::query("DELETE FROM media_tag WHERE tag = ?").bind(&*tag).execute(&conn).await.unwrap();
+    write_sidecars(&medias).await;
     Ok(Json(res.rows_affected()))
+}
+
+// when enabled, has the daemon write tags and albums back to XMP sidecars, failures don't fail the request
+async fn write_sidecars(medias: &[Media]) {
+    if !CONFIG.write_sidecars || medias.is_empty() {
+        return;
+    }
+    let stream = match UnixStream::connect(&CONFIG.socket_path).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            log::error!("ipc error connecting to socket: {:?}", e);
+            return;
+        }
+    };
+    let mut buf_stream = BufUnixStream::new(stream);
+    for media in medias {
+        if let Err(e) = ipc::request_write_sidecar(&mut buf_stream, media).await {
+            log::error!("couldn't write sidecar for {}: {}", media.uuid, e);
+        }
+    }
 }
\ No newline at end of file
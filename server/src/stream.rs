@@ -14,6 +14,8 @@ pub struct RemoteMediaFile {
     media: Media,
     stream: Option<BufUnixStream>,
 
+    // where the served range starts in the underlying file
+    offset: u64,
     cursor: u64,
     len: u64,
 
@@ -22,9 +24,14 @@ pub struct RemoteMediaFile {
 
 impl RemoteMediaFile {
     pub fn new(len: u64, media: Media, stream: BufUnixStream) -> Self {
-        
+        Self::with_offset(0, len, media, stream)
+    }
+
+    /// serves `len` bytes of the file starting at `offset`, e.g. a video embedded in a photo
+    pub fn with_offset(offset: u64, len: u64, media: Media, stream: BufUnixStream) -> Self {
         Self {
             media,
+            offset,
             cursor: 0,
             len,
             stream: Some(stream),
@@ -35,10 +42,11 @@ impl RemoteMediaFile {
     async fn send_request(
         mut stream: BufUnixStream,
         media: Media,
+        offset: u64,
         start: u64,
         end: u64,
     ) -> (io::Result<(u64, u64, Vec<u8>)>, BufUnixStream) {
-        let res_size = match ipc::request_file(&mut stream, &media, start, end).await {
+        let res_size = match ipc::request_file(&mut stream, &media, offset + start, offset + end).await {
             Err(e) => return (Err(io::Error::new(io::ErrorKind::Other, format!("unable to execute ipc file request: {} | {}", media.path, e))), stream),
             Ok(stream) => stream,
         };
@@ -55,6 +63,7 @@ impl RemoteMediaFile {
         self.current_task.set(Box::pin(Self::send_request(
             stream,
             self.media.clone(),
+            self.offset,
             start,
             end,
         )));
@@ -2,6 +2,14 @@ use std::env;
 use std::path::PathBuf;
 
 fn main() {
+    println!("cargo:rerun-if-changed=../objc/ocr.m");
+    println!("cargo:rerun-if-changed=../objc/ocr.h");
+
+    // the Vision OCR backend is macOS only, elsewhere the task uses tesseract
+    if env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("macos") {
+        return;
+    }
+
     let out_dir = PathBuf::from(env::var("OUT_DIR").unwrap());
 
     cc::Build::new()
@@ -14,6 +22,4 @@ fn main() {
     println!("cargo:rustc-link-lib=framework=Vision");
     println!("cargo:rustc-link-lib=static=ocr");
     println!("cargo:rustc-link-search=native={}", out_dir.display());
-    println!("cargo:rerun-if-changed=../objc/ocr.m");
-    println!("cargo:rerun-if-changed=../objc/ocr.h");
 }
@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use common::ipc::QueueState;
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+/// shared with running queues so they can be paused, drained or canceled from outside,
+/// anything but running sticks until `resume`
+pub struct QueueControl {
+    state: watch::Sender<QueueState>,
+    // canceled along with the whole queue
+    cancel: Mutex<CancellationToken>,
+    // children of `cancel`, one per task type that a run or `cancel_task` asked about
+    tasks: Mutex<HashMap<String, CancellationToken>>,
+    // tasks that can't start new items right now, e.g. in quiet hours
+    held: Mutex<Vec<String>>,
+}
+
+impl Default for QueueControl {
+    fn default() -> Self {
+        Self {
+            state: watch::Sender::new(QueueState::Running),
+            cancel: Mutex::new(CancellationToken::new()),
+            tasks: Mutex::new(HashMap::new()),
+            held: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl QueueControl {
+    pub fn state(&self) -> QueueState {
+        *self.state.borrow()
+    }
+
+    pub fn pause(&self) {
+        self.state.send_if_modified(|state| {
+            let running = *state == QueueState::Running;
+            if running {
+                *state = QueueState::Paused;
+            }
+            running
+        });
+    }
+
+    pub fn drain(&self) {
+        self.state.send_if_modified(|state| {
+            let open = matches!(state, QueueState::Running | QueueState::Paused);
+            if open {
+                *state = QueueState::Draining;
+            }
+            open
+        });
+    }
+
+    pub fn cancel(&self) {
+        self.state.send_replace(QueueState::Canceled);
+        self.cancel.lock().unwrap().cancel();
+    }
+
+    pub fn cancel_task(&self, task: &str) {
+        self.token(task).cancel();
+    }
+
+    /// clears every pause, drain and cancel, runs that were stopped don't come back but the next one starts as usual
+    pub fn resume(&self) {
+        *self.cancel.lock().unwrap() = CancellationToken::new();
+        self.tasks.lock().unwrap().clear();
+        self.state.send_replace(QueueState::Running);
+    }
+
+    pub fn canceled_tasks(&self) -> Vec<String> {
+        let mut tasks: Vec<String> = self
+            .tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, token)| token.is_cancelled())
+            .map(|(task, _)| task.clone())
+            .collect();
+        tasks.sort();
+        tasks
+    }
+
+    /// replaces the tasks whose workers stop taking items, unlike `cancel_task` the item in progress finishes
+    /// and `resume` doesn't clear them, whoever holds them lets them go
+    pub fn hold(&self, tasks: &[String]) {
+        *self.held.lock().unwrap() = tasks.to_vec();
+    }
+
+    pub fn held(&self, task: &str) -> bool {
+        self.held.lock().unwrap().iter().any(|held| held == task)
+    }
+
+    /// canceled when the queue or `task` is
+    pub fn token(&self, task: &str) -> CancellationToken {
+        self.tasks
+            .lock()
+            .unwrap()
+            .entry(task.to_string())
+            .or_insert_with(|| self.cancel.lock().unwrap().child_token())
+            .clone()
+    }
+
+    /// waits out a pause, false once the worker holding `token` should stop taking items
+    pub async fn proceed(&self, token: &CancellationToken) -> bool {
+        let mut state = self.state.subscribe();
+        loop {
+            if token.is_cancelled() {
+                return false;
+            }
+            match *state.borrow_and_update() {
+                QueueState::Running => return true,
+                QueueState::Draining | QueueState::Canceled => return false,
+                QueueState::Paused => {}
+            }
+            tokio::select! {
+                _ = state.changed() => {}
+                _ = token.cancelled() => return false,
+            }
+        }
+    }
+}
@@ -12,6 +12,7 @@ use sqlx::types::chrono::Utc;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
 use tokio::process::Command;
 use common::models::custom_task_media::CustomTaskMedia;
+use common::models::queue::Queue;
 use crate::tasks::{AnyTask, TaskError};
 use crate::tasks::thumbnail::ThumbnailGenerator;
 
@@ -43,6 +44,13 @@ macro_rules! python_func {
 python_func!(
     @raw
     async fn execute_task(db: &mut impl AcquireClone, media: &Media, app_config: &AppConfig, version: i32, _: &str| task_name: String, task_args_str: String) -> String {
+        let dependencies = AnyTask::custom_dependencies(&task_name);
+        if Queue::failed(db.acquire_clone(), media.id, dependencies).await.map_err(|e| TaskError::TaskError(e.into()))? {
+            return Err(TaskError::DependencyFailed(task_name, dependencies));
+        }
+        if Queue::pending(db.acquire_clone(), media.id, dependencies).await.map_err(|e| TaskError::TaskError(e.into()))? {
+            return Err(TaskError::DependencyPending(task_name, dependencies));
+        }
         AnyTask::run_custom_anywhere(&task_name, db, &app_config.remote, app_config, &task_args_str).await
     }
 );
@@ -105,7 +113,8 @@ python_func!(
 
 python_func!(
      async fn get_thumb(db: &mut impl AcquireClone, media: &Media, app_config: &AppConfig, version: i32, _: &str|full: bool) -> String {
-        let path = if full { ThumbnailGenerator::full_path(&media, app_config) } else {ThumbnailGenerator::thumb_path(&media, app_config)};
+        let path = if full { ThumbnailGenerator::source_path(&media, app_config) } else {ThumbnailGenerator::thumb_path(&media, app_config)};
+        let path = path.map_err(|e| TaskError::TaskError(e.into()))?;
         Ok(path.to_str().unwrap().to_string())
      }
 );
@@ -1,5 +1,6 @@
 pub mod tasks;
 pub mod ops;
+pub mod control;
 pub mod run_python;
 pub mod remote_utils;
 pub mod custom_task;
@@ -1,16 +1,19 @@
 use std::collections::HashMap;
 use std::path::Path;
+use std::str::FromStr;
 use std::time::Instant;
+use chrono::Utc;
 use clap::{Parser, ValueEnum};
-use log::{error, info};
+use log::{debug, error, info};
 use common::models::media::Media;
 use common::scan_config::{AppConfig, CustomConfig};
-use sqlx::{Connection, Pool, SqliteConnection};
+use sqlx::{Connection, Pool, SqliteConnection, SqlitePool};
 use tokio::sync::mpsc;
 use common::env::setup_log;
 use common::types::AcquireClone;
-use common::models::queue::Queue;
-use tasks::ops::{add_to_compatible_queues, run_custom, run_custom_tasks, run_queue, RunProgress};
+use common::media_query::MediaQuery;
+use common::models::queue::{Queue, QueueFailed, PRIORITY_USER};
+use tasks::ops::{add_to_compatible_queues, enqueue, purge, run_custom, run_custom_tasks, run_queue, RunProgress};
 use tasks::tasks::{AnyTask, TaskError};
 
 #[derive(Parser, Debug)]
@@ -28,6 +31,12 @@ pub struct CliArgs {
     store: bool,
     #[arg(short, long, default_value = "false")]
     custom: bool,
+    /// a MediaQuery, queues every matching media instead of a single --media
+    #[arg(short, long)]
+    query: Option<String>,
+    /// answer yes to every prompt, for cron and scripts
+    #[arg(short, long, default_value = "false")]
+    yes: bool,
 }
 
 #[derive(Debug, Clone, ValueEnum)]
@@ -37,6 +46,15 @@ pub enum Operation {
     Queue,
     #[clap(alias = "r")]
     Run,
+    /// counts and oldest item per task, --task to list its items too
+    #[clap(alias = "l")]
+    List,
+    /// removes queued items of --task, or of every task
+    Clear,
+    /// moves items that ran out of attempts back into the queue, of --task or of every task
+    RequeueFailed,
+    /// removes what --task stored for the media matching --query ("" for all of them) and queues them again
+    Purge,
 }
 
 async fn progress_handler(mut recv: mpsc::Receiver<RunProgress>, mut db: impl AcquireClone){
@@ -53,6 +71,9 @@ async fn progress_handler(mut recv: mpsc::Receiver<RunProgress>, mut db: impl Ac
                 info!("({}/{}) - task '{}' - media {}: succeeded, took: {:?}", progress.index+1, progress.total, progress.task, media.path, progress.time);
             }
         }
+        for (step, time) in &progress.steps {
+            debug!("          {}: {:?}", step, time);
+        }
 
         if progress.done() {
             break;
@@ -71,6 +92,8 @@ async fn main() {
         op,
         store,
         custom,
+        query,
+        yes,
     } = args;
 
     let mut app_config = AppConfig::from_path(&config_path);
@@ -157,10 +180,119 @@ async fn main() {
         return;
     }
 
+    match op {
+        Operation::List => {
+            list(&mut db, task_name.as_deref()).await;
+            return;
+        }
+        Operation::Clear => {
+            let tasks: Vec<&str> = match &task_name {
+                Some(task) => vec![task.as_str()],
+                None => AnyTask::BACKGROUND_TASK_NAMES.to_vec(),
+            };
+            if !confirm(&format!("clear the queue for {:?}?", tasks), yes) {
+                println!("aborting");
+                return;
+            }
+            for task in tasks {
+                let count = Queue::delete_by_task(&mut db, task).await.expect("error clearing queue");
+                println!("removed {} items from the queue for '{}'", count, task);
+            }
+            return;
+        }
+        Operation::RequeueFailed => {
+            let count = QueueFailed::requeue_all(&mut db, task_name.as_deref()).await.expect("error requeuing failed items");
+            println!("requeued {} failed items", count);
+            return;
+        }
+        Operation::Purge => {
+            let Some(task) = task_name else {
+                eprintln!("task must be provided when purging");
+                return;
+            };
+            if media.is_some() {
+                eprintln!("purge takes a query, not a media");
+                return;
+            }
+            // an empty query purges everything, but it has to be asked for
+            let Some(query) = query else {
+                eprintln!("query must be provided when purging, \"\" for all media");
+                return;
+            };
+            let query = match MediaQuery::from_str(&query) {
+                Ok(query) => query,
+                Err(e) => {
+                    eprintln!("invalid query: {}", e);
+                    return;
+                }
+            };
+            if let Err(e) = query.validate() {
+                eprintln!("invalid query: {}", e);
+                return;
+            }
+            if query.is_paged() {
+                eprintln!("purge applies to every match, limit and page aren't allowed");
+                return;
+            }
+            let mut medias = Media::get_all(&mut db, &query.to_count_query()).await.expect("error getting media");
+            if !confirm(&format!("{} media match. Remove their '{}' data and queue them again?", medias.len(), task), yes) {
+                println!("aborting");
+                return;
+            }
+            let count = purge(&mut db, &mut medias, &task, &app_config.tasks, &app_config).await.expect("error purging task data");
+            println!("purged '{}' data of {} media and queued them again", task, count);
+            return;
+        }
+        Operation::Queue | Operation::Run => {}
+    }
+
+    if let Some(query) = query {
+        if !matches!(op, Operation::Queue) {
+            eprintln!("query is only supported when queuing");
+            return;
+        }
+        if media.is_some() {
+            eprintln!("query and media can't be used together");
+            return;
+        }
+        let query = match MediaQuery::from_str(&query) {
+            Ok(query) => query,
+            Err(e) => {
+                eprintln!("invalid query: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = query.validate() {
+            eprintln!("invalid query: {}", e);
+            return;
+        }
+        // every match, not just a page of them
+        let medias = Media::get_all(&mut db, &query.to_count_query()).await.expect("error getting media");
+        if !confirm(&format!("{} media match the query. Queue them?", medias.len()), yes) {
+            println!("aborting");
+            return;
+        }
+        match task_name {
+            Some(task) => {
+                let count = enqueue(&mut db, &medias, &task, 0).await.expect("error queuing media");
+                println!("added {} media to the queue for '{}'", count, task);
+            }
+            None => {
+                for media in &medias {
+                    add_to_compatible_queues(&mut db, media, &AnyTask::BACKGROUND_TASK_NAMES)
+                        .await
+                        .expect("error adding to compatible queues");
+                }
+                println!("added {} media to compatible queues", medias.len());
+            }
+        }
+        return;
+    }
+
     match (task_name, media) {
         (None, None) => match op {
             Operation::Queue => {
-                eprintln!("task, media must be provided when queuing");
+                eprintln!("media or query must be provided when queuing");
                 return;
             }
             Operation::Run => {
@@ -169,17 +301,21 @@ async fn main() {
                     return;
                 }
 
-                let queue = run_queue(&mut db, &AnyTask::BACKGROUND_TASK_NAMES, &app_config.tasks, &app_config.remote, &app_config, Some(progress_tx))
+                let pool = SqlitePool::connect(&format!("sqlite:{}", app_config.db_path))
+                    .await
+                    .unwrap();
+                let queue = run_queue(&pool, &AnyTask::BACKGROUND_TASK_NAMES, &app_config.tasks, &app_config.remote, &app_config, Some(progress_tx), None)
                     .await
                     .expect("error running queue");
                 join.await.expect("error joining progress handler");
                 println!("{} tasks succeeded, {} failed", queue.0, queue.1);
             }
+            _ => unreachable!("handled above"),
         },
         (Some(task), None) => {
             match op {
                 Operation::Queue => {
-                    eprintln!("media must be provided when queuing");
+                    eprintln!("media or query must be provided when queuing");
                     return;
                 }
                 Operation::Run => {
@@ -200,17 +336,21 @@ async fn main() {
 
                     // let's confirm with user before running
 
-                    if !confirm(&format!("{} tasks in queue for '{}'. Continue?", tasks, task)) {
+                    if !confirm(&format!("{} tasks in queue for '{}'. Continue?", tasks, task), yes) {
                         println!("aborting");
                         return;
                     }
 
-                    let queue = run_queue(&mut db, &[&task], &app_config.tasks, &app_config.remote, &app_config, Some(progress_tx))
+                    let pool = SqlitePool::connect(&format!("sqlite:{}", app_config.db_path))
+                        .await
+                        .unwrap();
+                    let queue = run_queue(&pool, &[&task], &app_config.tasks, &app_config.remote, &app_config, Some(progress_tx), None)
                         .await
                         .expect("error running queue");
                     join.await.expect("error joining progress handler");
                     println!("{} tasks succeeded, {} failed", queue.0, queue.1);
                 }
+                _ => unreachable!("handled above"),
             }
         }
         (None, Some(media)) => {
@@ -233,6 +373,7 @@ async fn main() {
                     eprintln!("task must be provided when running specific media");
                     return;
                 }
+                _ => unreachable!("handled above"),
             }
         }
         (Some(task), Some(media)) => {
@@ -246,19 +387,13 @@ async fn main() {
                 Operation::Queue => {
                     if !AnyTask::compatible(&task, &media).await {
                         // media is not compatible, should we force?
-                        if !confirm(&format!("media is not compatible with '{}'. Force?", task)) {
+                        if !confirm(&format!("media is not compatible with '{}'. Force?", task), yes) {
                             println!("aborting");
                             return;
                         }
                     }
-                    // add media to the specified task queue
-                    let mut queue = Queue {
-                        id: 0,
-                        media_id: media.id,
-                        task: task.to_string(),
-                        created_at: chrono::Utc::now().naive_utc(),
-                    };
-                    queue.create(&mut db).await.expect("error creating queue");
+                    // add media to the specified task queue, ahead of the background backlog
+                    Queue::replace(&mut db, &task, media.id, PRIORITY_USER).await.expect("error creating queue");
                     println!("added media to queue for '{}'", task);
                 }
                 Operation::Run => {
@@ -266,7 +401,7 @@ async fn main() {
                     
                     if !AnyTask::compatible(&task, &media).await {
                         // media is not compatible, should we force?
-                        if !confirm(&format!("media is not compatible with '{}'. Force?", task)) {
+                        if !confirm(&format!("media is not compatible with '{}'. Force?", task), yes) {
                             println!("aborting");
                             return;
                         }
@@ -297,13 +432,60 @@ async fn main() {
 
                     println!("task '{}' succeeded, took: {:?}, result: {:?}", task.name(), end, res);
                 }
+                _ => unreachable!("handled above"),
             }
         }
     }
 }
 
 
-pub fn confirm(msg: &str) -> bool {
+async fn list(db: &mut SqliteConnection, task: Option<&str>) {
+    let summaries = Queue::summary(&mut *db).await.expect("error summarizing queue");
+    if summaries.is_empty() {
+        println!("queue is empty");
+        return;
+    }
+    let now = Utc::now().naive_utc();
+    println!("{:<20} {:>8} {:>10} {:>8}  oldest", "task", "pending", "available", "failed");
+    for summary in summaries.iter().filter(|s| task.is_none_or(|t| t == s.task)) {
+        let oldest = summary.oldest.map(|oldest| format_age(now - oldest)).unwrap_or_else(|| "-".to_string());
+        println!("{:<20} {:>8} {:>10} {:>8}  {}", summary.task, summary.pending, summary.available, summary.failed, oldest);
+    }
+
+    let Some(task) = task else {
+        return;
+    };
+    println!();
+    for queue in Queue::list(&mut *db, task, u32::MAX).await.expect("error listing queue") {
+        let media = Media::from_id(&mut *db, &queue.media_id).await.expect("error getting media");
+        let error = queue.last_error.map(|e| format!(", last error: {}", e)).unwrap_or_default();
+        println!("{} (priority {}, {} attempts{}, queued {} ago)", media.path, queue.priority, queue.attempts, error, format_age(now - queue.created_at));
+    }
+    for failed in QueueFailed::all(&mut *db, Some(task)).await.expect("error listing failed items") {
+        let media = Media::from_id(&mut *db, &failed.media_id).await.expect("error getting media");
+        println!("{} (failed {} ago after {} attempts: {})", media.path, format_age(now - failed.failed_at), failed.attempts, failed.last_error.unwrap_or_default());
+    }
+}
+
+// "3d 4h", "5h 12m", "40s"
+fn format_age(age: chrono::Duration) -> String {
+    let seconds = age.num_seconds().max(0);
+    let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else if minutes > 0 {
+        format!("{}m", minutes)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+pub fn confirm(msg: &str, yes: bool) -> bool {
+    if yes {
+        return true;
+    }
     let mut input = String::new();
     println!("{} (y/n)", msg);
     std::io::stdin()
@@ -1,20 +1,25 @@
 use std::borrow::Borrow;
+use std::collections::HashMap;
 use tokio::io::AsyncBufReadExt;
 use tokio::io::{AsyncWriteExt, BufReader};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
 use std::time::{Duration, Instant};
 use chrono::Utc;
 use log::{debug, error};
 use serde_json::json;
 use tokio::process::Command;
-use common::models::queue::Queue;
-use crate::tasks::{BackgroundTask, AnyTask, TaskError};
+use common::models::queue::{Queue, QueueFailed, PRIORITY_USER};
+use crate::tasks::{AnyTask, TaskError};
 use common::models::media::Media;
-use common::scan_config::{AppConfig, CustomConfig};
-use common::types::{AcquireClone, SqliteAcquire};
+use common::scan_config::{AppConfig, CustomConfig, QueueConfig};
+use common::types::{AcquireClone, DbPool, SqliteAcquire};
 use tokio::sync::mpsc;
+use tokio::task::JoinSet;
 use toml::Table;
 use common::ipc::RunProgressSer;
 use common::models::custom_task_media::CustomTaskMedia;
+use crate::control::QueueControl;
 use crate::custom_task::{call_fn, FnCall};
 
 #[derive(Debug, thiserror::Error)]
@@ -34,6 +39,7 @@ pub struct RunProgress {
     pub queue_id: Option<i32>,
     pub error: Option<TaskError>,
     pub time: Duration, // time taken to run the task in seconds
+    pub steps: Vec<(String, Duration)>, // time taken by each step, if the task reports them
 }
 impl From<RunProgress> for RunProgressSer {
     fn from(progress: RunProgress) -> Self {
@@ -45,6 +51,7 @@ impl From<RunProgress> for RunProgressSer {
             queue_id: progress.queue_id,
             error: progress.error.map(|e| e.to_string()),
             time: progress.time.as_secs() as u32,
+            steps: progress.steps.into_iter().map(|(step, time)| (step, time.as_millis() as u32)).collect(),
         }
     }
 }
@@ -55,79 +62,306 @@ impl RunProgress {
     }
 }
 
+/// removes what `task` stored for the media, it's outdated afterwards so the next scan queues it again
 pub async fn delete_task_data(
     db: &mut impl AcquireClone,
-    task: &impl BackgroundTask,
-    media: &Media,
+    task: &AnyTask,
+    media: &mut Media,
 ) -> Result<(), TaskOperationError> {
-    unimplemented!();
+    task.remove_data(db, media).await?;
+    Ok(())
 }
 
-pub async fn run_queue(
+/// removes what `task` stored for every compatible media and queues them again right away,
+/// for outputs of a broken model or version. returns how many were purged
+pub async fn purge(
     db: &mut impl AcquireClone,
+    medias: &mut [Media],
+    task: &str,
+    config: &Table,
+    app_config: &AppConfig,
+) -> Result<u32, TaskOperationError> {
+    let any_task = AnyTask::new(task, db, config, app_config).await?;
+    let mut count = 0;
+    for media in medias.iter_mut() {
+        if !AnyTask::compatible(task, media).await {
+            continue;
+        }
+        delete_task_data(db, &any_task, media).await?;
+        // an old failure would hold back tasks that depend on this one
+        QueueFailed::delete_by_media_id(db.acquire_clone(), any_task.name(), media.id).await?;
+        count += 1;
+    }
+    // asked for by hand, like a rerun
+    enqueue(db, medias, task, PRIORITY_USER).await?;
+    Ok(count)
+}
+
+/// how many items of `task` run at once, from the `[tasks.concurrency]` table, 1 if it isn't there
+pub fn task_concurrency(config: &Table, task: &str) -> u32 {
+    config
+        .get("concurrency")
+        .and_then(|c| c.get(task))
+        .and_then(|c| c.as_integer())
+        .map(|c| c.clamp(1, u32::MAX as i64) as u32)
+        .unwrap_or(1)
+}
+
+// how often a worker blocked on its dependencies looks again while they're still running
+const DEPENDENCY_POLL: Duration = Duration::from_secs(1);
+
+// how many times a lease is renewed over its length while the item runs
+const LEASE_RENEWALS: u32 = 3;
+const MIN_LEASE_RENEWAL: Duration = Duration::from_secs(1);
+
+// why a run ended before the task did
+enum Stopped {
+    Canceled,
+    LeaseLost,
+}
+
+#[derive(Default)]
+struct QueueCounters {
+    success: AtomicU32,
+    failed: AtomicU32,
+    // finished items, success or not, the next progress index
+    finished: AtomicU32,
+    // workers still running per task
+    workers: HashMap<&'static str, AtomicU32>,
+}
+
+impl QueueCounters {
+    fn running(&self, tasks: &[&str]) -> bool {
+        tasks.iter().any(|t| self.workers.get(t).is_some_and(|w| w.load(Ordering::SeqCst) > 0))
+    }
+}
+
+// counts a worker out however it stops
+struct WorkerGuard {
+    counters: Arc<QueueCounters>,
+    task: &'static str,
+}
+
+impl Drop for WorkerGuard {
+    fn drop(&mut self) {
+        if let Some(workers) = self.counters.workers.get(self.task) {
+            workers.fetch_sub(1, Ordering::SeqCst);
+        }
+    }
+}
+
+/// runs every task type at once, each with its own `task_concurrency` workers sharing the pool,
+/// items are taken by priority and only once the tasks they depend on are done with the media
+pub async fn run_queue(
+    pool: &DbPool,
     tasks: &[&str],
     config: &Table,
     remote_configs: &Table,
     app_config: &AppConfig,
     progress: Option<mpsc::Sender<RunProgress>>,
+    control: Option<Arc<QueueControl>>,
 ) -> Result<(u32, u32), TaskOperationError> {
-    let mut success = 0;
-    let mut failed = 0;
-
+    let control = control.unwrap_or_default();
     let mut total = 0;
+    let mut available = Vec::with_capacity(tasks.len());
 
     for task in tasks {
-        total += Queue::count(db.acquire_clone(), task).await?;
+        // items stuck behind a failed dependency would never become available
+        let blocked = Queue::fail_blocked(pool, task, AnyTask::dependencies_from_str(task)).await?;
+        if blocked > 0 {
+            error!("moved {} items of task {} to queue_failed, a task they depend on failed", blocked, task);
+        }
+        let count = Queue::count_available(pool, task, AnyTask::dependencies_from_str(task)).await?;
+        available.push(count);
+        total += count;
     }
 
-    for task in tasks {
-        let task = AnyTask::new(task, db, config, app_config).await?;
-        while let Some(queue) = Queue::get_next(db.acquire_clone(), task.name()).await? {
-            queue.delete(db.acquire_clone()).await?;
+    let mut instances = Vec::new();
+    let mut counters = QueueCounters::default();
+    for (task, available) in tasks.iter().zip(available) {
+        if available == 0 {
+            continue;
+        }
+        let concurrency = task_concurrency(config, task).min(available);
+        counters.workers.insert(AnyTask::name_from_str(task)?, AtomicU32::new(concurrency));
+        instances.push((*task, available, concurrency));
+    }
+    let counters = Arc::new(counters);
+
+    let mut workers = JoinSet::new();
+    for (task, available, concurrency) in instances {
+        // only what was there at the start, items that fail and come back later in the run wait for the next one
+        let remaining = Arc::new(AtomicU32::new(available));
+        for _ in 0..concurrency {
+            // one instance per worker, so timings and any other per run state don't mix
+            let task = AnyTask::new(task, &mut &*pool, config, app_config).await?;
+            workers.spawn(run_worker(
+                pool.clone(),
+                task,
+                remaining.clone(),
+                counters.clone(),
+                total,
+                remote_configs.clone(),
+                app_config.queue.clone(),
+                progress.clone(),
+                control.clone(),
+            ));
+        }
+    }
 
-            let mut media = Media::from_id(db.acquire_clone(), &queue.media_id).await?;
-            let start = Instant::now();
-            match task.run_and_store_anywhere(db, &mut media, remote_configs).await {
-                Ok(_) => {
-                    if let Some(progress) = &progress {
-                        if let Err(e) = progress.try_send(RunProgress {
-                            index: success + failed,
-                            total,
-                            task: queue.task,
-                            media_id: queue.media_id,
-                            queue_id: Some(queue.id),
-                            error: None,
-                            time: start.elapsed(),
-                        }) {
-                            eprintln!("error sending progress: {:?}", e);
-                        }
-                    }
+    while let Some(result) = workers.join_next().await {
+        result.expect("queue worker panicked")?;
+    }
+    Ok((counters.success.load(Ordering::SeqCst), counters.failed.load(Ordering::SeqCst)))
+}
 
-                    success += 1;
-                }
-                Err(e) => {
-                    eprintln!("error running task {} on {}: {:?}", e, media.path, e);
+#[allow(clippy::too_many_arguments)]
+async fn run_worker(
+    pool: DbPool,
+    task: AnyTask,
+    remaining: Arc<AtomicU32>,
+    counters: Arc<QueueCounters>,
+    total: u32,
+    remote_configs: Table,
+    queue_config: QueueConfig,
+    progress: Option<mpsc::Sender<RunProgress>>,
+    control: Arc<QueueControl>,
+) -> Result<(), TaskOperationError> {
+    let _guard = WorkerGuard { counters: counters.clone(), task: task.name() };
+    let mut db = &pool;
+    let lease = chrono::Duration::seconds(queue_config.lease_seconds);
+    // renewed well before it runs out, a slow write or two doesn't cost the lease
+    let renewal = (lease.to_std().unwrap_or_default() / LEASE_RENEWALS).max(MIN_LEASE_RENEWAL);
+    let dependencies = task.dependencies();
+    let cancel = control.token(task.name());
+
+    // claim one of the items counted at the start before leasing it, checking for a hold before each
+    while control.proceed(&cancel).await && !control.held(task.name()) && remaining.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |r| r.checked_sub(1)).is_ok() {
+        // the item stays in the queue until it's done, so a crash only delays it until the lease runs out
+        let mut queue = lease_next(&pool, task.name(), dependencies, lease).await?;
+        // what's left may be waiting on a dependency that's being worked on in this run
+        while queue.is_none() && counters.running(dependencies) && !cancel.is_cancelled() {
+            tokio::time::sleep(DEPENDENCY_POLL).await;
+            queue = lease_next(&pool, task.name(), dependencies, lease).await?;
+        }
+        let Some(mut queue) = queue else {
+            break;
+        };
+
+        // a runner died with it too many times already
+        if queue.attempts > queue_config.max_attempts {
+            let error = queue.last_error.clone().unwrap_or_else(|| "lease expired".to_string());
+            error!("giving up on task {} for media {} after {} attempts: {}", queue.task, queue.media_id, queue.attempts - 1, error);
+            queue.fail(db.acquire_clone(), &error).await?;
+            counters.failed.fetch_add(1, Ordering::SeqCst);
+            let index = counters.finished.fetch_add(1, Ordering::SeqCst);
+            send_progress(&progress, RunProgress {
+                index,
+                total,
+                task: queue.task,
+                media_id: queue.media_id,
+                queue_id: Some(queue.id),
+                error: Some(TaskError::TaskError(anyhow::anyhow!(error))),
+                time: Duration::ZERO,
+                steps: Vec::new(),
+            }).await;
+            continue;
+        }
 
-                    if let Some(progress) = &progress {
-                        if let Err(e) = progress.try_send(RunProgress {
-                            index: success + failed,
-                            total,
-                            task: queue.task,
-                            media_id: queue.media_id,
-                            queue_id: Some(queue.id),
-                            error: Some(e),
-                            time: start.elapsed(),
-                        }) {
-                            eprintln!("error sending progress: {:?}", e);
+        let mut media = Media::from_id(db.acquire_clone(), &queue.media_id).await?;
+        let start = Instant::now();
+        let result = {
+            let run = task.run_and_store_anywhere(&mut db, &mut media, &remote_configs);
+            tokio::pin!(run);
+            let mut heartbeat = tokio::time::interval_at(tokio::time::Instant::now() + renewal, renewal);
+            loop {
+                // dropping the run stops it at its next await
+                tokio::select! {
+                    result = &mut run => break Ok(result),
+                    _ = cancel.cancelled() => break Err(Stopped::Canceled),
+                    _ = heartbeat.tick() => {
+                        if !queue.renew(&pool, lease).await? {
+                            break Err(Stopped::LeaseLost);
                         }
                     }
+                }
+            }
+        };
+        let result = match result {
+            Ok(result) => result,
+            Err(Stopped::Canceled) => {
+                debug!("canceled task {} on {}, returning it to the queue", queue.task, media.path);
+                queue.release(db.acquire_clone()).await?;
+                break;
+            }
+            // whoever has it now finishes it, the item isn't ours to touch anymore
+            Err(Stopped::LeaseLost) => {
+                let error = "lost the lease on the queue item";
+                error!("{} for task {} on {}, abandoning the run", error, queue.task, media.path);
+                counters.failed.fetch_add(1, Ordering::SeqCst);
+                let index = counters.finished.fetch_add(1, Ordering::SeqCst);
+                send_progress(&progress, RunProgress {
+                    index,
+                    total,
+                    task: queue.task,
+                    media_id: queue.media_id,
+                    queue_id: Some(queue.id),
+                    error: Some(TaskError::TaskError(anyhow::anyhow!(error))),
+                    time: start.elapsed(),
+                    steps: task.take_timings(),
+                }).await;
+                continue;
+            }
+        };
 
-                    failed += 1;
+        match &result {
+            Ok(_) => {
+                queue.delete(db.acquire_clone()).await?;
+                counters.success.fetch_add(1, Ordering::SeqCst);
+            }
+            Err(e) => {
+                let message = e.to_string();
+                if queue.attempts >= queue_config.max_attempts {
+                    error!("task {} failed on {} after {} attempts, moving to queue_failed: {}", queue.task, media.path, queue.attempts, message);
+                    queue.fail(db.acquire_clone(), &message).await?;
+                } else {
+                    let backoff = queue_config.backoff(queue.attempts);
+                    error!("task {} failed on {} (attempt {}/{}), retrying in {}s: {}", queue.task, media.path, queue.attempts, queue_config.max_attempts, backoff.num_seconds(), message);
+                    queue.retry_later(db.acquire_clone(), &message, backoff).await?;
                 }
+                counters.failed.fetch_add(1, Ordering::SeqCst);
             }
         }
+
+        // taken after the item is counted, so the last index is always sent by the last item to finish
+        let index = counters.finished.fetch_add(1, Ordering::SeqCst);
+        send_progress(&progress, RunProgress {
+            index,
+            total,
+            task: queue.task,
+            media_id: queue.media_id,
+            queue_id: Some(queue.id),
+            error: result.err(),
+            time: start.elapsed(),
+            steps: task.take_timings(),
+        }).await;
+    }
+    Ok(())
+}
+
+// a dependency can fail on the media while the run goes on, what waited on it is failed along with it first
+async fn lease_next(pool: &DbPool, task: &str, dependencies: &[&str], lease: chrono::Duration) -> Result<Option<Queue>, sqlx::Error> {
+    Queue::fail_blocked(pool, task, dependencies).await?;
+    Queue::lease_next(pool, task, dependencies, lease).await
+}
+
+// waits for room rather than dropping, the receiver relies on seeing the last index
+async fn send_progress(progress: &Option<mpsc::Sender<RunProgress>>, run_progress: RunProgress) {
+    if let Some(progress) = progress {
+        if let Err(e) = progress.send(run_progress).await {
+            error!("error sending progress: {:?}", e);
+        }
     }
-    Ok((success, failed))
 }
 
 // takes a new media object and add it to all queues for compatible tasks
@@ -140,17 +374,7 @@ pub async fn add_to_compatible_queues(
 
     for task in tasks {
         if AnyTask::compatible(task, media).await {
-            Queue::delete_by_media_id(db.acquire_clone(), task, media.id)
-                .await
-                .map_err(|e| (added.clone(), e.into()))?;
-            let mut queue = Queue {
-                id: 0,
-                media_id: media.id,
-                task: task.to_string(),
-                created_at: chrono::Utc::now().naive_utc(),
-            };
-            queue
-                .create(db.acquire_clone())
+            Queue::replace(db.acquire_clone(), task, media.id, 0)
                 .await
                 .map_err(|e| (added.clone(), e.into()))?;
             added.push(AnyTask::name_from_str(task).map_err(|e| (added.clone(), e.into()))?);
@@ -160,6 +384,25 @@ pub async fn add_to_compatible_queues(
     Ok(added)
 }
 
+/// queues `task` for every compatible media whether it's outdated or not, replacing anything waiting in the queue,
+/// returns how many were queued
+pub async fn enqueue(
+    db: &mut impl AcquireClone,
+    medias: &[Media],
+    task: &str,
+    priority: i32,
+) -> Result<u32, TaskOperationError> {
+    let task = AnyTask::name_from_str(task)?;
+    let mut count = 0;
+    for media in medias {
+        if AnyTask::compatible(task, media).await {
+            Queue::replace(db.acquire_clone(), task, media.id, priority).await?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
 // takes medias and adds them to all queues for compatible & outdated tasks
 pub async fn add_outdated_queues(
     db: &mut impl AcquireClone,
@@ -182,17 +425,7 @@ pub async fn add_outdated_queues(
                     .await
                     .map_err(|e| (added.clone(), e.into()))?
             {
-                Queue::delete_by_media_id(db.acquire_clone(), task_name, media.id)
-                    .await
-                    .map_err(|e| (added.clone(), e.into()))?;
-                let mut queue = Queue {
-                    id: 0,
-                    media_id: media.id,
-                    task: task_name.to_string(),
-                    created_at: chrono::Utc::now().naive_utc(),
-                };
-                queue
-                    .create(db.acquire_clone())
+                Queue::replace(db.acquire_clone(), task_name, media.id, 0)
                     .await
                     .map_err(|e| (added.clone(), e.into()))?;
                 count += 1;
@@ -218,6 +451,8 @@ pub async fn run_custom(
         .env("KALEIDOSCOPE_PYTHON_DIR", &app_config.scripts_dir)
         .stdin(std::process::Stdio::piped())
         .stdout(std::process::Stdio::piped())
+        // a script that errors out on our side isn't left running
+        .kill_on_drop(true)
         .spawn()
         .expect("unable to spawn custom command");
 
@@ -303,6 +538,8 @@ pub async fn run_custom_tasks(
 ) -> Result<(u32, u32), TaskOperationError> {
     let mut success = 0;
     let mut failed = 0;
+    // waiting on a background task, they're picked up again by a later run
+    let mut deferred = 0;
 
     let mut total = 0;
     
@@ -332,13 +569,14 @@ pub async fn run_custom_tasks(
 
                     if let Some(progress) = &progress {
                         if let Err(e) = progress.try_send(RunProgress {
-                            index: success + failed,
+                            index: success + failed + deferred,
                             total: total as u32,
                             task: task_name.clone(),
                             media_id: media.id,
                             time: start.elapsed(),
                             error: None,
                             queue_id: None,
+                            steps: Vec::new(),
                         }) {
                             error!("error sending progress: {:?}", e);
                         }
@@ -346,19 +584,41 @@ pub async fn run_custom_tasks(
 
                     success += 1;
                 }
+                // what the script stored so far stays, it starts over once the dependencies are done
+                Err(e @ TaskError::DependencyPending(..)) => {
+                    debug!("deferring task {} on {}: {}", task_name, media.path, e);
+
+                    if let Some(progress) = &progress {
+                        if let Err(e) = progress.try_send(RunProgress {
+                            index: success + failed + deferred,
+                            total: total as u32,
+                            task: task_name.clone(),
+                            media_id: media.id,
+                            time: start.elapsed(),
+                            error: Some(e),
+                            queue_id: None,
+                            steps: Vec::new(),
+                        }) {
+                            error!("error sending progress: {:?}", e);
+                        }
+                    }
+
+                    deferred += 1;
+                }
                 Err(e) => {
                     media.remove_custom_for_version(db.acquire_clone(), config.version).await.expect("couldn't remove custom metadata");
                     error!("error running task {} on {}: {:?}", e, media.path, e);
 
                     if let Some(progress) = &progress {
                         if let Err(e) = progress.try_send(RunProgress {
-                            index: success + failed,
+                            index: success + failed + deferred,
                             total: total as u32,
                             task: task_name.clone(),
                             media_id: media.id,
                             time: start.elapsed(),
                             error: Some(e),
                             queue_id: None,
+                            steps: Vec::new(),
                         }) {
                             error!("error sending progress: {:?}", e);
                         }
@@ -1,13 +1,19 @@
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use axum::http::StatusCode;
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 use common::remote_models::job::Job;
 
 pub mod multipart_helper;
 pub mod remote_requester;
 pub mod job_util;
 
+// where jobs that produce files leave them for the client to download
+const JOB_DIR: &str = "jobs";
+
 #[derive(Deserialize)]
 pub struct StandardRemoteConfig {
     pub url: String,
@@ -29,4 +35,32 @@ pub enum RemoteTaskStatus {
 
 pub fn internal<E: Debug>(err: E) -> (StatusCode, String) {
     (StatusCode::INTERNAL_SERVER_ERROR, format!("internal error:{:?}", err))
-}
\ No newline at end of file
+}
+
+pub fn job_dir(data_dir: &str, job_uuid: &Uuid) -> PathBuf {
+    Path::new(data_dir).join(JOB_DIR).join(job_uuid.to_string())
+}
+
+/// removes job directories older than `max_age`, their client failed or never came back for them.
+/// returns how many were removed
+pub async fn remove_stale_job_dirs(data_dir: &str, max_age: Duration) -> std::io::Result<u32> {
+    let jobs_dir = Path::new(data_dir).join(JOB_DIR);
+    if !jobs_dir.exists() {
+        return Ok(0);
+    }
+    let mut removed = 0;
+    let mut entries = tokio::fs::read_dir(&jobs_dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let modified = entry.metadata().await?.modified()?;
+        if modified.elapsed().unwrap_or_default() > max_age {
+            tokio::fs::remove_dir_all(entry.path()).await?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+/// only plain file names are allowed to be requested, no separators or parent references
+pub fn is_safe_file_name(name: &str) -> bool {
+    !name.is_empty() && name != "." && name != ".." && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
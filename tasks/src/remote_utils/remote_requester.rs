@@ -6,6 +6,7 @@ use std::ops::{Add, Sub};
 use std::path::Path;
 use std::str::FromStr;
 use std::time::Duration;
+use tokio::io::AsyncWriteExt;
 use tokio::time::sleep;
 use uuid::Uuid;
 
@@ -51,6 +52,14 @@ impl RemoteRequester {
         req
     }
 
+    pub fn delete(&self, path: &str) -> RequestBuilder {
+        let mut req = Client::new().delete(format!("{}{}", self.url, path));
+        if let Some(password) = &self.password {
+            req = req.header("Authorization", password.clone());
+        }
+        req
+    }
+
     pub async fn request_multipart(&self, form: Form) -> Result<Response, RequestError> {
         let path = if self.background { "background" } else { "custom" };
         let res = self
@@ -89,6 +98,29 @@ impl RemoteRequester {
         }
     }
 
+    /// downloads a file the job left in its output directory on the runner
+    pub async fn download_job_file(&self, job_uuid: &Uuid, file_name: &str, to: &Path) -> Result<(), RequestError> {
+        let mut res = self.get(&format!("/job/{}/files/{}", job_uuid, file_name)).send().await?;
+        if res.status() != StatusCode::OK {
+            return Err(RequestError::Response(res));
+        }
+        let mut file = tokio::fs::File::create(to).await?;
+        while let Some(chunk) = res.chunk().await? {
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        Ok(())
+    }
+
+    /// tells the runner we are done with the job's output directory
+    pub async fn delete_job_files(&self, job_uuid: &Uuid) -> Result<(), RequestError> {
+        let res = self.delete(&format!("/job/{}/files", job_uuid)).send().await?;
+        if res.status() != StatusCode::OK {
+            return Err(RequestError::Response(res));
+        }
+        Ok(())
+    }
+
     pub async fn one_shot_file<T: AsRef<Path>>(&self, key: String, path: T, media_uuid: Option<Uuid>) -> Result<OneShotResponse, RequestError> {
         let mut form = Form::new();
         if let Some(uuid) = media_uuid {
@@ -1,10 +1,12 @@
-use std::process::Command;
+use tokio::process::Command;
 use common::scan_config::AppConfig;
 
-pub fn run_python(python_path: &str, script: &str, args: &[&str]) -> std::io::Result<std::process::Output> {
+// killed if the run is dropped, e.g. when its queue is canceled
+pub async fn run_python(python_path: &str, script: &str, args: &[&str]) -> std::io::Result<std::process::Output> {
     let mut cmd = Command::new(python_path);
     cmd.arg(script);
     cmd.args(args);
-    
-    Ok(cmd.output()?)
+    cmd.kill_on_drop(true);
+
+    cmd.output().await
 }
\ No newline at end of file
@@ -28,6 +28,23 @@ macro_rules! impl_task {
             }
 
 
+            pub fn dependencies(&self) -> &'static [&'static str] {
+                match self {
+                    $(
+                        AnyTask::$background_task(_) => <$background_task as Task>::DEPENDENCIES,
+                    )*
+                }
+            }
+
+            pub fn dependencies_from_str(task: &str) -> &'static [&'static str] {
+                match task {
+                    $(
+                        $background_task::NAME => <$background_task as Task>::DEPENDENCIES,
+                    )*
+                    _ => &[],
+                }
+            }
+
             pub fn name_from_str(task: &str) -> Result<&'static str, TaskError> {
                 match task {
                     $(
@@ -80,6 +97,16 @@ macro_rules! impl_task {
                 }
             }
 
+            pub async fn remove_data(&self, db: &mut impl AcquireClone, media: &mut Media) -> Result<(), TaskError> {
+                match self {
+                    $(
+                        AnyTask::$background_task(task) => {
+                            task.remove_data(db, media).await.map_err(|e| TaskError::TaskError(e.into()))
+                        }
+                    )*,
+                }
+            }
+
             pub async fn outdated(&self, db: &mut impl AcquireClone, media: &Media) -> Result<bool, TaskError> {
                 match self {
                     $(
@@ -90,6 +117,14 @@ macro_rules! impl_task {
                 }
             }
             
+            pub fn take_timings(&self) -> Vec<(String, Duration)> {
+                match self {
+                    $(
+                        AnyTask::$background_task(task) => task.take_timings(),
+                    )*
+                }
+            }
+
             pub fn background_remotable(task: &str) -> bool {
                 match task {
                     $(
@@ -180,6 +215,15 @@ macro_rules! impl_task {
                 }
             }
             
+            pub fn custom_dependencies(task: &str) -> &'static [&'static str] {
+                match task {
+                    $(
+                        <$custom_task as Task>::NAME => <$custom_task as Task>::DEPENDENCIES,
+                    )*
+                    _ => &[],
+                }
+            }
+
              pub fn custom_remotable(task: &str) -> bool {
                 match task {
                     $(
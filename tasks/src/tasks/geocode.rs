@@ -0,0 +1,109 @@
+use crate::tasks::{BackgroundTask, Task};
+use common::geocoding::{GeocodeError, Geocoder, Place};
+use common::models::media::Media;
+use common::scan_config::AppConfig;
+use common::types::AcquireClone;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const VERSION: i32 = 0;
+
+// further than this from any city in the dataset and the photo stays unplaced
+const DEFAULT_MAX_DISTANCE: f64 = 50.0;
+
+/// a GeoNames dump from https://download.geonames.org/export/dump/, nothing is ever fetched
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+pub struct GeocodeConfig {
+    /// cities1000.txt, cities5000.txt, ...
+    pub cities: Option<String>,
+    /// admin1CodesASCII.txt, regions are left empty without it
+    pub admin1: Option<String>,
+    /// countryInfo.txt, countries are ISO codes without it
+    pub countries: Option<String>,
+    /// in km
+    pub max_distance: Option<f64>,
+}
+
+/// resolves the coordinates of a media to country, region and city
+pub struct Geocode {
+    // None until a dataset is configured, media are left for later rather than failed
+    geocoder: Option<Geocoder>,
+    max_distance: f64,
+}
+
+impl Task for Geocode {
+    type Error = GeocodeTaskError;
+    const NAME: &'static str = "geocode";
+    type Config = GeocodeConfig;
+}
+
+impl BackgroundTask for Geocode {
+    // None if there's no city close enough
+    type Data = Option<Place>;
+
+    async fn new(db: &mut impl AcquireClone, config: &Self::Config, app_config: &AppConfig) -> Result<Self, Self::Error> {
+        let geocoder = match &config.cities {
+            Some(cities) => {
+                let geocoder = Geocoder::load(Path::new(cities), config.admin1.as_deref().map(Path::new), config.countries.as_deref().map(Path::new))?;
+                info!("loaded {} places for geocoding", geocoder.len());
+                Some(geocoder)
+            }
+            None => None,
+        };
+        Ok(Self {
+            geocoder,
+            max_distance: config.max_distance.unwrap_or(DEFAULT_MAX_DISTANCE),
+        })
+    }
+
+    async fn compatible(media: &Media) -> bool {
+        media.latitude.is_some() && media.longitude.is_some()
+    }
+
+    async fn outdated(&self, db: &mut impl AcquireClone, media: &Media) -> Result<bool, Self::Error> {
+        Ok(self.geocoder.is_some() && media.geocode_version < VERSION)
+    }
+
+    async fn run(&self, db: &mut impl AcquireClone, media: &Media) -> Result<Self::Data, Self::Error> {
+        let geocoder = self.geocoder.as_ref().ok_or(GeocodeTaskError::NotConfigured)?;
+        let (Some(latitude), Some(longitude)) = (media.latitude, media.longitude) else {
+            return Ok(None);
+        };
+        Ok(geocoder.lookup(latitude, longitude, self.max_distance))
+    }
+
+    async fn run_and_store(&self, db: &mut impl AcquireClone, media: &mut Media) -> Result<(), Self::Error> {
+        if self.geocoder.is_none() {
+            debug!("          no geocoding dataset configured, skipping {:?}", media.uuid);
+            return Ok(());
+        }
+
+        let place = self.run(db, media).await?;
+        media.country = place.as_ref().map(|p| p.country.clone());
+        media.region = place.as_ref().and_then(|p| p.region.clone());
+        media.city = place.map(|p| p.city);
+        media.geocode_version = VERSION;
+        media.update_by_id(db.acquire_clone()).await?;
+        Ok(())
+    }
+
+    async fn remove_data(&self, db: &mut impl AcquireClone, media: &mut Media) -> Result<(), Self::Error> {
+        media.country = None;
+        media.region = None;
+        media.city = None;
+        media.geocode_version = -1;
+        media.update_by_id(db.acquire_clone()).await?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GeocodeTaskError {
+    #[error("geocode error: {0}")]
+    GeocodeError(#[from] GeocodeError),
+    #[error("no geocoding dataset configured")]
+    NotConfigured,
+    #[error("sqlx error: {0}")]
+    SqlxError(#[from] sqlx::Error),
+}
@@ -2,13 +2,18 @@ pub mod thumbnail;
 pub mod whisper;
 pub mod ocr;
 pub mod vllm;
+pub mod transcode;
+pub mod subtitles;
+pub mod geocode;
 mod any_task;
 
 use common::models::media::Media;
 use common::types::{AcquireClone};
 use serde::{Deserialize, Serialize};
 use std::fmt::Debug;
+use std::path::{Path, PathBuf};
 use std::process::ExitStatus;
+use std::time::Duration;
 use axum::extract::Request;
 use axum::http::StatusCode;
 use axum::response::{ErrorResponse, IntoResponse, Response};
@@ -21,13 +26,27 @@ use crate::tasks::thumbnail::ThumbnailGenerator;
 use crate::tasks::whisper::Whisper;
 use crate::tasks::ocr::VisionOCR;
 use crate::tasks::vllm::VLLM;
+use crate::tasks::transcode::Transcode;
+use crate::tasks::subtitles::Subtitles;
+use crate::tasks::geocode::Geocode;
 
 const MODEL_DIR: &str = "models";
 
+/// per media directory under `root`, split by the first two characters of the uuid so no directory gets too large
+pub fn uuid_dir(root: &Path, media: &Media) -> PathBuf {
+    let uuid = media.uuid.to_string();
+    let mut chars = uuid.chars();
+    let a = chars.next().unwrap();
+    let b = chars.next().unwrap();
+    root.join(a.to_string()).join(b.to_string())
+}
+
 pub trait Task {
     type Error: Debug;
     const NAME: &'static str;
     type Config: Serialize + DeserializeOwned + Default;
+    /// background tasks whose output this one reads, a media waits in this queue until they're done with it
+    const DEPENDENCIES: &'static [&'static str] = &[];
 }
 
 pub trait RemoteTask {
@@ -82,6 +101,11 @@ pub trait BackgroundTask: Task + Sized {
     ) -> Result<(), Self::Error>;
 
     async fn remove_data(&self, db: &mut impl AcquireClone, media: &mut Media) -> Result<(), Self::Error>;
+
+    /// how long each step of the last run took, tasks that don't measure anything report nothing
+    fn take_timings(&self) -> Vec<(String, Duration)> {
+        Vec::new()
+    }
 }
 
 pub trait RemoteBackgroundTask: RemoteTask + BackgroundTask {
@@ -107,9 +131,9 @@ pub trait RemoteBackgroundTask: RemoteTask + BackgroundTask {
 }
 
 impl_task!(
-    @background [ThumbnailGenerator, Whisper, VisionOCR,],
-    3,
-    @background_remote [VisionOCR, Whisper,],
+    @background [ThumbnailGenerator, Whisper, VisionOCR, Transcode, Subtitles, Geocode,],
+    6,
+    @background_remote [VisionOCR, Whisper, Transcode,],
     @custom [VLLM,],
     @custom_remote [VLLM,]
 );
@@ -128,6 +152,10 @@ pub enum TaskError {
     Infallible,
     #[error("custom task failed with error")]
     CustomTaskError((ExitStatus, Vec<u8>)),
+    #[error("task {0} is waiting for {1:?}")]
+    DependencyPending(String, &'static [&'static str]),
+    #[error("task {0} can't run, one of {1:?} failed")]
+    DependencyFailed(String, &'static [&'static str]),
 }
 
 impl IntoResponse for TaskError {
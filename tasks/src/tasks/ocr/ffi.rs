@@ -1,5 +1,5 @@
 use std::ffi::{c_char, c_float, CStr, CString};
-use serde::{Deserialize, Serialize};
+use super::OCRResult;
 
 #[repr(C)]
 struct OCRResultFFI {
@@ -32,15 +32,6 @@ impl OCRResultFFI {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct OCRResult {
-    pub text: String,
-    pub origin_x: f32,
-    pub origin_y: f32,
-    pub size_width: f32,
-    pub size_height: f32,
-}
-
 pub fn vision_ocr(image_path: &str) -> Vec<OCRResult> {
     let image_path_c = CString::new(image_path).expect("CString::new failed");
     let mut count: c_size_t = 0;
@@ -1,5 +1,5 @@
 pub use crate::run_python::run_python;
-use crate::tasks::thumbnail::ThumbnailGenerator;
+use crate::tasks::thumbnail::{ThumbnailError, ThumbnailGenerator};
 use crate::tasks::{BackgroundTask, RemoteBackgroundTask, RemoteTask, Task};
 use axum::extract::{Request};
 use axum::response::{ErrorResponse, IntoResponse, Response};
@@ -17,25 +17,117 @@ use futures::TryFutureExt;
 use reqwest::multipart::Form;
 use tokio::fs;
 
+// Vision only exists on macOS, see build.rs
+#[cfg(target_os = "macos")]
 mod ffi;
+mod tesseract;
 
 use crate::remote_utils::multipart_helper::MultipartHelper;
 use crate::remote_utils::{internal, StandardClientConfig};
+#[cfg(target_os = "macos")]
 pub use ffi::vision_ocr;
-pub use ffi::OCRResult;
+pub use tesseract::{parse_tsv, tesseract_ocr};
 use crate::remote_utils::remote_requester::{OneShotResponse, RemoteRequester, RequestError};
 
 const VERSION: i32 = 0;
 
+const DEFAULT_TESSERACT_PATH: &str = "tesseract";
+
+/// a line of text, the box is normalized to the image with its origin at the bottom left
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OCRResult {
+    pub text: String,
+    pub origin_x: f32,
+    pub origin_y: f32,
+    pub size_width: f32,
+    pub size_height: f32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum OCRBackend {
+    /// Apple's Vision framework, macOS only
+    Vision,
+    /// the tesseract CLI
+    Tesseract,
+}
+
+impl Default for OCRBackend {
+    fn default() -> Self {
+        if cfg!(target_os = "macos") {
+            OCRBackend::Vision
+        } else {
+            OCRBackend::Tesseract
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+#[serde(from = "VisionOCRConfigForm")]
+pub struct VisionOCRConfig {
+    /// Vision on macOS, Tesseract anywhere else
+    #[serde(default)]
+    pub backend: OCRBackend,
+    /// `tesseract` on the PATH if unset
+    pub tesseract_path: Option<String>,
+    /// tesseract's `-l`, e.g. "eng+fra", its default (eng) if unset
+    pub languages: Option<String>,
+}
+
+// runners used to be configured with `vision_ocr = true`, which is the defaults
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum VisionOCRConfigForm {
+    Enabled(bool),
+    Config {
+        #[serde(default)]
+        backend: OCRBackend,
+        tesseract_path: Option<String>,
+        languages: Option<String>,
+    },
+}
+
+impl From<VisionOCRConfigForm> for VisionOCRConfig {
+    fn from(form: VisionOCRConfigForm) -> Self {
+        match form {
+            VisionOCRConfigForm::Enabled(_) => Self::default(),
+            VisionOCRConfigForm::Config { backend, tesseract_path, languages } => Self { backend, tesseract_path, languages },
+        }
+    }
+}
+
 pub struct VisionOCR {
+    config: VisionOCRConfig,
     app_config: AppConfig,
 }
 
 impl VisionOCR {
-    pub fn run_on_path(
+    fn with_config(config: VisionOCRConfig, app_config: AppConfig) -> Result<Self, VisionOCRError> {
+        if config.backend == OCRBackend::Vision && !cfg!(target_os = "macos") {
+            return Err(VisionOCRError::VisionUnavailable);
+        }
+        Ok(Self { config, app_config })
+    }
+
+    pub async fn run_on_path(
+        &self,
         image_path: &str,
     ) -> Result<<VisionOCR as BackgroundTask>::Data, <VisionOCR as Task>::Error> {
-        Ok(vision_ocr(image_path))
+        match self.config.backend {
+            #[cfg(target_os = "macos")]
+            OCRBackend::Vision => {
+                // Vision blocks until it's done, keep it off the runtime so the lease heartbeat keeps ticking
+                let image_path = image_path.to_string();
+                Ok(tokio::task::spawn_blocking(move || vision_ocr(&image_path)).await.unwrap())
+            }
+            #[cfg(not(target_os = "macos"))]
+            OCRBackend::Vision => Err(VisionOCRError::VisionUnavailable),
+            OCRBackend::Tesseract => tesseract_ocr(
+                self.config.tesseract_path.as_deref().unwrap_or(DEFAULT_TESSERACT_PATH),
+                image_path,
+                self.config.languages.as_deref(),
+            ).await,
+        }
     }
 
     pub async fn store(
@@ -67,13 +159,15 @@ impl VisionOCR {
 impl Task for VisionOCR {
     type Error = VisionOCRError;
     const NAME: &'static str = "vision_ocr";
-    type Config = ();
+    type Config = VisionOCRConfig;
+    // reads the full thumbnail instead of decoding the original
+    const DEPENDENCIES: &'static [&'static str] = &[ThumbnailGenerator::NAME];
 }
 
 impl RemoteTask for VisionOCR {
     type ClientTaskConfig = StandardClientConfig;
 
-    type RunnerTaskConfig = bool;
+    type RunnerTaskConfig = VisionOCRConfig;
 }
 
 
@@ -85,9 +179,7 @@ impl BackgroundTask for VisionOCR {
         config: &Self::Config,
         app_config: &AppConfig,
     ) -> Result<Self, Self::Error> {
-        Ok(Self {
-            app_config: app_config.clone(),
-        })
+        Self::with_config(config.clone(), app_config.clone())
     }
 
     async fn compatible(media: &Media) -> bool {
@@ -124,15 +216,15 @@ impl BackgroundTask for VisionOCR {
         db: &mut impl AcquireClone,
         media: &Media,
     ) -> Result<Self::Data, Self::Error> {
-        let full_path = ThumbnailGenerator::full_path(media, &self.app_config);
+        let full_path = ThumbnailGenerator::source_path(media, &self.app_config)?;
         if !full_path.exists() {
             return Err(VisionOCRError::NoThumbnailFound);
         }
-        let result = Self::run_on_path(
+        let result = self.run_on_path(
             full_path
                 .to_str()
                 .expect("thumbnail path contains invalid UTF-8"),
-        )?;
+        ).await?;
         Ok(result)
     }
 
@@ -167,9 +259,8 @@ impl RemoteBackgroundTask for VisionOCR {
         runner_config: &Self::RunnerTaskConfig,
         remote_server_config: &RemoteRunnerGlobalConfig,
     ) -> Result<Self, Self::Error> {
-        Ok(Self {
-            app_config: Default::default(), // create a default app_config that we won't use
-        })
+        // a default app_config that we won't use
+        Self::with_config(runner_config.clone(), Default::default())
     }
 
     async fn remote_handler(
@@ -183,11 +274,12 @@ impl RemoteBackgroundTask for VisionOCR {
 
         let (image_file, _) = multipart.file("image", ".jpg").await?;
 
-        let result = Self::run_on_path(
+        let result = self.run_on_path(
             image_file
                 .to_str()
                 .expect("image file contains invalid UTF-8"),
         )
+        .await
         .map_err(internal)?;
         
         fs::remove_file(image_file).await.map_err(internal)?;
@@ -203,7 +295,7 @@ impl RemoteBackgroundTask for VisionOCR {
         media: &Media,
         remote_config: &Self::ClientTaskConfig,
     ) -> Result<Self::Data, Self::Error> {
-        let full_path = ThumbnailGenerator::full_path(media, &self.app_config);
+        let full_path = ThumbnailGenerator::source_path(media, &self.app_config)?;
         if !full_path.exists() {
             return Err(VisionOCRError::NoThumbnailFound);
         }
@@ -233,6 +325,8 @@ pub enum VisionOCRError {
     MetadataError(#[from] MetadataError),
     #[error("no thumbnail full found for media")]
     NoThumbnailFound,
+    #[error("thumbnail error: {0}")]
+    ThumbnailError(#[from] ThumbnailError),
     #[error("sqlx error: {0}")]
     SqlxError(#[from] sqlx::Error),
     #[error("failed to parse OCR output: {0}")]
@@ -243,4 +337,10 @@ pub enum VisionOCRError {
     ReqwestError(#[from] reqwest::Error),
     #[error("request error: {0}")]
     RequestError(#[from] RequestError),
+    #[error("the vision backend is only available on macOS, use tesseract")]
+    VisionUnavailable,
+    #[error("tesseract error: {0}")]
+    TesseractError(String),
+    #[error("unexpected tesseract output: {0}")]
+    TesseractOutputError(String),
 }
@@ -0,0 +1,85 @@
+use tokio::process::Command;
+use super::{OCRResult, VisionOCRError};
+
+// columns of `tesseract <image> stdout tsv`
+const LEVEL: usize = 0;
+const LEFT: usize = 6;
+const TOP: usize = 7;
+const WIDTH: usize = 8;
+const HEIGHT: usize = 9;
+const TEXT: usize = 11;
+
+// rows are pages, blocks, paragraphs, lines then words
+const PAGE: &str = "1";
+const LINE: &str = "4";
+const WORD: &str = "5";
+
+pub async fn tesseract_ocr(tesseract_path: &str, image_path: &str, languages: Option<&str>) -> Result<Vec<OCRResult>, VisionOCRError> {
+    let mut cmd = Command::new(tesseract_path);
+    cmd.arg(image_path).arg("stdout");
+    if let Some(languages) = languages {
+        cmd.arg("-l").arg(languages);
+    }
+    cmd.arg("tsv");
+    // killed if the run is dropped, e.g. when its queue is canceled
+    cmd.kill_on_drop(true);
+
+    let output = cmd.output().await?;
+    if !output.status.success() {
+        return Err(VisionOCRError::TesseractError(String::from_utf8_lossy(&output.stderr).to_string()));
+    }
+    let stdout = String::from_utf8(output.stdout).map_err(|e| VisionOCRError::TesseractError(e.to_string()))?;
+    parse_tsv(&stdout)
+}
+
+/// one result per line like Vision, with the box normalized to the page and its origin at the bottom left
+pub fn parse_tsv(tsv: &str) -> Result<Vec<OCRResult>, VisionOCRError> {
+    let (mut page_width, mut page_height) = (0.0, 0.0);
+    let mut results: Vec<OCRResult> = Vec::new();
+
+    // skip the header
+    for row in tsv.lines().skip(1) {
+        let columns: Vec<&str> = row.splitn(TEXT + 1, '\t').collect();
+        if columns.len() <= HEIGHT {
+            continue;
+        }
+        let number = |i: usize| columns[i].parse::<f32>().map_err(|_| VisionOCRError::TesseractOutputError(row.to_string()));
+
+        match columns[LEVEL] {
+            PAGE => {
+                page_width = number(WIDTH)?;
+                page_height = number(HEIGHT)?;
+            }
+            LINE => {
+                if page_width <= 0.0 || page_height <= 0.0 {
+                    return Err(VisionOCRError::TesseractOutputError(row.to_string()));
+                }
+                let (left, top, width, height) = (number(LEFT)?, number(TOP)?, number(WIDTH)?, number(HEIGHT)?);
+                results.push(OCRResult {
+                    text: String::new(),
+                    origin_x: left / page_width,
+                    origin_y: 1.0 - (top + height) / page_height,
+                    size_width: width / page_width,
+                    size_height: height / page_height,
+                });
+            }
+            // words follow the line they're in
+            WORD => {
+                let word = columns.get(TEXT).map(|t| t.trim()).unwrap_or_default();
+                if word.is_empty() {
+                    continue;
+                }
+                if let Some(line) = results.last_mut() {
+                    if !line.text.is_empty() {
+                        line.text.push(' ');
+                    }
+                    line.text.push_str(word);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    results.retain(|r| !r.text.is_empty());
+    Ok(results)
+}
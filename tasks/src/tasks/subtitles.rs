@@ -0,0 +1,153 @@
+use crate::tasks::{BackgroundTask, Task};
+use common::media_processors::format::video::{Video, VideoError};
+use common::media_processors::format::{AnyFormat, FormatType};
+use common::models::media::Media;
+use common::scan_config::AppConfig;
+use common::subtitles::{self, Cue, SubtitleError};
+use common::types::AcquireClone;
+use log::debug;
+use std::path::{Path, PathBuf};
+use tokio::process::Command;
+
+const VERSION: i32 = 0;
+
+/// reads .srt/.vtt files next to videos, falling back to the first text subtitle stream in the file
+#[derive(Clone)]
+pub struct Subtitles {
+    app_config: AppConfig,
+}
+
+impl Subtitles {
+    // ffmpeg converts whatever text format the stream is in (SubRip, ASS, mov_text...) to WebVTT
+    async fn extract_embedded(path: &Path, stream: usize, ffmpeg_path: &str) -> Result<Vec<Cue>, SubtitlesError> {
+        let args = [
+            "-v", "error",
+            "-i", path.to_str().unwrap(),
+            "-map", &format!("0:s:{}", stream),
+            "-f", "webvtt", "-",
+        ];
+        debug!("          running ffmpeg {:?}", args);
+        let output = Command::new(ffmpeg_path).args(args).kill_on_drop(true).output().await?;
+        if !output.status.success() {
+            return Err(SubtitlesError::FfmpegError(String::from_utf8_lossy(&output.stderr).to_string()));
+        }
+        Ok(subtitles::parse(&String::from_utf8_lossy(&output.stdout))?)
+    }
+
+    // the sidecars next to the video, stored so adding or removing one makes the result outdated
+    fn sources(path: &Path) -> String {
+        let paths: Vec<String> = subtitles::sidecar_paths(path).iter().map(|p| p.to_string_lossy().to_string()).collect();
+        serde_json::to_string(&paths).unwrap()
+    }
+
+    pub async fn store(output: <Subtitles as BackgroundTask>::Data, db: &mut impl AcquireClone, media: &mut Media) -> Result<(), SubtitlesError> {
+        let extra = media.extra(db.acquire_clone()).await?;
+
+        let create = extra.is_none();
+
+        let mut media_extra = extra.unwrap_or_default();
+
+        media_extra.media_id = media.id;
+        media_extra.subtitles_version = VERSION;
+        media_extra.subtitles = output
+            .map(|cues| serde_json::to_string(&cues))
+            .transpose()
+            .map_err(|_| SubtitlesError::OutputParseError)?;
+        media_extra.subtitles_sources = Some(Self::sources(Path::new(&media.path)));
+
+        if create {
+            media_extra.create_no_bug(db.acquire_clone()).await?;
+        } else {
+            media_extra.update_by_id(db.acquire_clone()).await?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Task for Subtitles {
+    type Error = SubtitlesError;
+    const NAME: &'static str = "subtitles";
+    type Config = ();
+}
+
+impl BackgroundTask for Subtitles {
+    // None if the video has no subtitles
+    type Data = Option<Vec<Cue>>;
+
+    async fn new(db: &mut impl AcquireClone, config: &Self::Config, app_config: &AppConfig) -> Result<Self, Self::Error> {
+        Ok(Self {
+            app_config: app_config.clone(),
+        })
+    }
+
+    async fn compatible(media: &Media) -> bool {
+        let format = AnyFormat::try_new(PathBuf::from(&media.path));
+        if let Some(format) = format {
+            return format.format_type() == FormatType::Video;
+        }
+        false
+    }
+
+    async fn outdated(&self, db: &mut impl AcquireClone, media: &Media) -> Result<bool, Self::Error> {
+        let extra = media.extra(db.acquire_clone()).await?;
+        if let Some(extra) = extra {
+            if extra.subtitles_version >= VERSION && extra.subtitles_sources.as_deref() == Some(Self::sources(Path::new(&media.path)).as_str()) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn run(&self, db: &mut impl AcquireClone, media: &Media) -> Result<Self::Data, Self::Error> {
+        let path = Path::new(&media.path);
+
+        for sidecar in subtitles::sidecar_paths(path) {
+            match subtitles::read(&sidecar) {
+                Ok(cues) if !cues.is_empty() => return Ok(Some(cues)),
+                Ok(_) => {}
+                Err(e) => debug!("          skipping subtitles {:?}: {}", sidecar, e),
+            }
+        }
+
+        match Video::text_subtitle_stream(path)? {
+            Some(stream) => {
+                let cues = Self::extract_embedded(path, stream, &self.app_config.ffmpeg_path).await?;
+                Ok((!cues.is_empty()).then_some(cues))
+            }
+            None => Ok(None),
+        }
+    }
+
+    async fn run_and_store(&self, db: &mut impl AcquireClone, media: &mut Media) -> Result<(), Self::Error> {
+        let output = self.run(db, media).await?;
+        Self::store(output, db, media).await
+    }
+
+    async fn remove_data(&self, db: &mut impl AcquireClone, media: &mut Media) -> Result<(), Self::Error> {
+        let extra = media.extra(db.acquire_clone()).await?;
+        if let Some(mut extra) = extra {
+            extra.subtitles_version = -1;
+            extra.subtitles = None;
+            extra.subtitles_sources = None;
+            extra.update_by_id(db.acquire_clone()).await?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SubtitlesError {
+    #[error("video error: {0}")]
+    VideoError(#[from] VideoError),
+    #[error("subtitle error: {0}")]
+    SubtitleError(#[from] SubtitleError),
+    #[error("ffmpeg error: {0}")]
+    FfmpegError(String),
+    #[error("output parse error")]
+    OutputParseError,
+    #[error("iO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("sqlx error: {0}")]
+    SqlxError(#[from] sqlx::Error),
+}
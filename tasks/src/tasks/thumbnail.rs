@@ -1,60 +1,272 @@
 use std::path::PathBuf;
-use common::media_processors::format::{AnyFormat, MetadataError};
-use common::media_processors::RgbImage;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use common::media_processors::format::{resample, AnyFormat, MetadataError};
+use common::media_processors::{blurhash, palette, RgbImage};
 use common::models::media::Media;
+use common::models::media_color::MediaColor;
 use common::scan_config::AppConfig;
 use common::types::{AcquireClone};
+use image::codecs::avif::AvifEncoder;
+use image::codecs::jpeg::JpegEncoder;
 use log::debug;
-use crate::tasks::{BackgroundTask, Task};
+use serde::{Deserialize, Serialize};
+use crate::tasks::{uuid_dir, BackgroundTask, Task};
 
 const THUMBNAIL_DIR: &str = "thumbnails";
 
+// what image uses when saving a JPEG, so the default renditions come out the same as they always have
+const DEFAULT_QUALITY: u8 = 75;
+
+// 1 (slowest, smallest) to 10
+const AVIF_SPEED: u8 = 6;
+
+// the BlurHash and palette come from a copy this small, they'd look the same from the full image
+const PLACEHOLDER_SIZE: u32 = 64;
+
+pub const THUMB_RENDITION: &str = "thumb";
+pub const FULL_RENDITION: &str = "full";
+// a large JPEG for OCR and custom scripts, only written when `full` isn't a native resolution JPEG already
+pub const SOURCE_RENDITION: &str = "source";
+
+// big enough to OCR a scanned page, a native resolution JPEG of a 50MP photo is tens of MB
+const DEFAULT_SOURCE_SIZE: u32 = 4096;
+
 pub struct ThumbnailGenerator {
     config: ThumbnailGenerationConfig,
-    app_config: AppConfig
+    app_config: AppConfig,
+    // how long each step of the last run took, collected by the queue for progress reports
+    timings: Mutex<Vec<(String, Duration)>>,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RenditionFormat {
+    #[default]
+    Jpeg,
+    Webp,
+    Avif,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize, Default, Clone)]
+impl RenditionFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RenditionFormat::Jpeg => "jpeg",
+            RenditionFormat::Webp => "webp",
+            RenditionFormat::Avif => "avif",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RenditionFormat::Jpeg => "jpg",
+            RenditionFormat::Webp => "webp",
+            RenditionFormat::Avif => "avif",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            RenditionFormat::Jpeg => "image/jpeg",
+            RenditionFormat::Webp => "image/webp",
+            RenditionFormat::Avif => "image/avif",
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+pub struct Rendition {
+    /// used in the file name and `/media/{uuid}/thumb?size=`, only 0-9 a-z A-Z _ are allowed
+    pub name: String,
+    /// longest edge, None keeps the native resolution, never upscaled
+    pub max_size: Option<u32>,
+    #[serde(default)]
+    pub format: RenditionFormat,
+    /// 1-100
+    pub quality: Option<u8>,
+}
+
+impl Rendition {
+    fn key(&self) -> String {
+        format!("{}:{}:{}:{}", self.name, self.max_size.map(|s| s.to_string()).unwrap_or_default(), self.format.as_str(), self.quality())
+    }
+
+    fn quality(&self) -> u8 {
+        self.quality.unwrap_or(DEFAULT_QUALITY).clamp(1, 100)
+    }
+
+    fn encode(&self, image: &RgbImage) -> Result<Vec<u8>, image::ImageError> {
+        let mut out = Vec::new();
+        match self.format {
+            RenditionFormat::Jpeg => image.write_with_encoder(JpegEncoder::new_with_quality(&mut out, self.quality()))?,
+            RenditionFormat::Avif => image.write_with_encoder(AvifEncoder::new_with_speed_quality(&mut out, AVIF_SPEED, self.quality()))?,
+            // image only writes lossless WebP, which is far too big for photos
+            RenditionFormat::Webp => out.extend_from_slice(&webp::Encoder::from_rgb(image.as_raw(), image.width(), image.height()).encode(self.quality() as f32)),
+        }
+        Ok(out)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct ThumbnailGenerationConfig {
     pub thumb_size: u32,
+    /// when empty, a JPEG `thumb` of `thumb_size` and a native resolution JPEG `full`
+    #[serde(default)]
+    pub renditions: Vec<Rendition>,
+    /// longest edge of the `source` JPEG, written when `full` isn't a native resolution JPEG
+    #[serde(default = "default_source_size")]
+    pub source_size: u32,
+}
+
+fn default_source_size() -> u32 {
+    DEFAULT_SOURCE_SIZE
+}
+
+impl Default for ThumbnailGenerationConfig {
+    fn default() -> Self {
+        Self {
+            thumb_size: 0,
+            renditions: Vec::new(),
+            source_size: DEFAULT_SOURCE_SIZE,
+        }
+    }
+}
+
+impl ThumbnailGenerationConfig {
+    pub fn from_app_config(app_config: &AppConfig) -> Result<Self, ThumbnailError> {
+        let config: Self = app_config.tasks.get(ThumbnailGenerator::NAME)
+            .map(|v| v.clone().try_into())
+            .transpose()?
+            .unwrap_or_default();
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// rendition names end up in file names and URLs, so they're restricted and have to be unique
+    pub fn validate(&self) -> Result<(), ThumbnailError> {
+        let renditions = self.renditions();
+        for (i, rendition) in renditions.iter().enumerate() {
+            if rendition.name.is_empty() || rendition.name == SOURCE_RENDITION || !rendition.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(ThumbnailError::InvalidRendition(rendition.name.clone()));
+            }
+            if renditions[..i].iter().any(|r| r.name == rendition.name) {
+                return Err(ThumbnailError::DuplicateRendition(rendition.name.clone()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn renditions(&self) -> Vec<Rendition> {
+        if !self.renditions.is_empty() {
+            return self.renditions.clone();
+        }
+        vec![
+            Rendition { name: THUMB_RENDITION.to_string(), max_size: Some(self.thumb_size), format: RenditionFormat::Jpeg, quality: None },
+            Rendition { name: FULL_RENDITION.to_string(), max_size: None, format: RenditionFormat::Jpeg, quality: None },
+        ]
+    }
+
+    /// changes whenever the renditions do, media generated with a different key are outdated
+    pub fn renditions_key(&self) -> String {
+        self.written_renditions().iter().map(|r| r.key()).collect::<Vec<_>>().join(",")
+    }
+
+    pub fn rendition(&self, name: &str) -> Option<Rendition> {
+        self.renditions().into_iter().find(|r| r.name == name)
+    }
+
+    /// the smallest rendition with a longest edge of at least `size`, or the largest if none are big enough
+    pub fn rendition_for_size(&self, size: u32) -> Option<Rendition> {
+        let mut renditions = self.renditions();
+        renditions.sort_by_key(|r| r.max_size.unwrap_or(u32::MAX));
+        let largest = renditions.last().cloned();
+        renditions.into_iter().find(|r| r.max_size.unwrap_or(u32::MAX) >= size).or(largest)
+    }
+
+    /// `thumb` if there is one, otherwise the smallest
+    pub fn thumb_rendition(&self) -> Rendition {
+        self.rendition(THUMB_RENDITION)
+            .or_else(|| self.renditions().into_iter().min_by_key(|r| r.max_size.unwrap_or(u32::MAX)))
+            .unwrap()
+    }
+
+    /// `full` if there is one, otherwise the largest
+    pub fn full_rendition(&self) -> Rendition {
+        self.rendition(FULL_RENDITION)
+            .or_else(|| self.renditions().into_iter().max_by_key(|r| r.max_size.unwrap_or(u32::MAX)))
+            .unwrap()
+    }
+
+    /// what OCR and custom scripts read, `full` when it's a native resolution JPEG
+    pub fn source_rendition(&self) -> Rendition {
+        let full = self.full_rendition();
+        if full.format == RenditionFormat::Jpeg && full.max_size.is_none() {
+            return full;
+        }
+        Rendition { name: SOURCE_RENDITION.to_string(), max_size: Some(self.source_size), format: RenditionFormat::Jpeg, quality: None }
+    }
+
+    // the configured renditions plus the source if it isn't one of them
+    fn written_renditions(&self) -> Vec<Rendition> {
+        let mut renditions = self.renditions();
+        let source = self.source_rendition();
+        if !renditions.contains(&source) {
+            renditions.push(source);
+        }
+        renditions
+    }
 }
 
 impl ThumbnailGenerator {
     fn uuid_dir(media: &Media, app_config: &AppConfig) -> PathBuf {
         let thumb_dir = PathBuf::from(&app_config.data_dir).join(THUMBNAIL_DIR);
-        let uuid = media.uuid.to_string();
-        let mut chars = uuid.chars();
-        let a = chars.next().unwrap();
-        let b = chars.next().unwrap();
-        thumb_dir.join(a.to_string()).join(b.to_string())
-    }
-    
-    pub fn thumb_path(media: &Media, app_config: &AppConfig) -> PathBuf {
+        uuid_dir(&thumb_dir, media)
+    }
+
+    pub fn rendition_path(media: &Media, app_config: &AppConfig, rendition: &Rendition) -> PathBuf {
         let uuid_dir = Self::uuid_dir(media, app_config);
-        uuid_dir.join(format!("{:?}-thumb.jpg", media.uuid))
+        uuid_dir.join(format!("{:?}-{}.{}", media.uuid, rendition.name, rendition.format.extension()))
     }
-    
-    pub fn full_path(media: &Media, app_config: &AppConfig) -> PathBuf {
-        let thumb_dir = Self::uuid_dir(media, app_config);
-        thumb_dir.join(format!("{:?}-full.jpg", media.uuid))
+
+    pub fn thumb_path(media: &Media, app_config: &AppConfig) -> Result<PathBuf, ThumbnailError> {
+        let config = ThumbnailGenerationConfig::from_app_config(app_config)?;
+        Ok(Self::rendition_path(media, app_config, &config.thumb_rendition()))
+    }
+
+    pub fn full_path(media: &Media, app_config: &AppConfig) -> Result<PathBuf, ThumbnailError> {
+        let config = ThumbnailGenerationConfig::from_app_config(app_config)?;
+        Ok(Self::rendition_path(media, app_config, &config.full_rendition()))
+    }
+
+    /// always a JPEG, whatever the renditions are
+    pub fn source_path(media: &Media, app_config: &AppConfig) -> Result<PathBuf, ThumbnailError> {
+        let config = ThumbnailGenerationConfig::from_app_config(app_config)?;
+        Ok(Self::rendition_path(media, app_config, &config.source_rendition()))
+    }
+
+
+    fn record(&self, step: &str, start: Instant) {
+        self.timings.lock().unwrap().push((step.to_string(), start.elapsed()));
     }
 }
 
 impl Task for ThumbnailGenerator {
-    type Error = MetadataError;
+    type Error = ThumbnailError;
     const NAME: &'static str = "thumbnail";
     type Config = ThumbnailGenerationConfig;
 }
 
 impl BackgroundTask for ThumbnailGenerator {
-    type Data = (RgbImage, RgbImage, i32);
+    type Data = (Vec<(Rendition, RgbImage)>, i32);
 
     async fn new(db: &mut impl AcquireClone, config: &Self::Config, app_config: &AppConfig) -> Result<Self, Self::Error> {
         let thumb_dir = PathBuf::from(&app_config.data_dir).join(THUMBNAIL_DIR);
         tokio::fs::create_dir_all(&thumb_dir).await.expect("failed to create thumbnail directory");
+        config.validate()?;
         Ok(ThumbnailGenerator {
             config: config.clone(),
             app_config: app_config.clone(),
+            timings: Mutex::new(Vec::new()),
         })
     }
 
@@ -71,74 +283,147 @@ impl BackgroundTask for ThumbnailGenerator {
     async fn outdated(&self, db: &mut impl AcquireClone, media: &Media) -> Result<bool, Self::Error> {
         let path = PathBuf::from(&media.path);
         let format = AnyFormat::try_new(path).expect("media format is not, you should have checked it was compatible");
+        // thumbnails from before renditions were configurable are the defaults
+        let renditions_changed = match &media.thumbnail_renditions {
+            Some(key) => *key != self.config.renditions_key(),
+            None => !self.config.renditions.is_empty(),
+        };
         // if media doesn't have a thumbnail, or the thumbnail version is less than the media thumbnail version, or the format has changed, we need to update
-        Ok(!media.has_thumbnail || format.thumbnail_version() > media.thumbnail_version || media.format != format.format_type())
+        // media thumbnailed before placeholders existed need them filled in, and before there was a separate source
+        let source_missing = media.has_thumbnail && !Self::rendition_path(media, &self.app_config, &self.config.source_rendition()).exists();
+        Ok(!media.has_thumbnail || format.thumbnail_version() > media.thumbnail_version || media.format != format.format_type() || renditions_changed || media.blurhash.is_none() || source_missing)
     }
 
     async fn run(&self, db: &mut impl AcquireClone, media: &Media) -> Result<Self::Data, Self::Error> {
         let path = PathBuf::from(&media.path);
         let format = AnyFormat::try_new(path).expect("media format is not, you should have checked it was compatible");
 
-        let metadata = format.get_metadata(&self.app_config)?;
+        let renditions = self.config.written_renditions();
+        // one decode, big enough for the largest rendition, everything else is resampled from it
+        let largest = renditions.iter().map(|r| r.max_size).try_fold(0, |largest, size| size.map(|size| largest.max(size)));
 
-        // we want to generate a thumbnail while maintaining the aspect ratio, using thumb_size as the max size
+        let thumbnail_version = format.thumbnail_version();
 
-        let mut twidth = self.config.thumb_size;
-        let mut theight = self.config.thumb_size;
-        
-        if metadata.width > 0 && metadata.height > 0 {
-            if metadata.width > metadata.height {
-                theight = (metadata.height as f32 / metadata.width as f32 * twidth as f32) as u32;
-            } else {
-                twidth = (metadata.width as f32 / metadata.height as f32 * theight as f32) as u32;
-            }
-        }
+        // decoding and resampling are CPU bound, off the runtime so the lease heartbeat keeps ticking
+        let start = Instant::now();
+        let app_config = self.app_config.clone();
+        let base = tokio::task::spawn_blocking(move || format.decode(largest, &app_config)).await.unwrap()?;
+        self.record("decode", start);
 
-        let thumbnail = format.generate_thumbnail(twidth, theight, &self.app_config)?;
-        let full = format.generate_full(&self.app_config)?;
+        let start = Instant::now();
+        let images: Vec<(Rendition, RgbImage)> = tokio::task::spawn_blocking(move || {
+            renditions
+                .into_iter()
+                .map(|rendition| {
+                    let image = resample(&base, rendition.max_size);
+                    (rendition, image)
+                })
+                .collect()
+        }).await.unwrap();
+        self.record("resample", start);
 
-        Ok((thumbnail, full, format.thumbnail_version()))
+        Ok((images, thumbnail_version))
     }
 
     async fn run_and_store(&self, db: &mut impl AcquireClone, media: &mut Media) -> Result<(), Self::Error> {
-        let (thumbnail, full, thumbnail_version) = self.run(db, media).await?;
-        
+        let (images, thumbnail_version) = self.run(db, media).await?;
+
         let uuid_dir = Self::uuid_dir(media, &self.app_config);
         // create the directory if it doesn't exist
         tokio::fs::create_dir_all(&uuid_dir).await.expect("failed to create thumbnail directory");
 
-        let thumb_path = Self::thumb_path(media, &self.app_config);
-        let full_path = Self::full_path(media, &self.app_config);
+        let start = Instant::now();
+        let (images, blurhash, colors) = tokio::task::spawn_blocking(move || {
+            let smallest = images.iter().map(|(_, image)| image).min_by_key(|image| image.width() * image.height()).expect("there is always at least one rendition");
+            let placeholder = resample(smallest, Some(PLACEHOLDER_SIZE));
+            let blurhash = blurhash::encode(&placeholder, blurhash::X_COMPONENTS, blurhash::Y_COMPONENTS);
+            let colors = palette::dominant_colors(&placeholder, palette::PALETTE_SIZE);
+            (images, blurhash, colors)
+        }).await.unwrap();
+        self.record("placeholder", start);
 
-        // write thumbnail to disk
-        debug!("          writing thumbnail: {:?}", thumb_path);
-        thumbnail.save(thumb_path).unwrap();
+        let mut written = Vec::with_capacity(images.len());
+        for (rendition, image) in images {
+            let path = Self::rendition_path(media, &self.app_config, &rendition);
+            debug!("          writing {} rendition: {:?}", rendition.name, path);
+            let start = Instant::now();
+            let (rendition, bytes) = tokio::task::spawn_blocking(move || {
+                let bytes = rendition.encode(&image);
+                (rendition, bytes)
+            }).await.unwrap();
+            let bytes = bytes?;
+            self.record(&format!("encode {}", rendition.name), start);
+            let start = Instant::now();
+            tokio::fs::write(&path, bytes).await?;
+            self.record(&format!("write {}", rendition.name), start);
+            written.push(path);
+        }
+
+        // renditions that were removed from the config shouldn't linger, only once the new ones are there
+        self.remove_files(media, &written).await;
 
-        // write full to disk
-        debug!("          writing full: {:?}", full_path);
-        full.save(full_path).unwrap();
-        
         media.has_thumbnail = true;
         media.thumbnail_version = thumbnail_version;
+        media.thumbnail_renditions = Some(self.config.renditions_key());
+        media.blurhash = Some(blurhash);
+        media.palette = Some(colors.iter().map(|(color, _)| color.to_string()).collect::<Vec<_>>().join(","));
         media.update_by_id(db.acquire_clone()).await.unwrap();
+        MediaColor::replace(db, media.id, &colors).await?;
 
         Ok(())
     }
 
+    fn take_timings(&self) -> Vec<(String, Duration)> {
+        std::mem::take(&mut *self.timings.lock().unwrap())
+    }
+
     async fn remove_data(&self, db: &mut impl AcquireClone, media: &mut Media) -> Result<(), Self::Error> {
-        let thumb_path = Self::thumb_path(media, &self.app_config);
-        let full_path = Self::full_path(media, &self.app_config);
-        
-        // TODO: handle errors
-        std::fs::remove_file(thumb_path);
-        std::fs::remove_file(full_path);
-        
+        self.remove_files(media, &[]).await;
+
         // TODO: cleanup the UUID directory if empty
-        
+
         media.has_thumbnail = false;
         media.thumbnail_version = -1;
+        media.thumbnail_renditions = None;
+        media.blurhash = None;
+        media.palette = None;
         media.update_by_id(db.acquire_clone()).await.unwrap();
-        
+        MediaColor::delete_by_media_id(db.acquire_clone(), media.id).await?;
+
         Ok(())
     }
-}
\ No newline at end of file
+}
+
+impl ThumbnailGenerator {
+    // every file for this media but `keep`, whatever renditions it was generated with
+    async fn remove_files(&self, media: &Media, keep: &[PathBuf]) {
+        let prefix = format!("{:?}-", media.uuid);
+        let Ok(mut entries) = tokio::fs::read_dir(Self::uuid_dir(media, &self.app_config)).await else {
+            return;
+        };
+        while let Ok(Some(entry)) = entries.next_entry().await {
+            if entry.file_name().to_string_lossy().starts_with(&prefix) && !keep.contains(&entry.path()) {
+                // TODO: handle errors
+                let _ = tokio::fs::remove_file(entry.path()).await;
+            }
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ThumbnailError {
+    #[error("metadata error: {0}")]
+    MetadataError(#[from] MetadataError),
+    #[error("encoding error: {0}")]
+    EncodingError(#[from] image::ImageError),
+    #[error("iO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("invalid rendition name: {0:?}")]
+    InvalidRendition(String),
+    #[error("duplicate rendition name: {0:?}")]
+    DuplicateRendition(String),
+    #[error("invalid thumbnail config: {0}")]
+    InvalidConfig(#[from] toml::de::Error),
+    #[error("sqlx error: {0}")]
+    SqlxError(#[from] sqlx::Error),
+}
@@ -0,0 +1,458 @@
+use crate::remote_utils::multipart_helper::MultipartHelper;
+use crate::remote_utils::remote_requester::{OneShotResponse, RemoteRequester, RequestError};
+use crate::remote_utils::job_util::start_job;
+use crate::remote_utils::{internal, is_safe_file_name, job_dir, StandardClientConfig};
+use crate::tasks::{uuid_dir, BackgroundTask, RemoteBackgroundTask, RemoteTask, Task};
+use axum::extract::Request;
+use axum::response::{ErrorResponse, IntoResponse, Response};
+use common::media_processors::format::video::{Video, VideoError};
+use common::media_processors::format::{AnyFormat, FormatType};
+use common::models::media::Media;
+use common::remote_models::job::{Job, JobStatus};
+use common::runner_config::RemoteRunnerGlobalConfig;
+use common::scan_config::AppConfig;
+use common::types::AcquireClone;
+use log::debug;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::process::Command;
+use uuid::Uuid;
+
+// where the renditions are stored
+const TRANSCODE_DIR: &str = "transcode";
+
+pub const MP4_FILE: &str = "video.mp4";
+pub const HLS_MASTER_FILE: &str = "master.m3u8";
+
+const VERSION: i32 = 0;
+
+#[derive(Clone)]
+pub struct Transcode {
+    config: TranscodeConfig,
+    app_config: AppConfig,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TranscodeFormat {
+    #[default]
+    Mp4,
+    Hls,
+}
+
+impl TranscodeFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TranscodeFormat::Mp4 => "mp4",
+            TranscodeFormat::Hls => "hls",
+        }
+    }
+}
+
+impl FromStr for TranscodeFormat {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mp4" => Ok(TranscodeFormat::Mp4),
+            "hls" => Ok(TranscodeFormat::Hls),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct HlsVariant {
+    pub height: u32,
+    pub video_kbps: u32,
+    pub audio_kbps: u32,
+}
+
+#[derive(Debug, Deserialize, Serialize, Default, Clone)]
+#[serde(default)]
+pub struct TranscodeConfig {
+    /// transcoding is expensive so it must be opted into
+    pub enabled: bool,
+    pub format: TranscodeFormat,
+    /// transcode even if the original is already playable in browsers
+    pub transcode_all: bool,
+    /// mp4 only, the rendition is never upscaled
+    pub max_height: Option<u32>,
+    /// mp4 only, defaults to 23
+    pub crf: Option<u32>,
+    /// x264 preset, defaults to "veryfast"
+    pub preset: Option<String>,
+    /// hls only, defaults to 1080p and 720p
+    pub hls_variants: Vec<HlsVariant>,
+}
+
+impl TranscodeConfig {
+    fn hls_variants(&self) -> Vec<HlsVariant> {
+        if !self.hls_variants.is_empty() {
+            return self.hls_variants.clone();
+        }
+        vec![
+            HlsVariant { height: 1080, video_kbps: 5000, audio_kbps: 160 },
+            HlsVariant { height: 720, video_kbps: 2800, audio_kbps: 128 },
+        ]
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TranscodeOutput {
+    pub format: TranscodeFormat,
+    // directory the files currently live in, only meaningful to whoever produced them
+    #[serde(skip)]
+    pub dir: PathBuf,
+    pub files: Vec<String>,
+}
+
+impl Transcode {
+    pub fn output_dir(media: &Media, app_config: &AppConfig) -> PathBuf {
+        let transcode_dir = PathBuf::from(&app_config.data_dir).join(TRANSCODE_DIR);
+        uuid_dir(&transcode_dir, media).join(media.uuid.to_string())
+    }
+
+    async fn ffmpeg(ffmpeg_path: &str, args: &[String]) -> Result<(), TranscodeError> {
+        debug!("          running ffmpeg {:?}", args);
+        // a canceled run drops this future, ffmpeg goes with it
+        let output = Command::new(ffmpeg_path).args(args).kill_on_drop(true).output().await?;
+        if !output.status.success() {
+            return Err(TranscodeError::FfmpegError(String::from_utf8_lossy(&output.stderr).to_string()));
+        }
+        Ok(())
+    }
+
+    /// transcodes `input` into `out_dir` (which is created), returns the names of the files written
+    pub async fn transcode(&self, input: &Path, out_dir: &Path, ffmpeg_path: &str) -> Result<TranscodeOutput, TranscodeError> {
+        tokio::fs::create_dir_all(out_dir).await?;
+        let input = input.to_str().unwrap().to_string();
+        let preset = self.config.preset.clone().unwrap_or("veryfast".to_string());
+
+        let mut files = Vec::new();
+
+        match self.config.format {
+            TranscodeFormat::Mp4 => {
+                let mut args = vec![
+                    "-y".to_string(), "-i".to_string(), input,
+                    "-map".to_string(), "0:v:0".to_string(), "-map".to_string(), "0:a:0?".to_string(),
+                ];
+                if let Some(max_height) = self.config.max_height {
+                    args.extend(["-vf".to_string(), format!("scale=-2:'min({},ih)'", max_height)]);
+                }
+                args.extend([
+                    "-c:v".to_string(), "libx264".to_string(),
+                    "-preset".to_string(), preset,
+                    "-crf".to_string(), self.config.crf.unwrap_or(23).to_string(),
+                    "-pix_fmt".to_string(), "yuv420p".to_string(),
+                    "-c:a".to_string(), "aac".to_string(), "-b:a".to_string(), "160k".to_string(),
+                    "-movflags".to_string(), "+faststart".to_string(),
+                    out_dir.join(MP4_FILE).to_str().unwrap().to_string(),
+                ]);
+                Self::ffmpeg(ffmpeg_path, &args).await?;
+                files.push(MP4_FILE.to_string());
+            }
+            TranscodeFormat::Hls => {
+                let mut master = "#EXTM3U\n#EXT-X-VERSION:3\n".to_string();
+                // each variant is transcoded separately, we write the master playlist ourselves
+                for (i, variant) in self.config.hls_variants().iter().enumerate() {
+                    let playlist = format!("v{}.m3u8", i);
+                    let args = vec![
+                        "-y".to_string(), "-i".to_string(), input.clone(),
+                        "-map".to_string(), "0:v:0".to_string(), "-map".to_string(), "0:a:0?".to_string(),
+                        "-vf".to_string(), format!("scale=-2:'min({},ih)'", variant.height),
+                        "-c:v".to_string(), "libx264".to_string(),
+                        "-preset".to_string(), preset.clone(),
+                        "-b:v".to_string(), format!("{}k", variant.video_kbps),
+                        "-maxrate".to_string(), format!("{}k", variant.video_kbps),
+                        "-bufsize".to_string(), format!("{}k", variant.video_kbps * 2),
+                        "-pix_fmt".to_string(), "yuv420p".to_string(),
+                        "-c:a".to_string(), "aac".to_string(), "-b:a".to_string(), format!("{}k", variant.audio_kbps),
+                        "-f".to_string(), "hls".to_string(),
+                        "-hls_time".to_string(), "6".to_string(),
+                        "-hls_playlist_type".to_string(), "vod".to_string(),
+                        "-hls_segment_filename".to_string(), out_dir.join(format!("v{}_%04d.ts", i)).to_str().unwrap().to_string(),
+                        out_dir.join(&playlist).to_str().unwrap().to_string(),
+                    ];
+                    Self::ffmpeg(ffmpeg_path, &args).await?;
+                    master.push_str(&format!(
+                        "#EXT-X-STREAM-INF:BANDWIDTH={}\n{}\n",
+                        (variant.video_kbps + variant.audio_kbps) * 1000,
+                        playlist
+                    ));
+                }
+                tokio::fs::write(out_dir.join(HLS_MASTER_FILE), master).await?;
+
+                let mut entries = tokio::fs::read_dir(out_dir).await?;
+                while let Some(entry) = entries.next_entry().await? {
+                    files.push(entry.file_name().to_string_lossy().to_string());
+                }
+            }
+        }
+
+        Ok(TranscodeOutput {
+            format: self.config.format,
+            dir: out_dir.to_path_buf(),
+            files,
+        })
+    }
+
+    // the names come from the runner when it's remote, they're joined onto our own directories
+    fn check_file_names(files: &[String]) -> Result<(), TranscodeError> {
+        match files.iter().find(|file| !is_safe_file_name(file)) {
+            Some(file) => Err(TranscodeError::UnsafeFileName(file.clone())),
+            None => Ok(()),
+        }
+    }
+
+    pub async fn store(&self, output: <Transcode as BackgroundTask>::Data, db: &mut impl AcquireClone, media: &mut Media) -> Result<(), TranscodeError> {
+        if let Some(output) = &output {
+            Self::check_file_names(&output.files)?;
+        }
+        let extra = media.extra(db.acquire_clone()).await?;
+
+        let create = extra.is_none();
+
+        let mut media_extra = extra.unwrap_or_default();
+
+        let output_dir = Self::output_dir(media, &self.app_config);
+        // remove any previous rendition, the format may have changed
+        if output_dir.exists() {
+            tokio::fs::remove_dir_all(&output_dir).await?;
+        }
+
+        media_extra.media_id = media.id;
+        media_extra.transcode_version = VERSION;
+
+        if let Some(output) = output {
+            tokio::fs::create_dir_all(&output_dir).await?;
+            for file in &output.files {
+                let from = output.dir.join(file);
+                let to = output_dir.join(file);
+                // the temporary directory may be on a different filesystem
+                if tokio::fs::rename(&from, &to).await.is_err() {
+                    tokio::fs::copy(&from, &to).await?;
+                    tokio::fs::remove_file(&from).await?;
+                }
+            }
+            let _ = tokio::fs::remove_dir_all(&output.dir).await;
+            media_extra.transcode_format = Some(output.format.as_str().to_string());
+        } else {
+            // the original is playable as is
+            media_extra.transcode_format = None;
+        }
+
+        if create {
+            media_extra.create_no_bug(db.acquire_clone()).await?;
+        } else {
+            media_extra.update_by_id(db.acquire_clone()).await?;
+        }
+
+        Ok(())
+    }
+
+    fn needs_transcode(&self, media: &Media) -> Result<bool, TranscodeError> {
+        Ok(self.config.transcode_all || !Video::web_playable(Path::new(&media.path))?)
+    }
+}
+
+impl Task for Transcode {
+    type Error = TranscodeError;
+    const NAME: &'static str = "transcode";
+    type Config = TranscodeConfig;
+}
+
+impl RemoteTask for Transcode {
+    type RunnerTaskConfig = TranscodeConfig;
+    type ClientTaskConfig = StandardClientConfig;
+}
+
+impl BackgroundTask for Transcode {
+    // None if the original is already playable
+    type Data = Option<TranscodeOutput>;
+
+    async fn new(db: &mut impl AcquireClone, config: &Self::Config, app_config: &AppConfig) -> Result<Self, Self::Error> {
+        Ok(Self {
+            config: config.clone(),
+            app_config: app_config.clone(),
+        })
+    }
+
+    async fn compatible(media: &Media) -> bool {
+        let format = AnyFormat::try_new(PathBuf::from(&media.path));
+        if let Some(format) = format {
+            return format.format_type() == FormatType::Video;
+        }
+        false
+    }
+
+    async fn outdated(&self, db: &mut impl AcquireClone, media: &Media) -> Result<bool, Self::Error> {
+        if !self.config.enabled {
+            return Ok(false);
+        }
+        let extra = media.extra(db.acquire_clone()).await?;
+        if let Some(extra) = extra {
+            // a different format was configured since the last run
+            let format_changed = extra.transcode_format.as_deref().map(|f| f != self.config.format.as_str()).unwrap_or(false);
+            if extra.transcode_version >= VERSION && !format_changed {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    async fn run(&self, db: &mut impl AcquireClone, media: &Media) -> Result<Self::Data, Self::Error> {
+        if !self.needs_transcode(media)? {
+            return Ok(None);
+        }
+        let out_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        match self.transcode(Path::new(&media.path), &out_dir, &self.app_config.ffmpeg_path).await {
+            Ok(output) => Ok(Some(output)),
+            Err(e) => {
+                let _ = tokio::fs::remove_dir_all(&out_dir).await;
+                Err(e)
+            }
+        }
+    }
+
+    async fn run_and_store(&self, db: &mut impl AcquireClone, media: &mut Media) -> Result<(), Self::Error> {
+        if !self.config.enabled {
+            // nothing is stored so the media is picked up as outdated once enabled
+            return Ok(());
+        }
+        let output = self.run(db, media).await?;
+        self.store(output, db, media).await
+    }
+
+    async fn remove_data(&self, db: &mut impl AcquireClone, media: &mut Media) -> Result<(), Self::Error> {
+        let output_dir = Self::output_dir(media, &self.app_config);
+        if output_dir.exists() {
+            tokio::fs::remove_dir_all(&output_dir).await?;
+        }
+        let extra = media.extra(db.acquire_clone()).await?;
+        if let Some(mut extra) = extra {
+            extra.transcode_version = -1;
+            extra.transcode_format = None;
+            extra.update_by_id(db.acquire_clone()).await?;
+        }
+        Ok(())
+    }
+}
+
+impl RemoteBackgroundTask for Transcode {
+    async fn new_remote(db: &mut impl AcquireClone, runner_config: &Self::RunnerTaskConfig, remote_server_config: &RemoteRunnerGlobalConfig) -> Result<Self, Self::Error> {
+        Ok(Self {
+            config: runner_config.clone(),
+            app_config: Default::default(),
+        })
+    }
+
+    async fn remote_handler(
+        &self,
+        request: Request,
+        db: impl AcquireClone + Send + 'static,
+        runner_config: &Self::RunnerTaskConfig,
+        remote_server_config: &RemoteRunnerGlobalConfig,
+    ) -> Result<Response, ErrorResponse> {
+        let mut multipart = MultipartHelper::try_from_request(request).await?;
+        let media_uuid = Uuid::from_str(&multipart.text("media_uuid").await?).map_err(|_| (StatusCode::BAD_REQUEST, "bad media_uuid"))?;
+        let format: TranscodeFormat = multipart.json("format").await?;
+        let (video_file, _) = multipart.file("video", ".video").await?;
+
+        // the client decides the format, everything else comes from the runner's config
+        let mut this = self.clone();
+        this.config.format = format;
+        let remote_server_config = remote_server_config.clone();
+
+        let job = start_job(Self::NAME.to_string(), media_uuid, None, db, |job| {
+            async move {
+                let out_dir = job_dir(&remote_server_config.data_dir, &job.uuid);
+                let out = this
+                    .transcode(&video_file, &out_dir, &remote_server_config.ffmpeg_path)
+                    .await
+                    .map_err(|e| Some(format!("transcode error: {:?}", e)));
+                let _ = tokio::fs::remove_file(&video_file).await;
+                // nothing will come to download a failed job's files
+                if out.is_err() {
+                    let _ = tokio::fs::remove_dir_all(&out_dir).await;
+                }
+                Ok::<_, Option<String>>(Some(out?))
+            }
+        }).await.map_err(internal)?;
+
+        Ok((StatusCode::CREATED, job.uuid.to_string()).into_response())
+    }
+
+    async fn run_remote(&self, db: &mut impl AcquireClone, media: &Media, remote_config: &Self::ClientTaskConfig) -> Result<Self::Data, Self::Error> {
+        if !self.needs_transcode(media)? {
+            return Ok(None);
+        }
+
+        let client = RemoteRequester::new(Self::NAME.to_string(), remote_config.remote.url.clone(), remote_config.remote.password.clone(), true);
+
+        let form = reqwest::multipart::Form::new()
+            .text("media_uuid", media.uuid.to_string())
+            .text("format", serde_json::to_string(&self.config.format).unwrap())
+            .file("video", &media.path)
+            .await
+            .map_err(RequestError::from)?;
+
+        let res = client.request_multipart(form).await?;
+        if res.status() != StatusCode::CREATED {
+            return Err(TranscodeError::UnexpectedResponse(res));
+        }
+        let job_uuid = res.text().await.map_err(RequestError::from)?;
+        let job_uuid = Uuid::from_str(&job_uuid).map_err(|_| TranscodeError::InvalidResponse(format!("invalid job uuid: {:?}", job_uuid)))?;
+        let job = client.wait_for_completion(&job_uuid).await?;
+
+        if job.status != JobStatus::Success {
+            return Err(TranscodeError::JobError(job));
+        }
+
+        let success_data = job.success_data.as_deref().ok_or_else(|| TranscodeError::InvalidResponse("missing success data".to_string()))?;
+        let mut output: TranscodeOutput = serde_json::from_str(success_data).map_err(|e| TranscodeError::InvalidResponse(format!("unable to parse success data: {}", e)))?;
+        Self::check_file_names(&output.files)?;
+
+        // pull the files down from the runner
+        output.dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        tokio::fs::create_dir_all(&output.dir).await?;
+        for file in &output.files {
+            client.download_job_file(&job.uuid, file, &output.dir.join(file)).await?;
+        }
+        client.delete_job_files(&job.uuid).await?;
+
+        Ok(Some(output))
+    }
+
+    async fn run_remote_and_store(&self, db: &mut impl AcquireClone, media: &mut Media, remote_config: &Self::ClientTaskConfig) -> Result<(), Self::Error> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let output = self.run_remote(db, media, remote_config).await?;
+        self.store(output, db, media).await
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TranscodeError {
+    #[error("video error: {0}")]
+    VideoError(#[from] VideoError),
+    #[error("ffmpeg error: {0}")]
+    FfmpegError(String),
+    #[error("iO error: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error("sqlx error: {0}")]
+    SqlxError(#[from] sqlx::Error),
+    #[error("job error: {0:?}")]
+    JobError(Job),
+    #[error("unexpected response {0:?}")]
+    UnexpectedResponse(reqwest::Response),
+    #[error("request error: {0}")]
+    RequestError(#[from] RequestError),
+    #[error("invalid response from runner: {0}")]
+    InvalidResponse(String),
+    #[error("unsafe file name from runner: {0:?}")]
+    UnsafeFileName(String),
+}
@@ -3,6 +3,7 @@ use crate::remote_utils::remote_requester::{RemoteRequester, RequestError};
 use crate::remote_utils::{internal, StandardClientConfig};
 use crate::run_python::run_python;
 use crate::tasks::{CustomRemoteTask, CustomTask, RemoteTask, Task};
+use crate::tasks::thumbnail::ThumbnailGenerator;
 use axum::extract::Request;
 use axum::response::{ErrorResponse, IntoResponse, Response};
 use axum::Json;
@@ -26,13 +27,15 @@ impl Task for VLLM {
     type Error = VLLMError;
     const NAME: &'static str = "vllm";
     type Config = ();
+    // custom scripts pass it the full thumbnail
+    const DEPENDENCIES: &'static [&'static str] = &[ThumbnailGenerator::NAME];
 }
 
 impl VLLM {
-    pub fn vllm(scripts_dir: &str, python_path: &str, args: &<VLLM as CustomTask>::Args) -> Result<<VLLM as CustomTask>::Output, VLLMError> {
+    pub async fn vllm(scripts_dir: &str, python_path: &str, args: &<VLLM as CustomTask>::Args) -> Result<<VLLM as CustomTask>::Output, VLLMError> {
         let script_path = Path::new(scripts_dir).join(VLLM_SCRIPT);
         let (prompt, image_path, max_tokens, runs, temperature) = args;
-        let output = run_python(python_path, script_path.to_str().unwrap(), &[&prompt, &image_path, max_tokens.to_string().as_str(), runs.to_string().as_str(), temperature.to_string().as_str()])?;
+        let output = run_python(python_path, script_path.to_str().unwrap(), &[&prompt, &image_path, max_tokens.to_string().as_str(), runs.to_string().as_str(), temperature.to_string().as_str()]).await?;
         if !output.status.success() {
             let output = String::from_utf8(output.stderr)
                 .map_err(|_| VLLMError::OutputParseError)?;
@@ -68,7 +71,7 @@ impl CustomTask for VLLM {
     type Output = Vec<String>;
 
     async fn run_custom(db: &mut impl AcquireClone, config: &Self::Config, app_config: &AppConfig, args: Self::Args) -> Result<Self::Output, Self::Error> {
-        Self::vllm(&app_config.scripts_dir, &app_config.python_path, &args)
+        Self::vllm(&app_config.scripts_dir, &app_config.python_path, &args).await
     }
 }
 
@@ -87,6 +90,7 @@ impl CustomRemoteTask for VLLM {
         let (image_file, _) = multipart.file("image", ".jpg").await?;
         args.1 = image_file.to_str().expect("image file contains invalid UTF-8").to_string();
         let result = Self::vllm(&remote_server_config.scripts_dir, &remote_server_config.python_path, &args)
+            .await
             .map_err(internal)?;
 
         fs::remove_file(image_file).await.map_err(internal)?;
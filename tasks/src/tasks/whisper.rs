@@ -163,7 +163,8 @@ impl Whisper {
                 download_root.to_str().unwrap(),
                 target,
             ],
-        )?;
+        )
+        .await?;
 
         // delete the temporary file
         tokio::fs::remove_file(&target).await?;
@@ -0,0 +1,68 @@
+use tasks::tasks::ocr::{parse_tsv, OCRBackend, VisionOCRConfig};
+
+// `tesseract page.png stdout tsv` of a 1000x500 image, trimmed
+const TSV: &str = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+1\t1\t0\t0\t0\t0\t0\t0\t1000\t500\t-1\t
+2\t1\t1\t0\t0\t0\t100\t50\t400\t50\t-1\t
+3\t1\t1\t1\t0\t0\t100\t50\t400\t50\t-1\t
+4\t1\t1\t1\t1\t0\t100\t50\t400\t50\t-1\t
+5\t1\t1\t1\t1\t1\t100\t50\t180\t50\t96.1\tHello
+5\t1\t1\t1\t1\t2\t300\t50\t200\t50\t95.7\tworld
+2\t1\t2\t0\t0\t0\t0\t200\t1000\t50\t-1\t
+3\t1\t2\t1\t0\t0\t0\t200\t1000\t50\t-1\t
+4\t1\t2\t1\t1\t0\t0\t200\t1000\t50\t-1\t
+5\t1\t2\t1\t1\t1\t0\t200\t1000\t50\t95.0\t
+2\t1\t3\t0\t0\t0\t0\t400\t1000\t100\t-1\t
+3\t1\t3\t1\t0\t0\t0\t400\t1000\t100\t-1\t
+4\t1\t3\t1\t1\t0\t0\t400\t1000\t100\t-1\t
+5\t1\t3\t1\t1\t1\t0\t400\t300\t100\t91.2\tSecond
+5\t1\t3\t1\t1\t2\t350\t400\t10\t100\t12.0\t
+5\t1\t3\t1\t1\t3\t400\t400\t600\t100\t90.4\tline
+";
+
+fn assert_close(actual: f32, expected: f32) {
+    assert!((actual - expected).abs() < 1e-6, "{} != {}", actual, expected);
+}
+
+#[test]
+fn parse_tsv_joins_words_into_lines() {
+    let results = parse_tsv(TSV).expect("failed to parse tsv");
+
+    // the line that only has blank words is dropped
+    let text: Vec<&str> = results.iter().map(|r| r.text.as_str()).collect();
+    assert_eq!(text, vec!["Hello world", "Second line"]);
+
+    // tesseract's origin is the top left, Vision's the bottom left
+    assert_close(results[0].origin_x, 0.1);
+    assert_close(results[0].origin_y, 0.8);
+    assert_close(results[0].size_width, 0.4);
+    assert_close(results[0].size_height, 0.1);
+
+    assert_close(results[1].origin_x, 0.0);
+    assert_close(results[1].origin_y, 0.0);
+    assert_close(results[1].size_width, 1.0);
+    assert_close(results[1].size_height, 0.2);
+}
+
+#[test]
+fn parse_tsv_rejects_lines_before_the_page() {
+    let tsv = "level\tpage_num\tblock_num\tpar_num\tline_num\tword_num\tleft\ttop\twidth\theight\tconf\ttext
+4\t1\t1\t1\t1\t0\t100\t50\t400\t50\t-1\t
+";
+    assert!(parse_tsv(tsv).is_err());
+    assert!(parse_tsv("").unwrap().is_empty());
+}
+
+#[test]
+fn runner_config_accepts_the_old_bool() {
+    let config: toml::Table = toml::from_str("vision_ocr = true").unwrap();
+    let config: VisionOCRConfig = config["vision_ocr"].clone().try_into().expect("failed to parse bool config");
+    assert_eq!(config.backend, OCRBackend::default());
+    assert!(config.tesseract_path.is_none());
+
+    let config: toml::Table = toml::from_str("[vision_ocr]\nbackend = \"tesseract\"\nlanguages = \"eng+fra\"").unwrap();
+    let config: VisionOCRConfig = config["vision_ocr"].clone().try_into().expect("failed to parse table config");
+    assert_eq!(config.backend, OCRBackend::Tesseract);
+    assert_eq!(config.languages.as_deref(), Some("eng+fra"));
+    assert!(config.tesseract_path.is_none());
+}
@@ -0,0 +1,89 @@
+use tasks::tasks::thumbnail::{RenditionFormat, ThumbnailError, ThumbnailGenerationConfig, FULL_RENDITION, SOURCE_RENDITION, THUMB_RENDITION};
+
+fn config(toml: &str) -> ThumbnailGenerationConfig {
+    toml::from_str(toml).expect("failed to parse thumbnail config")
+}
+
+const RENDITIONS: &str = r#"
+thumb_size = 256
+
+[[renditions]]
+name = "small"
+max_size = 200
+format = "webp"
+
+[[renditions]]
+name = "large"
+max_size = 2000
+format = "avif"
+quality = 60
+
+[[renditions]]
+name = "medium"
+max_size = 800
+"#;
+
+#[test]
+fn default_renditions() {
+    let config = config("thumb_size = 256");
+    assert_eq!(config.renditions_key(), "thumb:256:jpeg:75,full::jpeg:75");
+
+    assert_eq!(config.rendition_for_size(100).unwrap().name, THUMB_RENDITION);
+    assert_eq!(config.rendition_for_size(256).unwrap().name, THUMB_RENDITION);
+    assert_eq!(config.rendition_for_size(257).unwrap().name, FULL_RENDITION);
+
+    assert_eq!(config.thumb_rendition().name, THUMB_RENDITION);
+    assert_eq!(config.full_rendition().name, FULL_RENDITION);
+    // full is already a native resolution JPEG
+    assert_eq!(config.source_rendition(), config.full_rendition());
+}
+
+#[test]
+fn custom_renditions_fall_back_by_size() {
+    let config = config(RENDITIONS);
+    assert!(config.validate().is_ok());
+
+    // no `thumb` or `full`, the smallest and the largest stand in
+    assert_eq!(config.thumb_rendition().name, "small");
+    assert_eq!(config.full_rendition().name, "large");
+    assert_eq!(config.full_rendition().format, RenditionFormat::Avif);
+
+    assert_eq!(config.rendition_for_size(200).unwrap().name, "small");
+    assert_eq!(config.rendition_for_size(500).unwrap().name, "medium");
+    // nothing is big enough
+    assert_eq!(config.rendition_for_size(5000).unwrap().name, "large");
+
+    // a separate, capped source for OCR
+    let source = config.source_rendition();
+    assert_eq!(source.name, SOURCE_RENDITION);
+    assert_eq!(source.format, RenditionFormat::Jpeg);
+    assert_eq!(source.max_size, Some(4096));
+}
+
+#[test]
+fn renditions_key_changes_with_renditions() {
+    let key = config(RENDITIONS).renditions_key();
+    assert_eq!(key, "small:200:webp:75,large:2000:avif:60,medium:800:jpeg:75,source:4096:jpeg:75");
+
+    let requality = config(&RENDITIONS.replace("quality = 60", "quality = 80"));
+    assert_ne!(requality.renditions_key(), key);
+
+    let resized_source = config(&format!("source_size = 2048\n{}", RENDITIONS));
+    assert_ne!(resized_source.renditions_key(), key);
+
+    // no separate source to resize
+    let defaults = config("thumb_size = 256\nsource_size = 2048");
+    assert_eq!(defaults.renditions_key(), config("thumb_size = 256").renditions_key());
+}
+
+#[test]
+fn invalid_rendition_names() {
+    let duplicate = config(&RENDITIONS.replace("\"medium\"", "\"small\""));
+    assert!(matches!(duplicate.validate(), Err(ThumbnailError::DuplicateRendition(name)) if name == "small"));
+
+    let reserved = config(&RENDITIONS.replace("\"medium\"", "\"source\""));
+    assert!(matches!(reserved.validate(), Err(ThumbnailError::InvalidRendition(name)) if name == "source"));
+
+    let separator = config(&RENDITIONS.replace("\"medium\"", "\"../medium\""));
+    assert!(matches!(separator.validate(), Err(ThumbnailError::InvalidRendition(_))));
+}